@@ -7,6 +7,7 @@ import androidx.activity.ComponentActivity
 import androidx.activity.compose.setContent
 import androidx.activity.viewModels
 import io.github.teamclouday.androidMic.AndroidMicApp
+import io.github.teamclouday.androidMic.Mode
 import io.github.teamclouday.androidMic.domain.service.WAIT_PERIOD
 import io.github.teamclouday.androidMic.ui.home.HomeScreen
 import io.github.teamclouday.androidMic.ui.home.openAppSettings
@@ -15,6 +16,10 @@ import io.github.teamclouday.androidMic.ui.utils.rememberWindowInfo
 import io.github.teamclouday.androidMic.utils.ignore
 
 private const val TAG = "MainActivity"
+
+// sent by the PC app over adb, with the "mode" and "port" extras
+const val START_STREAM_ACTION = "START_STREAM_ACTION"
+
 class MainActivity : ComponentActivity() {
 
     val vm: MainViewModel by viewModels()
@@ -35,6 +40,8 @@ class MainActivity : ComponentActivity() {
                 HomeScreen(vm, windowInfo, openAppSettings = ::openAppSettings)
             }
         }
+
+        handleStartStreamIntent(intent)
     }
 
     override fun onNewIntent(intent: Intent) {
@@ -46,6 +53,24 @@ class MainActivity : ComponentActivity() {
             // get status
             vm.askForStatus()
         }
+        handleStartStreamIntent(intent)
+    }
+
+    private fun handleStartStreamIntent(intent: Intent?) {
+        if (intent?.action != START_STREAM_ACTION) return
+
+        val mode = intent.getStringExtra("mode")?.let { mode ->
+            Mode.entries.find { it.name == mode }
+        }
+        val port = intent.getIntExtra("port", -1)
+        if (mode == null || port !in 1..65535) {
+            Log.w(TAG, "invalid start stream intent: ${intent.extras}")
+            return
+        }
+        Log.d(TAG, "onNewIntent -> START_STREAM_ACTION $mode $port")
+        // handled once
+        intent.action = null
+        vm.startStreamFromIntent(mode, port)
     }
 
     override fun onStart() {
@@ -50,6 +50,9 @@ class MainViewModel : ViewModel() {
 
     val isMuted = mutableStateOf(false)
 
+    // start requested by the PC app, waiting for the service
+    private var pendingStart: Pair<Mode, Int>? = null
+
     init {
         Log.d(TAG, "init")
     }
@@ -97,6 +100,26 @@ class MainViewModel : ViewModel() {
         val msg = CommandData(Command.BindCheck).toCommandMsg()
         msg.replyTo = messenger
         service?.send(msg)
+
+        startPendingStream()
+    }
+
+    fun startStreamFromIntent(mode: Mode, port: Int) {
+        pendingStart = Pair(mode, port)
+        startPendingStream()
+    }
+
+    private fun startPendingStream() {
+        val (mode, port) = pendingStart ?: return
+        if (!isBound) return
+        pendingStart = null
+
+        viewModelScope.launch {
+            prefs.mode.update(mode)
+            prefs.port.update(port.toString())
+            // the service also refuses to start twice
+            if (!isStreamStarted.value) onConnectButton()
+        }
     }
 
     fun onMuteSwitch() {
@@ -1,4 +1,8 @@
-use std::io::Write;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    io::Write,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use android_mic::{
     audio::{
@@ -9,7 +13,10 @@ use android_mic::{
         resampler::resample_f32_stream,
         speexdsp::process_speex_f32_stream,
     },
-    config::{AudioEffect, AudioFormat, ChannelCount, DenoiseKind, SampleRate},
+    config::{
+        AudioEffect, AudioFormat, ChannelCount, DenoiseKind, DitherMode, EqBand, ResamplerQuality,
+        SampleRate,
+    },
     streamer::{AudioPacketMessage, AudioStream},
 };
 use criterion::{Criterion, criterion_group, criterion_main};
@@ -31,6 +38,30 @@ fn make_random(size: usize) -> Vec<u8> {
 
 const SHARED_BUFFER_SIZE: usize = 48000 * 2;
 
+/// Counts allocations, to check that the processing path doesn't allocate
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
 fn bench_player(c: &mut Criterion) {
     let (mut producer, mut consumer) = RingBuffer::<u8>::new(SHARED_BUFFER_SIZE);
 
@@ -53,13 +84,16 @@ fn bench_process(c: &mut Criterion) {
 
     let audio_params = AudioProcessParams {
         target_format: AudioPacketFormat {
-            sample_rate: SampleRate::S48000,
+            sample_rate: SampleRate::from_number(48000).unwrap(),
             audio_format: AudioFormat::I16,
             channel_count: ChannelCount::Mono,
         },
+        resampler_quality: ResamplerQuality::HighQuality,
+        dither: DitherMode::Tpdf,
         denoise: Some(DenoiseKind::Speexdsp),
         amplify: None,
-        post_effect: AudioEffect::NoEffect,
+        post_effects: Vec::new(),
+        equalizer: None,
         speex_noise_suppress: -30,
         speex_vad_enabled: false,
         speex_vad_threshold: 80,
@@ -82,6 +116,7 @@ fn bench_process(c: &mut Criterion) {
                 sample_rate: 44100,
                 channel_count: 1,
                 audio_format: 2,
+                ..Default::default()
             };
 
             audio_stream
@@ -94,6 +129,71 @@ fn bench_process(c: &mut Criterion) {
     });
 }
 
+/// Not a timing bench: fails if processing a packet allocates once the pipeline is warmed up
+fn bench_process_allocations(_c: &mut Criterion) {
+    let (producer, mut consumer) = RingBuffer::<u8>::new(SHARED_BUFFER_SIZE);
+
+    let audio_params = AudioProcessParams {
+        target_format: AudioPacketFormat {
+            sample_rate: SampleRate::from_number(48000).unwrap(),
+            audio_format: AudioFormat::I16,
+            channel_count: ChannelCount::Stereo,
+        },
+        resampler_quality: ResamplerQuality::HighQuality,
+        dither: DitherMode::Tpdf,
+        denoise: Some(DenoiseKind::Rnnoise),
+        amplify: Some(1.5),
+        post_effects: vec![(1, AudioEffect::Echo.preset().unwrap())],
+        equalizer: Some(vec![EqBand::default()]),
+        speex_noise_suppress: -30,
+        speex_vad_enabled: false,
+        speex_vad_threshold: 80,
+        speex_agc_enabled: true,
+        speex_agc_target: 8000,
+        speex_dereverb_enabled: false,
+        speex_dereverb_level: 0.5,
+    };
+
+    let mut audio_stream = AudioStream::new(producer, audio_params, false);
+
+    let mut cache = ProcessCache::new();
+
+    const WARM_UP_PACKETS: usize = 64;
+    const MEASURED_PACKETS: usize = 256;
+
+    // packets are created up front, their allocation is done by the network layer
+    let mut packets = (0..WARM_UP_PACKETS + MEASURED_PACKETS).map(|_| AudioPacketMessage {
+        buffer: make_random(3840),
+        sample_rate: 44100,
+        channel_count: 2,
+        audio_format: 2,
+        ..Default::default()
+    });
+    let packets_warm_up: Vec<_> = packets.by_ref().take(WARM_UP_PACKETS).collect();
+    let packets_measured: Vec<_> = packets.collect();
+
+    for packet in packets_warm_up {
+        audio_stream
+            .process_audio_packet(packet, &mut cache)
+            .unwrap();
+        consumer.read_chunk(consumer.slots()).unwrap().commit_all();
+    }
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+
+    for packet in packets_measured {
+        audio_stream
+            .process_audio_packet(packet, &mut cache)
+            .unwrap();
+        consumer.read_chunk(consumer.slots()).unwrap().commit_all();
+    }
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+    println!("bench_process_allocations: {allocations} allocations for {MEASURED_PACKETS} packets");
+    assert_eq!(allocations, 0, "the processing path allocated");
+}
+
 fn bench_resampling(c: &mut Criterion) {
     let source = make_random(3840);
 
@@ -102,6 +202,7 @@ fn bench_resampling(c: &mut Criterion) {
         sample_rate: 44100,
         channel_count: 1,
         audio_format: 2,
+        ..Default::default()
     };
 
     let buffer = convert_packet_to_f32(&packet).unwrap();
@@ -110,7 +211,14 @@ fn bench_resampling(c: &mut Criterion) {
 
     c.bench_function("bench_resampling", |b| {
         b.iter(|| {
-            resample_f32_stream(&buffer, 44100, 48000, &mut cache).unwrap();
+            resample_f32_stream(
+                &buffer,
+                ResamplerQuality::HighQuality,
+                44100,
+                48000,
+                &mut cache,
+            )
+            .unwrap();
         });
     });
 }
@@ -123,19 +231,23 @@ fn bench_speexdsp(c: &mut Criterion) {
         sample_rate: 48000,
         channel_count: 1,
         audio_format: 2,
+        ..Default::default()
     };
 
     let buffer = convert_packet_to_f32(&packet).unwrap();
 
     let audio_params = AudioProcessParams {
         target_format: AudioPacketFormat {
-            sample_rate: SampleRate::S48000,
+            sample_rate: SampleRate::from_number(48000).unwrap(),
             audio_format: AudioFormat::I16,
             channel_count: ChannelCount::Mono,
         },
+        resampler_quality: ResamplerQuality::HighQuality,
+        dither: DitherMode::Tpdf,
         denoise: Some(DenoiseKind::Speexdsp),
         amplify: None,
-        post_effect: AudioEffect::NoEffect,
+        post_effects: Vec::new(),
+        equalizer: None,
         speex_noise_suppress: -30,
         speex_vad_enabled: false,
         speex_vad_threshold: 80,
@@ -146,10 +258,11 @@ fn bench_speexdsp(c: &mut Criterion) {
     };
 
     let mut cache = None;
+    let mut output = Vec::new();
 
     c.bench_function("bench_speexdsp", |b| {
         b.iter(|| {
-            process_speex_f32_stream(&buffer, &audio_params, &mut cache).unwrap();
+            process_speex_f32_stream(&buffer, &mut output, &audio_params, &mut cache).unwrap();
         });
     });
 }
@@ -162,15 +275,18 @@ fn bench_rnnoise(c: &mut Criterion) {
         sample_rate: 48000,
         channel_count: 1,
         audio_format: 2,
+        ..Default::default()
     };
 
     let buffer = convert_packet_to_f32(&packet).unwrap();
 
     let mut cache = None;
+    let mut output = Vec::new();
 
     c.bench_function("bench_rnnoise", |b| {
         b.iter(|| {
-            denoise_rnnoise::process_denoise_rnnoise_f32_stream(&buffer, &mut cache).unwrap();
+            denoise_rnnoise::process_denoise_rnnoise_f32_stream(&buffer, &mut output, &mut cache)
+                .unwrap();
         });
     });
 }
@@ -180,6 +296,7 @@ criterion_group!(
     benches,
     bench_player,
     bench_process,
+    bench_process_allocations,
     bench_resampling,
     bench_speexdsp,
     bench_rnnoise,
@@ -192,7 +309,7 @@ use pprof::criterion::{Output, PProfProfiler};
 criterion_group!(
     name = benches;
     config = Criterion::default().with_profiler(PProfProfiler::new(100, Output::Flamegraph(None)));
-    targets = bench_player, bench_process, bench_resampling, bench_speexdsp, bench_rnnoise,
+    targets = bench_player, bench_process, bench_process_allocations, bench_resampling, bench_speexdsp, bench_rnnoise,
 );
 
 criterion_main!(benches);
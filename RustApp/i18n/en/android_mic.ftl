@@ -8,12 +8,24 @@ connection_tcp = WIFI / LAN (TCP)
 connection_udp = WIFI / LAN (UDP)
 connection_usb = USB Serial
 connection_adb = USB Adb
+adb_device = ADB device
+adb_device_auto = The only plugged device
+adb_device_selected = ADB device: {$device}
+adb_launch_app = Open the app on the phone
+usb_device = USB device
+usb_device_auto = Any Android phone
+usb_device_selected = USB device: {$device}
 none = None
 
 tray_show_window = Show Window
 tray_connect = Connect
 tray_disconnect = Disconnect
 tray_exit = Exit
+tray_profiles = Profiles
+tray_connection_mode = Connection
+tray_output_device = Output device
+tray_effects = Effects
+tray_mute = Mute
 minimized_to_tray = Application is minimized to system tray
 
 state_disconnected = Disconnected
@@ -25,11 +37,35 @@ listening = Listening...
 disconnect = Disconnect
 waiting = Waiting...
 
+profiles = Profiles
+profile_active = Active profile
+profile_none = None
+profile_delete = Delete
+profile_include_connection = Save connection and output format
+profile_include_dsp = Save audio processing
+profile_name = Profile name
+profile_save = Save as profile
+profile_saved = Saved profile { $name }
+profile_deleted = Deleted profile { $name }
+profile_switched = Switched to profile { $name }
+profile_not_found = No profile named { $name }
+
 title_audio_format = Audio format
 sample_rate = Sample rate
+resampler_quality = Resampler
+resampler_latency = Waits for { $frames } frames before producing output
+resampler_low_latency = Low latency (polynomial)
+resampler_balanced = Balanced (sinc)
+resampler_high_quality = High quality (FFT)
+dither = Dither
+dither_description = Used when the audio format is an integer
+dither_off = Off
+dither_tpdf = Triangular (TPDF)
+dither_noise_shaped = Noise shaped
 channel_count = Channel count
 audio_format = Audio format
 use_recommended_audio_format = Use Recommended Audio Format
+output_format_fallback = The output device does not support the selected format, using { $format } instead
 
 title_connection = Connection
 
@@ -48,14 +84,69 @@ dereverberation_enabled = Enabled
 
 reset_denoise_settings = Reset Denoise Settings
 
+equalizer = Equalizer
+equalizer_enabled = Enabled
+equalizer_band = Band
+equalizer_add_band = Add band
+equalizer_remove_band = Remove
+eq_low_shelf = Low shelf
+eq_high_shelf = High shelf
+eq_peaking = Peaking
+eq_high_pass = High-pass
+eq_low_pass = Low-pass
+
+reset_effect_params = Reset to preset
+effect_add = Add effect
+effect_add_placeholder = Choose an effect
+effect_remove = Remove
+effect_bypass = Bypass
+effect_mix = Mix
+effect_delay = Delay
+effect_feedback = Feedback
+effect_cutoff = Tone
+effect_room_size = Room size
+effect_damping = Damping
+effect_pitch = Pitch
+effect_rate = Rate
+effect_min_delay = Min delay
+effect_depth = Depth
+effect_min_frequency = Min frequency
+effect_max_frequency = Max frequency
+effect_center_frequency = Center frequency
+effect_q = Q
+effect_drive = Drive
+effect_threshold = Threshold
+effect_bands = Bands
+effect_carrier_frequency = Carrier frequency
+
 title_app = App
 
 start_at_login = Start at login
+autostart_method = Start with
+autostart_desktop_entry = Desktop session
+autostart_systemd_unit = systemd user service
+autostart_reason = Start AndroidMic when you log in
 start_minimized = Start minimized
 auto_connect = Auto connect
 theme = Theme
+log_level = Log level
 amplify = Amplify
 
+title_notifications = Notifications
+notify_connected = Phone connected
+notify_disconnected = Phone disconnected
+notify_connect_failed = Connection failed
+notify_buffer_underruns = Choppy audio
+notify_output_device_lost = Output device lost
+
+notification_connected = Phone connected with {$mode}
+notification_connected_peer = Phone connected with {$mode} from {$peer}
+notification_disconnected = Phone disconnected: {$reason}
+notification_connection_lost = Phone stopped sending audio, waiting for it to reconnect
+notification_connect_failed = Can't connect: {$error}
+notification_buffer_underruns = Audio is choppy, the phone can't keep up or the network is unstable
+notification_output_device_lost = The output device is no longer available
+
 about = About
 about_open = open
 repository = Repository
@@ -73,4 +164,7 @@ hight_contrast_light_theme = HighContrastLight
 mono = Mono
 stereo = Stereo
 
-clear_logs = Clear logs
\ No newline at end of file
+clear_logs = Clear logs
+open_log_folder = Open log folder
+create_diagnostics_bundle = Create diagnostics bundle
+diagnostics_bundle_created = Diagnostics bundle created: `{$path}`
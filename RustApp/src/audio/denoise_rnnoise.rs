@@ -10,10 +10,12 @@ pub struct DenoiseCache {
     output_buffer_i16: Vec<f32>,
 }
 
+/// Denoise `data` into `output`, reusing the buffers of `output` and of the cache
 pub fn process_denoise_rnnoise_f32_stream(
     data: &[Vec<f32>],
+    output: &mut Vec<Vec<f32>>,
     cache: &mut Option<DenoiseCache>,
-) -> anyhow::Result<Vec<Vec<f32>>> {
+) -> anyhow::Result<()> {
     if match cache {
         Some(c) => data.len() != c.denoisers.len(),
         None => true,
@@ -33,41 +35,34 @@ pub fn process_denoise_rnnoise_f32_stream(
 
     let cache = cache.as_mut().unwrap();
 
-    // Convert f32 to i16 range
-    let data_i16: Vec<Vec<f32>> = data
-        .iter()
-        .map(|channel| channel.iter().map(|&x| x * i16::MAX as f32).collect())
-        .collect();
-
     // Append new data into the cache
-    for channel_idx in 0..data_i16.len() {
-        cache.sample_buffer[channel_idx].extend(&data_i16[channel_idx]);
+    for (ring_buffer, channel) in cache.sample_buffer.iter_mut().zip(data) {
+        ring_buffer.extend(channel);
     }
 
-    let mut output: Vec<Vec<f32>> =
-        vec![
-            Vec::with_capacity(cache.sample_buffer[0].number_of_chunk() * Denoiser::frame_size());
-            data.len()
-        ];
+    output.resize_with(data.len(), Vec::new);
+    for channel in output.iter_mut() {
+        channel.clear();
+    }
 
     while cache.sample_buffer[0].has_chunk_available() {
         for channel_idx in 0..data.len() {
             let ring_buffer = &mut cache.sample_buffer[channel_idx];
-            let chunk = ring_buffer.first_chunk();
+            let chunk = ring_buffer.first_chunk_mut();
+
+            // Convert f32 to i16 range, in place
+            for x in chunk.iter_mut() {
+                *x *= i16::MAX as f32;
+            }
 
             cache.denoisers[channel_idx].process(chunk, &mut cache.output_buffer_i16);
 
             // Scale back to -1.0 to 1.0 range
-            output[channel_idx].extend_from_slice(
-                &cache
-                    .output_buffer_i16
-                    .iter()
-                    .map(|&x| x / i16::MAX as f32)
-                    .collect::<Vec<f32>>(),
-            );
+            output[channel_idx]
+                .extend(cache.output_buffer_i16.iter().map(|&x| x / i16::MAX as f32));
             ring_buffer.remove_first_chunk();
         }
     }
 
-    Ok(output)
+    Ok(())
 }
@@ -0,0 +1,197 @@
+use crate::config::{AudioFormat, DitherMode};
+
+/// Integer grid of an output format, matching the scaling of `AudioBytes::from_f32`
+#[derive(Debug, Clone, Copy)]
+pub struct QuantizationGrid {
+    scale: f32,
+    min: f32,
+    max: f32,
+}
+
+impl QuantizationGrid {
+    /// `None` for formats which don't benefit from dither
+    pub fn new(audio_format: &AudioFormat) -> Option<Self> {
+        let (scale, bits) = match audio_format {
+            AudioFormat::U8 => (128.0, 8),
+            AudioFormat::I16 => (i16::MAX as f32, 16),
+            AudioFormat::I24 => (((1 << 23) - 1) as f32, 24),
+            // f32 can't even represent the lsb of i32 samples
+            AudioFormat::I32 | AudioFormat::F32 => return None,
+        };
+        let max = ((1_i64 << (bits - 1)) - 1) as f32;
+        Some(Self {
+            scale,
+            min: -max - 1.0,
+            max,
+        })
+    }
+}
+
+pub struct DitherCache {
+    /// xorshift32 state
+    rng: u32,
+    /// Last quantization error of each channel, in lsb
+    errors: Vec<f32>,
+}
+
+impl DitherCache {
+    fn new(channel_count: usize) -> Self {
+        Self {
+            // any non zero seed works
+            rng: 0x9E37_79B9,
+            errors: vec![0.0; channel_count],
+        }
+    }
+
+    /// Uniform in [0, 1]
+    fn random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng as f32 / u32::MAX as f32
+    }
+
+    /// Triangular distribution in [-1, 1] lsb
+    fn tpdf(&mut self) -> f32 {
+        self.random() - self.random()
+    }
+}
+
+/// Quantize the samples on the grid of the output format, in place.
+/// Values are clamped, so overs can't wrap around once converted to integers.
+pub fn process_dither_f32_stream(
+    data: &mut [Vec<f32>],
+    mode: DitherMode,
+    grid: &QuantizationGrid,
+    cache: &mut Option<DitherCache>,
+) {
+    if cache.as_ref().is_none_or(|c| c.errors.len() != data.len()) {
+        *cache = Some(DitherCache::new(data.len()));
+    }
+
+    let cache = cache.as_mut().unwrap();
+
+    for (channel_idx, channel) in data.iter_mut().enumerate() {
+        for sample in channel.iter_mut() {
+            let target = *sample * grid.scale;
+
+            // first order error feedback, pushes the noise toward high frequencies
+            let shaped = match mode {
+                DitherMode::NoiseShaped => target - cache.errors[channel_idx],
+                _ => target,
+            };
+            let dither = match mode {
+                DitherMode::Off => 0.0,
+                DitherMode::Tpdf | DitherMode::NoiseShaped => cache.tpdf(),
+            };
+
+            let quantized = (shaped + dither).round().clamp(grid.min, grid.max);
+            cache.errors[channel_idx] = quantized - shaped;
+            *sample = quantized / grid.scale;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    const FREQUENCY: f32 = 1000.0;
+
+    fn sine(amplitude: f32) -> Vec<f32> {
+        // a whole number of periods, so every harmonic falls on a dft bin
+        (0..SAMPLE_RATE as usize)
+            .map(|i| {
+                amplitude * (2.0 * PI * (FREQUENCY * i as f32 / SAMPLE_RATE) as f64).sin() as f32
+            })
+            .collect()
+    }
+
+    fn quantize(input: &[f32], mode: DitherMode) -> Vec<f32> {
+        let grid = QuantizationGrid::new(&AudioFormat::I16).unwrap();
+        let mut data = vec![input.to_vec()];
+        process_dither_f32_stream(&mut data, mode, &grid, &mut None);
+        data.remove(0)
+    }
+
+    /// Power of the dft bin at `frequency`, with the Goertzel algorithm
+    fn power_at(signal: &[f32], frequency: f32) -> f64 {
+        let coeff = 2.0 * (2.0 * PI * frequency as f64 / SAMPLE_RATE as f64).cos();
+        let (mut s1, mut s2) = (0.0, 0.0);
+        for &x in signal {
+            let s = x as f64 + coeff * s1 - s2;
+            s2 = s1;
+            s1 = s;
+        }
+        s1 * s1 + s2 * s2 - coeff * s1 * s2
+    }
+
+    fn total_harmonic_distortion(signal: &[f32]) -> f64 {
+        let harmonics: f64 = (2..=10)
+            .map(|k| power_at(signal, FREQUENCY * k as f32))
+            .sum();
+        (harmonics / power_at(signal, FREQUENCY)).sqrt()
+    }
+
+    fn rms_db(signal: impl Iterator<Item = f32>) -> f64 {
+        let (sum, count) = signal.fold((0.0, 0), |(sum, count), x| {
+            (sum + (x as f64).powi(2), count + 1)
+        });
+        10.0 * (sum / count as f64).log10()
+    }
+
+    fn residual(output: &[f32], input: &[f32]) -> Vec<f32> {
+        output.iter().zip(input).map(|(o, i)| o - i).collect()
+    }
+
+    #[test]
+    fn tpdf_dither_removes_low_level_distortion() {
+        // 3 lsb of amplitude, where plain rounding is very audible
+        let input = sine(3.0 / i16::MAX as f32);
+
+        let undithered = total_harmonic_distortion(&quantize(&input, DitherMode::Off));
+        let dithered = total_harmonic_distortion(&quantize(&input, DitherMode::Tpdf));
+
+        assert!(undithered > 0.03, "thd without dither: {undithered}");
+        assert!(dithered < 0.01, "thd with dither: {dithered}");
+    }
+
+    #[test]
+    fn tpdf_noise_floor() {
+        let input = sine(0.1);
+        let output = quantize(&input, DitherMode::Tpdf);
+
+        // rounding plus tpdf dither adds lsb²/4 of noise, about -96 dBFS for i16
+        let noise = rms_db(residual(&output, &input).into_iter());
+        assert!((-98.0..-94.0).contains(&noise), "noise floor: {noise} dB");
+        assert!(total_harmonic_distortion(&output) < 1e-4);
+    }
+
+    #[test]
+    fn noise_shaping_lowers_low_frequency_noise() {
+        let input = sine(0.1);
+        let low_band_noise = |mode| {
+            let residual = residual(&quantize(&input, mode), &input);
+            // moving average, a crude low pass
+            rms_db(residual.windows(8).map(|w| w.iter().sum::<f32>() / 8.0))
+        };
+
+        let flat = low_band_noise(DitherMode::Tpdf);
+        let shaped = low_band_noise(DitherMode::NoiseShaped);
+
+        assert!(shaped < flat - 3.0, "flat: {flat} dB, shaped: {shaped} dB");
+    }
+
+    #[test]
+    fn overs_are_clamped() {
+        let output = quantize(&sine(1.5), DitherMode::Tpdf);
+
+        for sample in output {
+            let value = sample * i16::MAX as f32;
+            assert!((i16::MIN as f32..=i16::MAX as f32).contains(&value.round()));
+        }
+    }
+}
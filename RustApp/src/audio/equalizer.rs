@@ -0,0 +1,70 @@
+use crate::{
+    audio::postprocessing::BiquadFilter,
+    config::{EqBand, EqBandKind},
+};
+
+pub struct EqualizerCache {
+    sample_rate: u32,
+    bands: Vec<EqBand>,
+    /// One filter per band, per channel
+    filters: Vec<Vec<BiquadFilter>>,
+}
+
+fn create_filter(band: &EqBand, sample_rate: u32) -> BiquadFilter {
+    // keep the corner frequency below nyquist, otherwise the filter becomes unstable
+    let frequency = band.frequency.clamp(10.0, sample_rate as f32 * 0.49);
+    let q = band.q.max(0.1);
+
+    match band.kind {
+        EqBandKind::LowShelf => BiquadFilter::new_low_shelf(sample_rate, frequency, q, band.gain),
+        EqBandKind::HighShelf => BiquadFilter::new_high_shelf(sample_rate, frequency, q, band.gain),
+        EqBandKind::Peaking => BiquadFilter::new_peaking(sample_rate, frequency, q, band.gain),
+        EqBandKind::HighPass => BiquadFilter::new_hpf(sample_rate, frequency, q),
+        EqBandKind::LowPass => BiquadFilter::new_lpf(sample_rate, frequency, q),
+    }
+}
+
+/// Apply the parametric equalizer in place
+pub fn process_equalizer_f32_stream(
+    data: &mut [Vec<f32>],
+    sample_rate: u32,
+    bands: &[EqBand],
+    cache: &mut Option<EqualizerCache>,
+) {
+    if bands.is_empty() {
+        return;
+    }
+
+    if match cache {
+        Some(c) => {
+            c.sample_rate != sample_rate || c.bands != bands || c.filters.len() != data.len()
+        }
+        None => true,
+    } {
+        *cache = Some(EqualizerCache {
+            sample_rate,
+            bands: bands.to_vec(),
+            filters: data
+                .iter()
+                .map(|_| {
+                    bands
+                        .iter()
+                        .map(|band| create_filter(band, sample_rate))
+                        .collect()
+                })
+                .collect(),
+        });
+    }
+
+    let cache = cache.as_mut().unwrap();
+
+    for (channel, filters) in data.iter_mut().zip(cache.filters.iter_mut()) {
+        for sample in channel.iter_mut() {
+            let mut value = *sample;
+            for filter in filters.iter_mut() {
+                value = filter.process(value);
+            }
+            *sample = value.clamp(-1.0, 1.0);
+        }
+    }
+}
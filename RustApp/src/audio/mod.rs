@@ -1,15 +1,23 @@
 #![allow(clippy::needless_range_loop)]
+use std::{fmt::Display, sync::atomic::Ordering};
+
 use byteorder::{ByteOrder, NativeEndian, WriteBytesExt};
-use cpal::traits::StreamTrait;
-use rtrb::Consumer;
+use cpal::{I24, traits::StreamTrait};
+use rtrb::{Producer, RingBuffer};
 
 use crate::{
-    config::{AudioEffect, AudioFormat, ChannelCount, Config, DenoiseKind, SampleRate},
+    config::{
+        AudioFormat, ChannelCount, Config, DenoiseKind, DitherMode, EffectId, EffectParams, EqBand,
+        ResamplerQuality, SampleRate,
+    },
     ui::app::{AppState, Stream},
 };
 
 mod chunked_ring_buffer;
 pub mod denoise_rnnoise;
+pub mod dither;
+pub mod equalizer;
+pub mod pipeline;
 pub mod player;
 mod postprocessing;
 pub mod process;
@@ -17,12 +25,16 @@ pub mod resampler;
 pub mod speexdsp;
 
 /// Audio processing parameters
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct AudioProcessParams {
     pub target_format: AudioPacketFormat,
+    pub resampler_quality: ResamplerQuality,
+    pub dither: DitherMode,
     pub denoise: Option<DenoiseKind>,
     pub amplify: Option<f32>,
-    pub post_effect: AudioEffect,
+    /// Effect chain without the bypassed entries, with the id of each entry
+    pub post_effects: Vec<(EffectId, EffectParams)>,
+    pub equalizer: Option<Vec<EqBand>>,
     pub speex_noise_suppress: i32,
     pub speex_vad_enabled: bool,
     pub speex_vad_threshold: u32,
@@ -34,11 +46,21 @@ pub struct AudioProcessParams {
 
 impl AudioProcessParams {
     pub fn new(target_format: AudioPacketFormat, config: Config) -> Self {
+        let post_effects = config
+            .post_effects
+            .iter()
+            .filter(|entry| !entry.bypass)
+            .map(|entry| (entry.id, entry.params))
+            .collect();
+
         Self {
             target_format,
+            resampler_quality: config.resampler_quality,
+            dither: config.dither,
             denoise: config.denoise.then_some(config.denoise_kind),
             amplify: config.amplify.then_some(config.amplify_value),
-            post_effect: config.post_effect,
+            post_effects,
+            equalizer: config.equalizer.then_some(config.equalizer_bands),
             speex_noise_suppress: config.speex_noise_suppress,
             speex_vad_enabled: config.speex_vad_enabled,
             speex_vad_threshold: config.speex_vad_threshold,
@@ -55,6 +77,15 @@ impl AudioProcessParams {
             .is_some_and(|k| *k == DenoiseKind::Speexdsp)
     }
 
+    /// Equal but for the effect parameters, which the pipeline can update in place
+    pub fn differs_only_by_effects(&self, other: &Self) -> bool {
+        let without_effects = |params: &Self| Self {
+            post_effects: Vec::new(),
+            ..params.clone()
+        };
+        without_effects(self) == without_effects(other)
+    }
+
     pub fn is_speex_used(&self) -> bool {
         self.is_speex_denoise_enabled()
             || self.speex_vad_enabled
@@ -63,12 +94,19 @@ impl AudioProcessParams {
     }
 }
 
+const I24_MAX: i32 = (1 << 23) - 1;
+const I24_MIN: i32 = -(1 << 23);
+
+/// Duration of audio that the buffer shared with the output stream can hold
+const SHARED_BUF_SIZE_S: f32 = 1.; // 0.15s
+
 impl AppState {
+    /// Create the output stream, with the closest format supported by the device.
+    /// Returns the format actually used, and the producer side of the shared buffer.
     pub fn create_audio_stream(
         &mut self,
-        consumer: Consumer<u8>,
         auto_play: bool,
-    ) -> anyhow::Result<AudioPacketFormat> {
+    ) -> anyhow::Result<(AudioPacketFormat, Producer<u8>)> {
         self.audio_stream = None;
 
         let device = self
@@ -83,8 +121,24 @@ impl AppState {
             channel_count: config.channel_count,
         };
 
-        let (stream, final_audio_config) =
-            player::create_audio_stream(device, wanted_audio_config, consumer)?;
+        let output_format = player::select_output_format(device, &wanted_audio_config)?;
+        let final_audio_config = output_format.format.clone();
+        if final_audio_config != wanted_audio_config {
+            warn!(
+                "output format {wanted_audio_config} is not supported, using {final_audio_config}"
+            );
+        }
+        if output_format.device_channels != final_audio_config.channel_count.to_number() {
+            info!(
+                "up-mixing {} to the {} channels of the device",
+                final_audio_config.channel_count, output_format.device_channels
+            );
+        }
+
+        let (producer, consumer) = RingBuffer::<u8>::new(final_audio_config.shared_buf_size());
+        self.output.device_lost.store(false, Ordering::Relaxed);
+        let stream =
+            player::create_audio_stream(device, &output_format, consumer, self.output.clone())?;
 
         if auto_play {
             if let Err(e) = stream.play() {
@@ -99,17 +153,29 @@ impl AppState {
             config: final_audio_config.clone(),
         });
 
-        Ok(final_audio_config)
+        Ok((final_audio_config, producer))
     }
 }
 
 pub trait AudioBytes {
-    fn from_bytes(bytes: &[u8]) -> Self
+    /// Read a sample with an explicit byte order, as found in audio packets
+    fn read_bytes<E: ByteOrder>(bytes: &[u8]) -> Self
     where
         Self: Sized;
 
+    /// Read a sample in native byte order, as written to the output buffer
+    fn from_bytes(bytes: &[u8]) -> Self
+    where
+        Self: Sized,
+    {
+        Self::read_bytes::<NativeEndian>(bytes)
+    }
+
     fn to_bytes(&self) -> Vec<u8>;
 
+    /// Write the sample at the start of `bytes`, without allocating
+    fn write_bytes(&self, bytes: &mut [u8]);
+
     fn to_f32(&self) -> f32;
 
     fn from_f32(value: f32) -> Self
@@ -124,11 +190,11 @@ pub trait AudioBytes {
 }
 
 impl AudioBytes for i16 {
-    fn from_bytes(bytes: &[u8]) -> Self
+    fn read_bytes<E: ByteOrder>(bytes: &[u8]) -> Self
     where
         Self: Sized,
     {
-        NativeEndian::read_i16(bytes)
+        E::read_i16(bytes)
     }
 
     fn to_bytes(&self) -> Vec<u8> {
@@ -137,12 +203,17 @@ impl AudioBytes for i16 {
         bytes
     }
 
+    fn write_bytes(&self, bytes: &mut [u8]) {
+        NativeEndian::write_i16(bytes, *self);
+    }
+
     fn to_f32(&self) -> f32 {
         *self as f32 / i16::MAX as f32
     }
 
     fn from_f32(value: f32) -> Self {
-        (value * i16::MAX as f32) as i16
+        // `as` saturates on overs
+        (value * i16::MAX as f32).round() as i16
     }
 
     fn to_f64(&self) -> f64 {
@@ -150,16 +221,16 @@ impl AudioBytes for i16 {
     }
 
     fn from_f64(value: f64) -> Self {
-        (value * i16::MAX as f64) as i16
+        (value * i16::MAX as f64).round() as i16
     }
 }
 
 impl AudioBytes for i32 {
-    fn from_bytes(bytes: &[u8]) -> Self
+    fn read_bytes<E: ByteOrder>(bytes: &[u8]) -> Self
     where
         Self: Sized,
     {
-        NativeEndian::read_i32(bytes)
+        E::read_i32(bytes)
     }
 
     fn to_bytes(&self) -> Vec<u8> {
@@ -168,12 +239,16 @@ impl AudioBytes for i32 {
         bytes
     }
 
+    fn write_bytes(&self, bytes: &mut [u8]) {
+        NativeEndian::write_i32(bytes, *self);
+    }
+
     fn to_f32(&self) -> f32 {
         *self as f32 / i32::MAX as f32
     }
 
     fn from_f32(value: f32) -> Self {
-        (value * i32::MAX as f32) as i32
+        (value * i32::MAX as f32).round() as i32
     }
 
     fn to_f64(&self) -> f64 {
@@ -181,32 +256,77 @@ impl AudioBytes for i32 {
     }
 
     fn from_f64(value: f64) -> Self {
-        (value * i32::MAX as f64) as i32
+        (value * i32::MAX as f64).round() as i32
     }
 }
 
-impl AudioBytes for f32 {
-    fn from_bytes(bytes: &[u8]) -> Self
+/// 24-bit samples are packed in 3 bytes in audio packets, and unpacked in 4 bytes
+/// (like cpal) in the output buffer. The length of `bytes` selects the layout.
+impl AudioBytes for I24 {
+    fn read_bytes<E: ByteOrder>(bytes: &[u8]) -> Self
     where
         Self: Sized,
     {
+        let value = match bytes.len() {
+            3 => E::read_i24(bytes),
+            4 => E::read_i32(bytes),
+            len => {
+                debug_assert!(false, "a 24-bit sample can't be read from {len} bytes");
+                0
+            }
+        };
+        I24::new_unchecked(value.clamp(I24_MIN, I24_MAX))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.write_i32::<NativeEndian>(self.inner()).unwrap();
+        bytes
+    }
+
+    fn write_bytes(&self, bytes: &mut [u8]) {
         if bytes.len() == 3 {
-            // 3 bytes for i24 format
-            let val = NativeEndian::read_i24(bytes);
-            val as f32 / (1 << 23) as f32
-        } else if bytes.len() == 4 {
-            NativeEndian::read_f32(bytes)
+            NativeEndian::write_i24(bytes, self.inner());
         } else {
-            panic!()
+            NativeEndian::write_i32(bytes, self.inner());
         }
     }
 
+    fn to_f32(&self) -> f32 {
+        self.inner() as f32 / I24_MAX as f32
+    }
+
+    fn from_f32(value: f32) -> Self {
+        I24::new_unchecked(((value * I24_MAX as f32).round() as i32).clamp(I24_MIN, I24_MAX))
+    }
+
+    fn to_f64(&self) -> f64 {
+        self.inner() as f64 / I24_MAX as f64
+    }
+
+    fn from_f64(value: f64) -> Self {
+        I24::new_unchecked(((value * I24_MAX as f64).round() as i32).clamp(I24_MIN, I24_MAX))
+    }
+}
+
+impl AudioBytes for f32 {
+    fn read_bytes<E: ByteOrder>(bytes: &[u8]) -> Self
+    where
+        Self: Sized,
+    {
+        E::read_f32(bytes)
+    }
+
     fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = vec![];
         bytes.write_f32::<NativeEndian>(*self).unwrap();
         bytes
     }
 
+    fn write_bytes(&self, bytes: &mut [u8]) {
+        NativeEndian::write_f32(bytes, *self);
+    }
+
     fn to_f32(&self) -> f32 {
         *self
     }
@@ -225,23 +345,34 @@ impl AudioBytes for f32 {
 }
 
 impl AudioBytes for u8 {
-    fn from_bytes(bytes: &[u8]) -> Self
+    fn read_bytes<E: ByteOrder>(bytes: &[u8]) -> Self
     where
         Self: Sized,
     {
-        bytes[0]
+        match bytes.first() {
+            Some(&value) => value,
+            None => {
+                debug_assert!(false, "an 8-bit sample can't be read from 0 bytes");
+                // silence, unsigned samples are centered on 128
+                128
+            }
+        }
     }
 
     fn to_bytes(&self) -> Vec<u8> {
         vec![*self]
     }
 
+    fn write_bytes(&self, bytes: &mut [u8]) {
+        bytes[0] = *self;
+    }
+
     fn to_f32(&self) -> f32 {
         (*self as f32 - 128.0) / 128.0
     }
 
     fn from_f32(value: f32) -> Self {
-        (value * 128.0 + 128.0) as u8
+        (value * 128.0 + 128.0).round() as u8
     }
 
     fn to_f64(&self) -> f64 {
@@ -249,16 +380,16 @@ impl AudioBytes for u8 {
     }
 
     fn from_f64(value: f64) -> Self {
-        (value * 128.0 + 128.0) as u8
+        (value * 128.0 + 128.0).round() as u8
     }
 }
 
 impl AudioBytes for u32 {
-    fn from_bytes(bytes: &[u8]) -> Self
+    fn read_bytes<E: ByteOrder>(bytes: &[u8]) -> Self
     where
         Self: Sized,
     {
-        NativeEndian::read_u32(bytes)
+        E::read_u32(bytes)
     }
 
     fn to_bytes(&self) -> Vec<u8> {
@@ -267,6 +398,10 @@ impl AudioBytes for u32 {
         bytes
     }
 
+    fn write_bytes(&self, bytes: &mut [u8]) {
+        NativeEndian::write_u32(bytes, *self);
+    }
+
     fn to_f32(&self) -> f32 {
         *self as f32 / u32::MAX as f32
     }
@@ -284,9 +419,32 @@ impl AudioBytes for u32 {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AudioPacketFormat {
     pub sample_rate: SampleRate,
     pub audio_format: AudioFormat,
     pub channel_count: ChannelCount,
 }
+
+impl AudioPacketFormat {
+    pub fn shared_buf_size(&self) -> usize {
+        let size = ((self.sample_rate.to_number() as f32
+            * self.channel_count.to_number() as f32
+            * self.audio_format.output_sample_size() as f32)
+            * SHARED_BUF_SIZE_S)
+            .ceil() as usize;
+        info!("shared buf size: {size}");
+
+        size
+    }
+}
+
+impl Display for AudioPacketFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} Hz, {}, {}",
+            self.sample_rate, self.audio_format, self.channel_count
+        )
+    }
+}
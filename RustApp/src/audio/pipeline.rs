@@ -0,0 +1,567 @@
+use std::time::Duration;
+
+use crate::{
+    audio::{
+        AudioProcessParams,
+        denoise_rnnoise::{
+            DENOISE_RNNOISE_SAMPLE_RATE, DenoiseCache, process_denoise_rnnoise_f32_stream,
+        },
+        dither::{DitherCache, QuantizationGrid, process_dither_f32_stream},
+        equalizer::{EqualizerCache, process_equalizer_f32_stream},
+        postprocessing::{AudioPostProcessor, post_apply_effect},
+        resampler::{ResamplerCache, resample_f32_stream_in_place},
+        speexdsp::{
+            SPEEXDSP_FRAME_SIZE, SPEEXDSP_SAMPLE_RATE, SpeexdspCache, process_speex_f32_stream,
+        },
+    },
+    config::{DenoiseKind, DitherMode, EffectId, EffectParams, EqBand, ResamplerQuality},
+};
+
+/// A processing stage of the audio pipeline
+pub trait AudioNode: Send {
+    /// Short name, used in logs
+    fn name(&self) -> &'static str;
+
+    /// Sample rate required on the input of this node, `None` if any rate works
+    fn sample_rate(&self) -> Option<u32> {
+        None
+    }
+
+    /// Sample rate produced by this node, given the rate of its input
+    fn output_sample_rate(&self, input_sample_rate: u32) -> u32 {
+        input_sample_rate
+    }
+
+    /// Latency added by this node, in frames of its input
+    fn latency(&self) -> usize {
+        0
+    }
+
+    /// Process one block of planar samples. The node can either modify
+    /// the buffer in place or swap it with one of its own buffers, e.g. when
+    /// the number of frames changes. Nodes must not allocate once warmed up.
+    fn process(&mut self, buffer: &mut Vec<Vec<f32>>, sample_rate: u32) -> anyhow::Result<()>;
+
+    /// Drop any internal state, as if no sample had been processed
+    fn reset(&mut self);
+
+    /// Effects of the chain can be updated without losing their state
+    fn as_effect(&mut self) -> Option<&mut EffectNode> {
+        None
+    }
+}
+
+struct Stage {
+    input_sample_rate: u32,
+    node: Box<dyn AudioNode>,
+}
+
+/// Ordered chain of [`AudioNode`]
+pub struct AudioPipeline {
+    input_sample_rate: u32,
+    output_sample_rate: u32,
+    stages: Vec<Stage>,
+}
+
+impl AudioPipeline {
+    pub fn builder(
+        input_sample_rate: u32,
+        resampler_quality: ResamplerQuality,
+    ) -> AudioPipelineBuilder {
+        AudioPipelineBuilder {
+            current_sample_rate: input_sample_rate,
+            resampler_quality,
+            pipeline: AudioPipeline {
+                input_sample_rate,
+                output_sample_rate: input_sample_rate,
+                stages: Vec::new(),
+            },
+        }
+    }
+
+    /// Build the pipeline described by the processing parameters:
+    /// denoise, speexdsp, resampling to the output rate, equalizer, effects, amplification
+    /// and dither for integer output formats.
+    pub fn from_params(params: &AudioProcessParams, input_sample_rate: u32) -> Self {
+        let mut builder = Self::builder(input_sample_rate, params.resampler_quality);
+
+        if let Some(DenoiseKind::Rnnoise) = params.denoise {
+            builder = builder.node(RnnoiseNode::default());
+        }
+
+        if params.is_speex_used() {
+            builder = builder.node(SpeexdspNode::new(params.clone()));
+        }
+
+        builder = builder.resample(params.target_format.sample_rate.to_number());
+
+        if let Some(bands) = &params.equalizer {
+            builder = builder.node(EqualizerNode::new(bands.clone()));
+        }
+
+        for (id, params) in &params.post_effects {
+            builder = builder.node(EffectNode::new(*id, *params));
+        }
+
+        if let Some(amplify) = params.amplify {
+            builder = builder.node(AmplifyNode { amplify });
+        }
+
+        // must stay last, nothing can touch the samples once quantized
+        if params.dither != DitherMode::Off
+            && let Some(grid) = QuantizationGrid::new(&params.target_format.audio_format)
+        {
+            builder = builder.node(DitherNode::new(params.dither, grid));
+        }
+
+        builder.build()
+    }
+
+    pub fn input_sample_rate(&self) -> u32 {
+        self.input_sample_rate
+    }
+
+    pub fn output_sample_rate(&self) -> u32 {
+        self.output_sample_rate
+    }
+
+    pub fn node_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.stages.iter().map(|stage| stage.node.name())
+    }
+
+    /// Total latency added by the nodes
+    pub fn latency(&self) -> Duration {
+        self.stages
+            .iter()
+            .map(|stage| {
+                Duration::from_secs_f64(
+                    stage.node.latency() as f64 / stage.input_sample_rate as f64,
+                )
+            })
+            .sum()
+    }
+
+    pub fn process(&mut self, buffer: &mut Vec<Vec<f32>>) -> anyhow::Result<()> {
+        for stage in &mut self.stages {
+            stage.node.process(buffer, stage.input_sample_rate)?;
+        }
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        for stage in &mut self.stages {
+            stage.node.reset();
+        }
+    }
+
+    fn effects(&mut self) -> impl Iterator<Item = &mut EffectNode> {
+        self.stages
+            .iter_mut()
+            .filter_map(|stage| stage.node.as_effect())
+    }
+
+    /// Set new parameters on the effects, keeping their state.
+    /// Returns `false` when the entries are not the same, the pipeline must then be rebuilt.
+    pub fn update_effects(&mut self, effects: &[(EffectId, EffectParams)]) -> bool {
+        let same_entries = self.effects().count() == effects.len()
+            && self
+                .effects()
+                .zip(effects)
+                .all(|(node, (id, params))| node.id == *id && node.is_same_kind(params));
+        if !same_entries {
+            return false;
+        }
+
+        for (node, (_, params)) in self.effects().zip(effects) {
+            node.params = *params;
+        }
+        true
+    }
+
+    /// Keep the state of the effects that were already in `previous`,
+    /// whatever their position
+    pub fn take_effects_state(&mut self, previous: &mut AudioPipeline) {
+        for node in self.effects() {
+            if let Some(previous_node) = previous
+                .effects()
+                .find(|previous_node| previous_node.id == node.id)
+                && previous_node.is_same_kind(&node.params)
+            {
+                node.processor = std::mem::take(&mut previous_node.processor);
+            }
+        }
+    }
+}
+
+pub struct AudioPipelineBuilder {
+    current_sample_rate: u32,
+    resampler_quality: ResamplerQuality,
+    pipeline: AudioPipeline,
+}
+
+impl AudioPipelineBuilder {
+    /// Append a node, preceded by a resampler if it requires another sample rate
+    pub fn node(mut self, node: impl AudioNode + 'static) -> Self {
+        if let Some(sample_rate) = node.sample_rate() {
+            self = self.resample(sample_rate);
+        }
+
+        let input_sample_rate = self.current_sample_rate;
+        self.current_sample_rate = node.output_sample_rate(input_sample_rate);
+        self.pipeline.stages.push(Stage {
+            input_sample_rate,
+            node: Box::new(node),
+        });
+        self
+    }
+
+    /// Append a resampler, only if the current rate differs
+    pub fn resample(self, sample_rate: u32) -> Self {
+        if self.current_sample_rate == sample_rate {
+            return self;
+        }
+        let node = ResamplerNode::new(
+            self.resampler_quality,
+            self.current_sample_rate,
+            sample_rate,
+        );
+        self.node(node)
+    }
+
+    pub fn build(mut self) -> AudioPipeline {
+        self.pipeline.output_sample_rate = self.current_sample_rate;
+        self.pipeline
+    }
+}
+
+pub struct ResamplerNode {
+    quality: ResamplerQuality,
+    input_sample_rate: u32,
+    output_sample_rate: u32,
+    cache: Option<ResamplerCache>,
+}
+
+impl ResamplerNode {
+    pub fn new(quality: ResamplerQuality, input_sample_rate: u32, output_sample_rate: u32) -> Self {
+        Self {
+            quality,
+            input_sample_rate,
+            output_sample_rate,
+            cache: None,
+        }
+    }
+}
+
+impl AudioNode for ResamplerNode {
+    fn name(&self) -> &'static str {
+        "resampler"
+    }
+
+    fn sample_rate(&self) -> Option<u32> {
+        Some(self.input_sample_rate)
+    }
+
+    fn output_sample_rate(&self, _input_sample_rate: u32) -> u32 {
+        self.output_sample_rate
+    }
+
+    fn latency(&self) -> usize {
+        // the exact delay is only known once the resampler is created
+        self.cache
+            .as_ref()
+            .map_or(self.quality.chunk_size(), ResamplerCache::latency)
+    }
+
+    fn process(&mut self, buffer: &mut Vec<Vec<f32>>, _sample_rate: u32) -> anyhow::Result<()> {
+        resample_f32_stream_in_place(
+            buffer,
+            self.quality,
+            self.input_sample_rate as usize,
+            self.output_sample_rate as usize,
+            &mut self.cache,
+        )
+    }
+
+    fn reset(&mut self) {
+        self.cache = None;
+    }
+}
+
+#[derive(Default)]
+pub struct RnnoiseNode {
+    cache: Option<DenoiseCache>,
+    output: Vec<Vec<f32>>,
+}
+
+impl AudioNode for RnnoiseNode {
+    fn name(&self) -> &'static str {
+        "rnnoise"
+    }
+
+    fn sample_rate(&self) -> Option<u32> {
+        Some(DENOISE_RNNOISE_SAMPLE_RATE)
+    }
+
+    fn latency(&self) -> usize {
+        rnnoise_rs::Denoiser::frame_size()
+    }
+
+    fn process(&mut self, buffer: &mut Vec<Vec<f32>>, _sample_rate: u32) -> anyhow::Result<()> {
+        process_denoise_rnnoise_f32_stream(buffer, &mut self.output, &mut self.cache)?;
+        std::mem::swap(buffer, &mut self.output);
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.cache = None;
+    }
+}
+
+pub struct SpeexdspNode {
+    params: AudioProcessParams,
+    cache: Option<SpeexdspCache>,
+    output: Vec<Vec<f32>>,
+}
+
+impl SpeexdspNode {
+    pub fn new(params: AudioProcessParams) -> Self {
+        Self {
+            params,
+            cache: None,
+            output: Vec::new(),
+        }
+    }
+}
+
+impl AudioNode for SpeexdspNode {
+    fn name(&self) -> &'static str {
+        "speexdsp"
+    }
+
+    fn sample_rate(&self) -> Option<u32> {
+        Some(SPEEXDSP_SAMPLE_RATE)
+    }
+
+    fn latency(&self) -> usize {
+        SPEEXDSP_FRAME_SIZE
+    }
+
+    fn process(&mut self, buffer: &mut Vec<Vec<f32>>, _sample_rate: u32) -> anyhow::Result<()> {
+        process_speex_f32_stream(buffer, &mut self.output, &self.params, &mut self.cache)?;
+        std::mem::swap(buffer, &mut self.output);
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.cache = None;
+    }
+}
+
+pub struct EqualizerNode {
+    bands: Vec<EqBand>,
+    cache: Option<EqualizerCache>,
+}
+
+impl EqualizerNode {
+    pub fn new(bands: Vec<EqBand>) -> Self {
+        Self { bands, cache: None }
+    }
+}
+
+impl AudioNode for EqualizerNode {
+    fn name(&self) -> &'static str {
+        "equalizer"
+    }
+
+    fn process(&mut self, buffer: &mut Vec<Vec<f32>>, sample_rate: u32) -> anyhow::Result<()> {
+        process_equalizer_f32_stream(buffer, sample_rate, &self.bands, &mut self.cache);
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.cache = None;
+    }
+}
+
+pub struct EffectNode {
+    /// Entry of the effect chain
+    id: EffectId,
+    params: EffectParams,
+    processor: AudioPostProcessor,
+}
+
+impl EffectNode {
+    pub fn new(id: EffectId, params: EffectParams) -> Self {
+        Self {
+            id,
+            params,
+            processor: AudioPostProcessor::default(),
+        }
+    }
+
+    /// The state of another effect is of no use
+    fn is_same_kind(&self, params: &EffectParams) -> bool {
+        std::mem::discriminant(&self.params) == std::mem::discriminant(params)
+    }
+}
+
+impl AudioNode for EffectNode {
+    fn name(&self) -> &'static str {
+        "effect"
+    }
+
+    fn process(&mut self, buffer: &mut Vec<Vec<f32>>, sample_rate: u32) -> anyhow::Result<()> {
+        post_apply_effect(&mut self.processor, buffer, sample_rate, &self.params);
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.processor = AudioPostProcessor::default();
+    }
+
+    fn as_effect(&mut self) -> Option<&mut EffectNode> {
+        Some(self)
+    }
+}
+
+pub struct AmplifyNode {
+    amplify: f32,
+}
+
+impl AudioNode for AmplifyNode {
+    fn name(&self) -> &'static str {
+        "amplify"
+    }
+
+    fn process(&mut self, buffer: &mut Vec<Vec<f32>>, _sample_rate: u32) -> anyhow::Result<()> {
+        for channel in buffer {
+            for v in channel {
+                *v *= self.amplify;
+            }
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self) {}
+}
+
+pub struct DitherNode {
+    mode: DitherMode,
+    grid: QuantizationGrid,
+    cache: Option<DitherCache>,
+}
+
+impl DitherNode {
+    pub fn new(mode: DitherMode, grid: QuantizationGrid) -> Self {
+        Self {
+            mode,
+            grid,
+            cache: None,
+        }
+    }
+}
+
+impl AudioNode for DitherNode {
+    fn name(&self) -> &'static str {
+        "dither"
+    }
+
+    fn process(&mut self, buffer: &mut Vec<Vec<f32>>, _sample_rate: u32) -> anyhow::Result<()> {
+        process_dither_f32_stream(buffer, self.mode, &self.grid, &mut self.cache);
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.cache = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AudioEffect;
+
+    struct FixedRateNode(u32);
+
+    impl AudioNode for FixedRateNode {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+
+        fn sample_rate(&self) -> Option<u32> {
+            Some(self.0)
+        }
+
+        fn process(&mut self, _buffer: &mut Vec<Vec<f32>>, _: u32) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn reset(&mut self) {}
+    }
+
+    #[test]
+    fn resamplers_only_where_rates_differ() {
+        let pipeline = AudioPipeline::builder(44100, ResamplerQuality::default())
+            .node(FixedRateNode(48000))
+            .node(FixedRateNode(48000))
+            .resample(48000)
+            .node(AmplifyNode { amplify: 2.0 })
+            .resample(44100)
+            .build();
+
+        assert_eq!(
+            pipeline.node_names().collect::<Vec<_>>(),
+            ["resampler", "fixed", "fixed", "amplify", "resampler"]
+        );
+        assert_eq!(pipeline.output_sample_rate(), 44100);
+    }
+
+    #[test]
+    fn amplify_in_place() {
+        let mut pipeline = AudioPipeline::builder(48000, ResamplerQuality::default())
+            .node(AmplifyNode { amplify: 0.5 })
+            .build();
+        let mut buffer = vec![vec![1.0, -1.0], vec![0.5, 0.0]];
+
+        pipeline.process(&mut buffer).unwrap();
+
+        assert_eq!(buffer, vec![vec![0.5, -0.5], vec![0.25, 0.0]]);
+    }
+
+    #[test]
+    fn effects_keep_their_state() {
+        let echo = AudioEffect::Echo.preset().unwrap();
+        let reverb = AudioEffect::ReverbSpatious.preset().unwrap();
+        let build = |effects: &[(EffectId, EffectParams)]| {
+            let mut builder = AudioPipeline::builder(48000, ResamplerQuality::default());
+            for (id, params) in effects {
+                builder = builder.node(EffectNode::new(*id, *params));
+            }
+            builder.build()
+        };
+        let silence = || vec![vec![0.0; 4800]];
+
+        let mut pipeline = build(&[(1, echo), (2, reverb)]);
+        pipeline.process(&mut vec![vec![0.5; 4800]]).unwrap();
+
+        // new parameters, same entries
+        let other_reverb = AudioEffect::ReverbIntimate.preset().unwrap();
+        assert!(pipeline.update_effects(&[(1, echo), (2, other_reverb)]));
+        assert_eq!(pipeline.effects().nth(1).unwrap().params, other_reverb);
+
+        // the echo is bypassed, the reverb moves up and keeps its tail
+        assert!(!pipeline.update_effects(&[(2, other_reverb)]));
+        let mut rebuilt = build(&[(2, other_reverb)]);
+        rebuilt.take_effects_state(&mut pipeline);
+        let mut tail = silence();
+        rebuilt.process(&mut tail).unwrap();
+        assert!(tail[0].iter().any(|sample| *sample != 0.0));
+
+        // a new entry starts from silence
+        let mut fresh = build(&[(3, other_reverb)]);
+        fresh.take_effects_state(&mut pipeline);
+        let mut output = silence();
+        fresh.process(&mut output).unwrap();
+        assert!(output[0].iter().all(|sample| *sample == 0.0));
+    }
+}
@@ -1,75 +1,281 @@
-use anyhow::bail;
-use cpal::traits::DeviceTrait;
+use std::{
+    fmt::Write,
+    ops::RangeInclusive,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+};
+
+use anyhow::{anyhow, bail};
+use cpal::{
+    I24, Sample,
+    traits::{DeviceTrait, HostTrait},
+};
 use rtrb::{Consumer, chunks::ChunkError};
 
 use crate::config::{AudioFormat, ChannelCount, SampleRate};
 
 use super::{AudioBytes, AudioPacketFormat};
 
-pub fn create_audio_stream(
+/// Format of the output stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputFormat {
+    /// What the pipeline writes to the shared buffer
+    pub format: AudioPacketFormat,
+    /// Channels of the device config, can be more than `format.channel_count`.
+    /// The extra channels are filled by [`upmix`].
+    pub device_channels: u16,
+}
+
+/// Pick the output format of the stream: the wanted one when the device supports it,
+/// else the closest supported config. The pipeline then resamples and converts to it.
+pub fn select_output_format(
     device: &cpal::Device,
-    config: AudioPacketFormat,
-    consumer: Consumer<u8>,
-) -> anyhow::Result<(cpal::Stream, AudioPacketFormat)> {
-    let audio_format = config.audio_format.clone();
-    let sample_rate = config.sample_rate.to_number();
-    let mut channel_count = config.channel_count.to_number();
-
-    // check if config is supported by device
-    let supported_configs = device.supported_output_configs()?;
-
-    let mut supported = false;
-    for supported_config in supported_configs {
-        if audio_format == supported_config.sample_format()
-            && supported_config.max_sample_rate() >= sample_rate
-            && supported_config.min_sample_rate() <= sample_rate
-        {
-            // use recommended channel count
-            if supported_config.channels() != channel_count {
-                warn!(
-                    "Using channel count {} instead of {}",
-                    supported_config.channels(),
-                    channel_count
-                );
-                channel_count = supported_config.channels();
-            }
-            supported = true;
-            break;
+    wanted: &AudioPacketFormat,
+) -> anyhow::Result<OutputFormat> {
+    let default_config = device.default_output_config().ok();
+    let wanted_sample_rate = wanted.sample_rate.to_number();
+
+    device
+        .supported_output_configs()?
+        .filter_map(|supported_config| {
+            let audio_format = if wanted.audio_format == supported_config.sample_format() {
+                wanted.audio_format.clone()
+            } else {
+                AudioFormat::from_cpal_format(supported_config.sample_format())?
+            };
+            // surround devices get mono or stereo up-mixed
+            let device_channels = supported_config.channels();
+            let channel_count = ChannelCount::from_number(device_channels)
+                .unwrap_or_else(|| wanted.channel_count.clone());
+            let sample_rate = wanted_sample_rate.clamp(
+                supported_config.min_sample_rate(),
+                supported_config.max_sample_rate(),
+            );
+            let is_default = default_config.as_ref().is_some_and(|default_config| {
+                default_config.sample_format() == supported_config.sample_format()
+                    && default_config.channels() == supported_config.channels()
+            });
+
+            // resampling matters more than format conversion,
+            // the device default breaks ties
+            let distance = (
+                sample_rate.abs_diff(wanted_sample_rate),
+                audio_format != wanted.audio_format,
+                device_channels != wanted.channel_count.to_number(),
+                !is_default,
+            );
+
+            let format = AudioPacketFormat {
+                sample_rate: SampleRate::from_number(sample_rate)?,
+                audio_format,
+                channel_count,
+            };
+            Some((
+                distance,
+                OutputFormat {
+                    format,
+                    device_channels,
+                },
+            ))
+        })
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, format)| format)
+        .ok_or_else(|| anyhow!("No supported output config for this audio device"))
+}
+
+/// Spread frames of `input_channels` over frames of `output_channels`.
+/// Mono and stereo go to the front left and right, the other channels are silent.
+pub fn upmix<F: Copy>(
+    input: &[F],
+    input_channels: usize,
+    output: &mut [F],
+    output_channels: usize,
+    silence: F,
+) {
+    for (input_frame, output_frame) in input
+        .chunks_exact(input_channels)
+        .zip(output.chunks_exact_mut(output_channels))
+    {
+        output_frame.fill(silence);
+        if input_channels == 1 {
+            let front = output_channels.min(2);
+            output_frame[..front].fill(input_frame[0]);
+        } else {
+            let len = input_channels.min(output_channels);
+            output_frame[..len].copy_from_slice(&input_frame[..len]);
         }
     }
+}
 
-    if !supported {
-        bail!(
-            "Unsupported output audio format or sample rate. Please apply recommended format from settings page."
-        );
-    }
+/// Shared with the callbacks of the output stream
+#[derive(Debug, Default)]
+pub struct OutputState {
+    /// Silence the output, the buffer is still consumed to keep the latency
+    pub muted: AtomicBool,
+    /// Callbacks which didn't find enough audio in the buffer
+    pub underruns: AtomicU32,
+    /// The device was unplugged or disabled
+    pub device_lost: AtomicBool,
+}
 
+pub fn create_audio_stream(
+    device: &cpal::Device,
+    output_format: &OutputFormat,
+    consumer: Consumer<u8>,
+    state: Arc<OutputState>,
+) -> anyhow::Result<cpal::Stream> {
+    let format = &output_format.format;
     let config = cpal::StreamConfig {
-        channels: channel_count,
-        sample_rate,
+        channels: output_format.device_channels,
+        sample_rate: format.sample_rate.to_number(),
         buffer_size: cpal::BufferSize::Default,
     };
+    let channels = format.channel_count.to_number() as usize;
 
     // create stream config
-    let stream: cpal::Stream = match audio_format {
-        AudioFormat::I16 => build_output_stream::<i16>(device, config.clone(), consumer),
-        AudioFormat::I24 => build_output_stream::<f32>(device, config.clone(), consumer),
-        AudioFormat::I32 => build_output_stream::<i32>(device, config.clone(), consumer),
-        AudioFormat::U8 => build_output_stream::<u8>(device, config.clone(), consumer),
-        AudioFormat::F32 => build_output_stream::<f32>(device, config.clone(), consumer),
+    let stream: cpal::Stream = match format.audio_format {
+        AudioFormat::I16 => build_output_stream::<i16>(device, config, channels, consumer, state),
+        AudioFormat::I24 => build_output_stream::<I24>(device, config, channels, consumer, state),
+        AudioFormat::I32 => build_output_stream::<i32>(device, config, channels, consumer, state),
+        AudioFormat::U8 => build_output_stream::<u8>(device, config, channels, consumer, state),
+        AudioFormat::F32 => build_output_stream::<f32>(device, config, channels, consumer, state),
     }?;
 
-    // convert stream config to AudioPacketFormat
-    let config = AudioPacketFormat {
-        sample_rate: SampleRate::from_number(sample_rate).unwrap(),
-        audio_format,
-        channel_count: ChannelCount::from_number(channel_count).unwrap(),
-    };
+    Ok(stream)
+}
+
+/// Sample rate ranges supported by the device for this audio format
+pub fn supported_sample_rate_ranges(
+    device: &cpal::Device,
+    audio_format: &AudioFormat,
+) -> anyhow::Result<Vec<RangeInclusive<u32>>> {
+    let mut ranges: Vec<_> = device
+        .supported_output_configs()?
+        .filter(|supported_config| *audio_format == supported_config.sample_format())
+        .map(|supported_config| {
+            supported_config.min_sample_rate()..=supported_config.max_sample_rate()
+        })
+        .collect();
+    ranges.sort_by_key(|range| *range.start());
+    ranges.dedup();
+    Ok(ranges)
+}
+
+/// Check a sample rate when it is saved, to warn in settings that
+/// [`select_output_format`] will use the closest supported rate
+pub fn check_sample_rate(
+    device: &cpal::Device,
+    audio_format: &AudioFormat,
+    sample_rate: SampleRate,
+) -> anyhow::Result<()> {
+    let ranges = supported_sample_rate_ranges(device, audio_format)?;
+    let sample_rate = sample_rate.to_number();
+
+    if !ranges.iter().any(|range| range.contains(&sample_rate)) {
+        bail!(
+            "Sample rate {sample_rate} is not supported by this device in {audio_format}, the closest supported rate will be used. Supported: {}",
+            format_ranges(&ranges)
+        );
+    }
+    Ok(())
+}
+
+/// Every host, output device and supported config, for `--info` and the diagnostics bundle
+pub fn supported_configs_report() -> String {
+    let mut report = String::new();
+
+    for host_id in cpal::available_hosts() {
+        let _ = writeln!(report, "host: {}", host_id.name());
+        let host = match cpal::host_from_id(host_id) {
+            Ok(host) => host,
+            Err(e) => {
+                let _ = writeln!(report, "  unavailable: {e}");
+                continue;
+            }
+        };
+        let default_id = host
+            .default_output_device()
+            .and_then(|device| device.id().ok())
+            .map(|id| id.to_string());
+
+        let devices = match host.output_devices() {
+            Ok(devices) => devices,
+            Err(e) => {
+                let _ = writeln!(report, "  can't list output devices: {e}");
+                continue;
+            }
+        };
+
+        for device in devices {
+            let name = device
+                .description()
+                .map(|desc| desc.name().to_owned())
+                .unwrap_or_else(|e| format!("<{e}>"));
+            let id = device.id().map(|id| id.to_string()).unwrap_or_default();
+            let default = if Some(&id) == default_id.as_ref() {
+                " (default)"
+            } else {
+                ""
+            };
+            let _ = writeln!(report, "  device: {name}{default}, id: {id}");
+
+            match device.default_output_config() {
+                Ok(config) => {
+                    let _ = writeln!(
+                        report,
+                        "    default: {} channels, {} Hz, {}",
+                        config.channels(),
+                        config.sample_rate(),
+                        config.sample_format()
+                    );
+                }
+                Err(e) => {
+                    let _ = writeln!(report, "    default: {e}");
+                }
+            }
+
+            match device.supported_output_configs() {
+                Ok(configs) => {
+                    for config in configs {
+                        let _ = writeln!(
+                            report,
+                            "    supported: {} channels, {}-{} Hz, {}, buffer {:?}",
+                            config.channels(),
+                            config.min_sample_rate(),
+                            config.max_sample_rate(),
+                            config.sample_format(),
+                            config.buffer_size()
+                        );
+                    }
+                }
+                Err(e) => {
+                    let _ = writeln!(report, "    supported: {e}");
+                }
+            }
+        }
+    }
 
-    Ok((stream, config))
+    report
 }
 
-pub fn process_audio<F>(data: &mut [F], consumer: &mut Consumer<u8>, frame_bytes: usize)
+fn format_ranges(ranges: &[RangeInclusive<u32>]) -> String {
+    ranges
+        .iter()
+        .map(|range| {
+            if range.start() == range.end() {
+                range.start().to_string()
+            } else {
+                format!("{}-{}", range.start(), range.end())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Returns `false` on underrun, when the buffer didn't hold enough audio to fill `data`
+pub fn process_audio<F>(data: &mut [F], consumer: &mut Consumer<u8>, frame_bytes: usize) -> bool
 where
     F: cpal::SizedSample + AudioBytes,
 {
@@ -85,11 +291,12 @@ where
             let aligned = slots - (slots % frame_bytes);
             match consumer.read_chunk(aligned) {
                 Ok(c) => c,
-                Err(_) => return,
+                Err(_) => return false,
             }
         }
-        _ => return,
+        _ => return false,
     };
+    let complete = chunk.len() == byte_len;
 
     let (chunk1, mut chunk2) = chunk.as_slices();
 
@@ -122,26 +329,58 @@ where
     }
 
     chunk.commit_all();
+    complete
 }
 
+/// `channels` is the channel count of the shared buffer, up-mixed when the device has more
 fn build_output_stream<F>(
     device: &cpal::Device,
     config: cpal::StreamConfig,
+    channels: usize,
     mut consumer: Consumer<u8>,
+    state: Arc<OutputState>,
 ) -> anyhow::Result<cpal::Stream, cpal::Error>
 where
     F: cpal::SizedSample + AudioBytes + 'static,
 {
     let frame_size = std::mem::size_of::<F>();
-    let channels = config.channels as usize;
+    let device_channels = config.channels as usize;
     let frame_bytes = frame_size * channels;
+    let mut upmix_buffer: Vec<F> = Vec::new();
+
+    let error_state = state.clone();
 
     device.build_output_stream(
         config,
         move |data: &mut [F], _| {
-            process_audio(data, &mut consumer, frame_bytes);
+            let complete = if channels == device_channels {
+                process_audio(data, &mut consumer, frame_bytes)
+            } else {
+                // only grows when the device asks for more than before
+                upmix_buffer.resize(data.len() / device_channels * channels, F::EQUILIBRIUM);
+                let complete = process_audio(&mut upmix_buffer, &mut consumer, frame_bytes);
+                upmix(
+                    &upmix_buffer,
+                    channels,
+                    data,
+                    device_channels,
+                    F::EQUILIBRIUM,
+                );
+                complete
+            };
+            if !complete {
+                state.underruns.fetch_add(1, Ordering::Relaxed);
+            }
+            if state.muted.load(Ordering::Relaxed) {
+                data.fill(F::EQUILIBRIUM);
+            }
+        },
+        move |err| {
+            error!("an error occurred on audio stream: {err}");
+            if let cpal::StreamError::DeviceNotAvailable = err {
+                error_state.device_lost.store(true, Ordering::Relaxed);
+            }
         },
-        |err| error!("an error occurred on audio stream: {err}"),
         None,
     )
 }
@@ -179,4 +418,45 @@ mod tests {
 
         assert_eq!(output, [1000, -2000, 0, 0]);
     }
+
+    #[test]
+    fn process_audio_reads_unpacked_i24() {
+        let (mut producer, mut consumer) = RingBuffer::<u8>::new(16);
+        let input: Vec<u8> = [I24::new_unchecked(-(1 << 23)), I24::new_unchecked(1234)]
+            .iter()
+            .flat_map(AudioBytes::to_bytes)
+            .collect();
+        producer.write_all(&input).unwrap();
+
+        let mut output = [I24::new_unchecked(55); 3];
+        process_audio(&mut output, &mut consumer, std::mem::size_of::<I24>());
+
+        assert_eq!(
+            output,
+            [
+                I24::new_unchecked(-(1 << 23)),
+                I24::new_unchecked(1234),
+                I24::new_unchecked(0)
+            ]
+        );
+    }
+
+    #[test]
+    fn upmix_to_surround() {
+        let mut output = [9_i16; 12];
+        upmix(&[1, 2], 1, &mut output, 6, 0);
+        assert_eq!(output, [1, 1, 0, 0, 0, 0, 2, 2, 0, 0, 0, 0]);
+
+        let mut output = [9_i16; 8];
+        upmix(&[1, 2, 3, 4], 2, &mut output, 4, 0);
+        assert_eq!(output, [1, 2, 0, 0, 3, 4, 0, 0]);
+    }
+
+    #[test]
+    fn packed_i24_round_trip() {
+        let mut bytes = [0u8; 3];
+        I24::new_unchecked(-5_000_000).write_bytes(&mut bytes);
+
+        assert_eq!(I24::from_bytes(&bytes), I24::new_unchecked(-5_000_000));
+    }
 }
@@ -1,7 +1,6 @@
-use std::{
-    sync::{LazyLock, Mutex},
-    vec,
-};
+use std::vec;
+
+use crate::config::EffectParams;
 
 #[derive(Debug, Clone, Default)]
 struct SawtoothOscillator {
@@ -251,7 +250,7 @@ impl PitchShifter {
 }
 
 #[derive(Debug, Clone, Default)]
-struct BiquadFilter {
+pub(super) struct BiquadFilter {
     b0: f32,
     b1: f32,
     b2: f32,
@@ -287,7 +286,7 @@ impl BiquadFilter {
     }
 
     // LPF: Low-Pass Filter
-    fn new_lpf(sample_rate: u32, cutoff_freq: f32, q: f32) -> Self {
+    pub(super) fn new_lpf(sample_rate: u32, cutoff_freq: f32, q: f32) -> Self {
         let w0 = 2.0 * std::f32::consts::PI * cutoff_freq / (sample_rate as f32);
         let alpha = w0.sin() / (2.0 * q);
         let cos_w0 = w0.cos();
@@ -308,7 +307,7 @@ impl BiquadFilter {
     }
 
     // HPF: High-Pass Filter
-    fn new_hpf(sample_rate: u32, cutoff_freq: f32, q: f32) -> Self {
+    pub(super) fn new_hpf(sample_rate: u32, cutoff_freq: f32, q: f32) -> Self {
         let w0 = 2.0 * std::f32::consts::PI * cutoff_freq / (sample_rate as f32);
         let alpha = w0.sin() / (2.0 * q);
         let cos_w0 = w0.cos();
@@ -328,7 +327,66 @@ impl BiquadFilter {
         }
     }
 
-    fn process(&mut self, input: f32) -> f32 {
+    // Peaking EQ: boost or cut around the center frequency
+    pub(super) fn new_peaking(sample_rate: u32, center_freq: f32, q: f32, gain_db: f32) -> Self {
+        let w0 = 2.0 * std::f32::consts::PI * center_freq / (sample_rate as f32);
+        let alpha = w0.sin() / (2.0 * q);
+        let cos_w0 = w0.cos();
+        let a = 10.0f32.powf(gain_db / 40.0);
+
+        let a0 = 1.0 + alpha / a;
+
+        Self {
+            b0: (1.0 + alpha * a) / a0,
+            b1: (-2.0 * cos_w0) / a0,
+            b2: (1.0 - alpha * a) / a0,
+            a1: (-2.0 * cos_w0) / a0,
+            a2: (1.0 - alpha / a) / a0,
+            ..Default::default()
+        }
+    }
+
+    // Low shelf: boost or cut everything below the corner frequency
+    pub(super) fn new_low_shelf(sample_rate: u32, corner_freq: f32, q: f32, gain_db: f32) -> Self {
+        let w0 = 2.0 * std::f32::consts::PI * corner_freq / (sample_rate as f32);
+        let alpha = w0.sin() / (2.0 * q);
+        let cos_w0 = w0.cos();
+        let a = 10.0f32.powf(gain_db / 40.0);
+        let two_sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+
+        let a0 = (a + 1.0) + (a - 1.0) * cos_w0 + two_sqrt_a_alpha;
+
+        Self {
+            b0: (a * ((a + 1.0) - (a - 1.0) * cos_w0 + two_sqrt_a_alpha)) / a0,
+            b1: (2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0)) / a0,
+            b2: (a * ((a + 1.0) - (a - 1.0) * cos_w0 - two_sqrt_a_alpha)) / a0,
+            a1: (-2.0 * ((a - 1.0) + (a + 1.0) * cos_w0)) / a0,
+            a2: ((a + 1.0) + (a - 1.0) * cos_w0 - two_sqrt_a_alpha) / a0,
+            ..Default::default()
+        }
+    }
+
+    // High shelf: boost or cut everything above the corner frequency
+    pub(super) fn new_high_shelf(sample_rate: u32, corner_freq: f32, q: f32, gain_db: f32) -> Self {
+        let w0 = 2.0 * std::f32::consts::PI * corner_freq / (sample_rate as f32);
+        let alpha = w0.sin() / (2.0 * q);
+        let cos_w0 = w0.cos();
+        let a = 10.0f32.powf(gain_db / 40.0);
+        let two_sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+
+        let a0 = (a + 1.0) - (a - 1.0) * cos_w0 + two_sqrt_a_alpha;
+
+        Self {
+            b0: (a * ((a + 1.0) + (a - 1.0) * cos_w0 + two_sqrt_a_alpha)) / a0,
+            b1: (-2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0)) / a0,
+            b2: (a * ((a + 1.0) + (a - 1.0) * cos_w0 - two_sqrt_a_alpha)) / a0,
+            a1: (2.0 * ((a - 1.0) - (a + 1.0) * cos_w0)) / a0,
+            a2: ((a + 1.0) - (a - 1.0) * cos_w0 - two_sqrt_a_alpha) / a0,
+            ..Default::default()
+        }
+    }
+
+    pub(super) fn process(&mut self, input: f32) -> f32 {
         let output = self.b0 * input + self.b1 * self.x1 + self.b2 * self.x2
             - self.a1 * self.y1
             - self.a2 * self.y2;
@@ -503,8 +561,9 @@ struct VocoderBand {
     envelope: EnvelopeFollower,
 }
 
+/// State of a single effect, one instance per entry of the effect chain
 #[derive(Debug, Clone, Default)]
-struct AudioPostProcessor {
+pub struct AudioPostProcessor {
     channels: usize,
     sample_rate: u32,
     dry_wet_mix: f32,
@@ -1196,11 +1255,9 @@ impl AudioPostProcessor {
     }
 }
 
-static AUDIO_POST_PROCESSOR: LazyLock<Mutex<AudioPostProcessor>> =
-    LazyLock::new(|| Mutex::new(AudioPostProcessor::default()));
-
 // apply echo effect to audio buffer in place
 pub fn post_apply_echo(
+    processor: &mut AudioPostProcessor,
     buffer: &mut [Vec<f32>],
     sample_rate: u32,
     delay_ms: u32,
@@ -1208,38 +1265,38 @@ pub fn post_apply_echo(
     cutoff: f32,
     mix: f32,
 ) {
-    let mut processor = AUDIO_POST_PROCESSOR.lock().unwrap();
     processor.configure_echo(sample_rate, buffer.len(), delay_ms, decay, cutoff, mix);
     processor.apply_echo(buffer);
 }
 
 // apply reverb effect to audio buffer in place
 pub fn post_apply_reverb(
+    processor: &mut AudioPostProcessor,
     buffer: &mut [Vec<f32>],
     sample_rate: u32,
     room_size: f32,
     damping: f32,
     mix: f32,
 ) {
-    let mut processor = AUDIO_POST_PROCESSOR.lock().unwrap();
     processor.configure_reverb(sample_rate, buffer.len(), room_size, damping, mix);
     processor.apply_reverb(buffer);
 }
 
 // apply pitch shift effect to audio buffer in place
 pub fn post_apply_pitch_shift(
+    processor: &mut AudioPostProcessor,
     buffer: &mut [Vec<f32>],
     sample_rate: u32,
     pitch_ratio: f32,
     mix: f32,
 ) {
-    let mut processor = AUDIO_POST_PROCESSOR.lock().unwrap();
     processor.configure_pitch_shift(sample_rate, buffer.len(), pitch_ratio, mix);
     processor.apply_pitch_shift(buffer);
 }
 
 // apply walkie-talkie effect to audio buffer in place
 pub fn post_apply_walkie_talkie(
+    processor: &mut AudioPostProcessor,
     buffer: &mut [Vec<f32>],
     sample_rate: u32,
     center_freq: f32,
@@ -1247,20 +1304,25 @@ pub fn post_apply_walkie_talkie(
     drive: f32,
     mix: f32,
 ) {
-    let mut processor = AUDIO_POST_PROCESSOR.lock().unwrap();
     processor.configure_walkie_talkie(sample_rate, buffer.len(), center_freq, q, drive, mix);
     processor.apply_walkie_talkie(buffer);
 }
 
 // apply popstar effect to audio buffer in place
-pub fn post_apply_popstar(buffer: &mut [Vec<f32>], sample_rate: u32, rms_threshold: f32, mix: f32) {
-    let mut processor = AUDIO_POST_PROCESSOR.lock().unwrap();
+pub fn post_apply_popstar(
+    processor: &mut AudioPostProcessor,
+    buffer: &mut [Vec<f32>],
+    sample_rate: u32,
+    rms_threshold: f32,
+    mix: f32,
+) {
     processor.configure_popstar(sample_rate, buffer.len(), rms_threshold, mix);
     processor.apply_popstar(buffer);
 }
 
 // apply flanger effect to audio buffer in place
 pub fn post_apply_flanger(
+    processor: &mut AudioPostProcessor,
     buffer: &mut [Vec<f32>],
     sample_rate: u32,
     rate_hz: f32,
@@ -1269,7 +1331,6 @@ pub fn post_apply_flanger(
     feedback: f32,
     mix: f32,
 ) {
-    let mut processor = AUDIO_POST_PROCESSOR.lock().unwrap();
     processor.configure_flanger(
         sample_rate,
         buffer.len(),
@@ -1284,6 +1345,7 @@ pub fn post_apply_flanger(
 
 // apply phaser effect to audio buffer in place
 pub fn post_apply_phaser(
+    processor: &mut AudioPostProcessor,
     buffer: &mut [Vec<f32>],
     sample_rate: u32,
     rate_hz: f32,
@@ -1292,7 +1354,6 @@ pub fn post_apply_phaser(
     feedback: f32,
     mix: f32,
 ) {
-    let mut processor = AUDIO_POST_PROCESSOR.lock().unwrap();
     processor.configure_phaser(
         sample_rate,
         buffer.len(),
@@ -1307,6 +1368,7 @@ pub fn post_apply_phaser(
 
 // apply vocoder effect to audio buffer in place
 pub fn post_apply_vocoder(
+    processor: &mut AudioPostProcessor,
     buffer: &mut [Vec<f32>],
     sample_rate: u32,
     num_bands: usize,
@@ -1314,7 +1376,6 @@ pub fn post_apply_vocoder(
     q_factor: f32,
     mix: f32,
 ) {
-    let mut processor = AUDIO_POST_PROCESSOR.lock().unwrap();
     processor.configure_vocoder(
         sample_rate,
         buffer.len(),
@@ -1325,3 +1386,87 @@ pub fn post_apply_vocoder(
     );
     processor.apply_vocoder(buffer);
 }
+
+// apply the effect described by params to audio buffer in place
+pub fn post_apply_effect(
+    processor: &mut AudioPostProcessor,
+    buffer: &mut [Vec<f32>],
+    sample_rate: u32,
+    params: &EffectParams,
+) {
+    match *params {
+        EffectParams::Echo(p) => {
+            post_apply_echo(
+                processor,
+                buffer,
+                sample_rate,
+                p.delay_ms,
+                p.decay,
+                p.cutoff,
+                p.mix,
+            );
+        }
+        EffectParams::Reverb(p) => {
+            post_apply_reverb(
+                processor,
+                buffer,
+                sample_rate,
+                p.room_size,
+                p.damping,
+                p.mix,
+            );
+        }
+        EffectParams::PitchShift(p) => {
+            post_apply_pitch_shift(processor, buffer, sample_rate, p.pitch_ratio, p.mix);
+        }
+        EffectParams::Flanger(p) => {
+            post_apply_flanger(
+                processor,
+                buffer,
+                sample_rate,
+                p.rate_hz,
+                p.min_delay_ms,
+                p.depth_ms,
+                p.feedback,
+                p.mix,
+            );
+        }
+        EffectParams::Phaser(p) => {
+            post_apply_phaser(
+                processor,
+                buffer,
+                sample_rate,
+                p.rate_hz,
+                p.f_min,
+                p.f_max,
+                p.feedback,
+                p.mix,
+            );
+        }
+        EffectParams::WalkieTalkie(p) => {
+            post_apply_walkie_talkie(
+                processor,
+                buffer,
+                sample_rate,
+                p.center_freq,
+                p.q,
+                p.drive,
+                p.mix,
+            );
+        }
+        EffectParams::Popstar(p) => {
+            post_apply_popstar(processor, buffer, sample_rate, p.rms_threshold, p.mix);
+        }
+        EffectParams::Vocoder(p) => {
+            post_apply_vocoder(
+                processor,
+                buffer,
+                sample_rate,
+                p.num_bands.max(1),
+                p.carrier_freq,
+                p.q_factor,
+                p.mix,
+            );
+        }
+    }
+}
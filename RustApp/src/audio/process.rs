@@ -1,30 +1,20 @@
-use std::borrow::Cow;
+use anyhow::{anyhow, bail};
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use cpal::I24;
 
 use crate::{
-    audio::{
-        denoise_rnnoise::{DENOISE_RNNOISE_SAMPLE_RATE, DenoiseCache},
-        postprocessing::{
-            post_apply_echo, post_apply_flanger, post_apply_phaser, post_apply_pitch_shift,
-            post_apply_popstar, post_apply_reverb, post_apply_vocoder, post_apply_walkie_talkie,
-        },
-        resampler::{ResamplerCache, resample_f32_stream_owned},
-        speexdsp::{SPEEXDSP_SAMPLE_RATE, SpeexdspCache, process_speex_f32_stream},
-    },
-    config::{AudioEffect, AudioFormat, DenoiseKind},
-    streamer::{AudioPacketMessage, AudioStream},
+    audio::{AudioProcessParams, pipeline::AudioPipeline},
+    config::AudioFormat,
+    streamer::{AudioPacketMessage, AudioStream, Endianness},
 };
 
-use super::{
-    AudioBytes, denoise_rnnoise::process_denoise_rnnoise_f32_stream, resampler::resample_f32_stream,
-};
+use super::AudioBytes;
 
 #[derive(Default)]
 pub struct ProcessCache {
-    resample_rnnoise_cache: Option<ResamplerCache>,
-    resample_speexdsp_cache: Option<ResamplerCache>,
-    resample_to_target: Option<ResamplerCache>,
-    speexdsp: Option<SpeexdspCache>,
-    denoise: Option<DenoiseCache>,
+    pipeline: Option<AudioPipeline>,
+    /// Planar buffer reused for every packet
+    buffer: Vec<Vec<f32>>,
 }
 
 impl ProcessCache {
@@ -32,14 +22,36 @@ impl ProcessCache {
         Self::default()
     }
     pub fn clear(&mut self) {
-        self.resample_rnnoise_cache = None;
-        self.resample_speexdsp_cache = None;
-        self.resample_to_target = None;
-        self.speexdsp = None;
-        self.denoise = None;
+        self.pipeline = None;
+    }
+
+    /// Follow a config change. Effects keep their state: their parameters are
+    /// updated in place when nothing else changed, else they move to the new pipeline.
+    pub fn reconfigure(&mut self, previous: &AudioProcessParams, params: &AudioProcessParams) {
+        let Some(pipeline) = &mut self.pipeline else {
+            return;
+        };
+        if params.differs_only_by_effects(previous) && pipeline.update_effects(&params.post_effects)
+        {
+            return;
+        }
+
+        let mut new_pipeline = build_pipeline(params, pipeline.input_sample_rate());
+        new_pipeline.take_effects_state(pipeline);
+        self.pipeline = Some(new_pipeline);
     }
 }
 
+fn build_pipeline(params: &AudioProcessParams, input_sample_rate: u32) -> AudioPipeline {
+    let pipeline = AudioPipeline::from_params(params, input_sample_rate);
+    info!(
+        "audio pipeline: [{}], latency {:?}",
+        pipeline.node_names().collect::<Vec<_>>().join(" -> "),
+        pipeline.latency()
+    );
+    pipeline
+}
+
 impl AudioStream {
     /// This function converts an audio stream from packet into producer
     /// apply any necessary conversions based on the audio format
@@ -51,7 +63,7 @@ impl AudioStream {
     ) -> anyhow::Result<Option<Vec<f32>>> {
         match self.audio_params.target_format.audio_format {
             AudioFormat::I16 => self.process_audio_packet_internal::<i16>(packet, cache),
-            AudioFormat::I24 => self.process_audio_packet_internal::<f32>(packet, cache),
+            AudioFormat::I24 => self.process_audio_packet_internal::<I24>(packet, cache),
             AudioFormat::I32 => self.process_audio_packet_internal::<i32>(packet, cache),
             AudioFormat::U8 => self.process_audio_packet_internal::<u8>(packet, cache),
             AudioFormat::F32 => self.process_audio_packet_internal::<f32>(packet, cache),
@@ -71,122 +83,25 @@ impl AudioStream {
         F: cpal::SizedSample + AudioBytes + std::fmt::Debug + 'static,
     {
         let config = &self.audio_params;
-        let mut current_sample_rate = packet.sample_rate;
 
         // first convert audio packet to f32 vector
-        let mut buffer = convert_packet_to_f32(&packet)?;
-
-        // next run resampler and denoise on the buffer
-        if let Some(denoise) = &config.denoise {
-            match denoise {
-                DenoiseKind::Rnnoise => {
-                    let prepared_buffer: Cow<'_, [Vec<f32>]> =
-                        if current_sample_rate == DENOISE_RNNOISE_SAMPLE_RATE {
-                            Cow::Borrowed(&buffer)
-                        } else {
-                            let tmp = resample_f32_stream(
-                                &buffer,
-                                current_sample_rate as usize,
-                                DENOISE_RNNOISE_SAMPLE_RATE as usize,
-                                &mut cache.resample_rnnoise_cache,
-                            )?;
-                            current_sample_rate = DENOISE_RNNOISE_SAMPLE_RATE;
-                            Cow::Borrowed(tmp)
-                        };
-
-                    // denoise the audio stream
-                    buffer =
-                        process_denoise_rnnoise_f32_stream(&prepared_buffer, &mut cache.denoise)?;
-                }
-                DenoiseKind::Speexdsp => {}
-            }
-        }
+        convert_packet_to_f32_into(&packet, &mut cache.buffer)?;
 
-        if config.is_speex_used() {
-            let prepared_buffer: Cow<'_, [Vec<f32>]> =
-                if current_sample_rate == SPEEXDSP_SAMPLE_RATE {
-                    Cow::Borrowed(&buffer)
-                } else {
-                    let tmp = resample_f32_stream(
-                        &buffer,
-                        current_sample_rate as usize,
-                        SPEEXDSP_SAMPLE_RATE as usize,
-                        &mut cache.resample_speexdsp_cache,
-                    )?;
-                    current_sample_rate = SPEEXDSP_SAMPLE_RATE;
-                    Cow::Borrowed(tmp)
-                };
-
-            buffer = process_speex_f32_stream(&prepared_buffer, config, &mut cache.speexdsp)?;
+        // the pipeline is rebuilt when the input rate changes,
+        // see `ProcessCache::reconfigure` for config changes
+        if cache
+            .pipeline
+            .as_ref()
+            .is_none_or(|pipeline| pipeline.input_sample_rate() != packet.sample_rate)
+        {
+            cache.pipeline = Some(build_pipeline(config, packet.sample_rate));
         }
 
-        buffer = if config.target_format.sample_rate.to_number() == current_sample_rate {
-            buffer
-        } else {
-            resample_f32_stream_owned(
-                &buffer,
-                current_sample_rate as usize,
-                config.target_format.sample_rate.to_number() as usize,
-                &mut cache.resample_to_target,
-            )?
-        };
+        let pipeline = cache.pipeline.as_mut().unwrap();
+        let buffer = &mut cache.buffer;
 
-        // inject post effect if needed
-        // NOTE: one day I might add UI for users to customize these parameters, but for now just hardcode the presets
-        match &config.post_effect {
-            AudioEffect::NoEffect => {}
-            AudioEffect::Echo => {
-                post_apply_echo(&mut buffer, current_sample_rate, 300, 0.5, 0.3, 0.25);
-            }
-            AudioEffect::ReverbIntimate => {
-                post_apply_reverb(&mut buffer, current_sample_rate, 0.5, 0.8, 0.15);
-            }
-            AudioEffect::ReverbSpatious => {
-                post_apply_reverb(&mut buffer, current_sample_rate, 0.85, 0.5, 0.3);
-            }
-            AudioEffect::Spaceship => {
-                post_apply_flanger(&mut buffer, current_sample_rate, 0.25, 1.0, 6.0, 0.8, 0.5);
-            }
-            AudioEffect::Underwater => {
-                post_apply_phaser(
-                    &mut buffer,
-                    current_sample_rate,
-                    1.5,
-                    150.0,
-                    1200.0,
-                    0.6,
-                    0.7,
-                );
-            }
-            AudioEffect::PitchUp => {
-                post_apply_pitch_shift(&mut buffer, current_sample_rate, 1.5, 1.0);
-            }
-            AudioEffect::PitchDown => {
-                post_apply_pitch_shift(&mut buffer, current_sample_rate, 0.75, 1.0);
-            }
-            AudioEffect::Demon => {
-                post_apply_pitch_shift(&mut buffer, current_sample_rate, 0.8, 0.65);
-            }
-            AudioEffect::Walkie => {
-                post_apply_walkie_talkie(&mut buffer, current_sample_rate, 1200.0, 1.5, 5.0, 1.0);
-            }
-            AudioEffect::Popstar => {
-                post_apply_popstar(&mut buffer, current_sample_rate, 0.02, 0.8);
-            }
-            AudioEffect::Robot => {
-                // NOTE: this vocoder preset does not sound great, but I have no idea how to improve it further
-                // Leave it here for now and maybe one day there will be a better solution
-                post_apply_vocoder(&mut buffer, current_sample_rate, 4, 120.0, 2.8, 0.9);
-            }
-        }
-
-        if let Some(amplify) = config.amplify {
-            for channel in &mut buffer {
-                for v in channel {
-                    *v *= amplify;
-                }
-            }
-        }
+        // run denoise, resampling and effects on the buffer
+        pipeline.process(buffer)?;
 
         // finally convert to output format
         let num_channels = config.target_format.channel_count.to_number() as usize;
@@ -197,7 +112,7 @@ impl AudioStream {
         if num_bytes > 0 {
             match self.buff.write_chunk_uninit(num_bytes) {
                 Ok(chunk) => {
-                    let buffer_ref = &buffer;
+                    let buffer_ref = &*buffer;
 
                     chunk.fill_from_iter((0..num_frames).flat_map(|frame_idx| {
                         (0..num_channels).flat_map(move |channel_idx| {
@@ -208,7 +123,9 @@ impl AudioStream {
                             } else {
                                 0.0 // fill with zero if out of bounds
                             };
-                            F::from_f32(sample).to_bytes()
+                            let mut bytes = [0u8; 4];
+                            F::from_f32(sample).write_bytes(&mut bytes);
+                            bytes.into_iter().take(std::mem::size_of::<F>())
                         })
                     }));
                 }
@@ -245,75 +162,266 @@ impl AudioStream {
     }
 }
 
+/// Validate the header of a packet against its buffer, so malformed packets
+/// are rejected instead of being partially decoded
+fn check_packet(packet: &AudioPacketMessage) -> anyhow::Result<(AudioFormat, Endianness)> {
+    let audio_format = AudioFormat::from_android_format(packet.audio_format)
+        .ok_or_else(|| anyhow!("unknown audio format {}", packet.audio_format))?;
+
+    let endianness = Endianness::try_from(packet.endianness)
+        .map_err(|_| anyhow!("unknown endianness {}", packet.endianness))?;
+
+    if packet.channel_count == 0 {
+        bail!("audio packet without channels");
+    }
+
+    let frame_size = audio_format.sample_size() * packet.channel_count as usize;
+    if packet.buffer.len() % frame_size != 0 {
+        bail!(
+            "audio packet of {} bytes is not made of {frame_size} bytes frames",
+            packet.buffer.len()
+        );
+    }
+
+    Ok((audio_format, endianness))
+}
+
 pub fn convert_packet_to_f32(packet: &AudioPacketMessage) -> anyhow::Result<Vec<Vec<f32>>> {
-    let audio_format = AudioFormat::from_android_format(packet.audio_format).unwrap();
+    let mut result = Vec::new();
+    convert_packet_to_f32_into(packet, &mut result)?;
+    Ok(result)
+}
+
+/// Convert an audio packet to planar f32 channels, reusing the buffers of `result`
+pub fn convert_packet_to_f32_into(
+    packet: &AudioPacketMessage,
+    result: &mut Vec<Vec<f32>>,
+) -> anyhow::Result<()> {
+    let (audio_format, endianness) = check_packet(packet)?;
+    match endianness {
+        Endianness::Little => {
+            convert_packet_to_f32_ordered::<LittleEndian>(packet, &audio_format, result)
+        }
+        Endianness::Big => {
+            convert_packet_to_f32_ordered::<BigEndian>(packet, &audio_format, result)
+        }
+    }
+    Ok(())
+}
+
+fn convert_packet_to_f32_ordered<E: ByteOrder>(
+    packet: &AudioPacketMessage,
+    audio_format: &AudioFormat,
+    result: &mut Vec<Vec<f32>>,
+) {
+    let sample_size = audio_format.sample_size();
     match audio_format {
-        AudioFormat::U8 => convert_packet_to_f32_internal::<u8>(packet),
-        AudioFormat::I16 => convert_packet_to_f32_internal::<i16>(packet),
-        AudioFormat::I24 => convert_packet_to_f32_internal::<f32>(packet),
-        AudioFormat::I32 => convert_packet_to_f32_internal::<i32>(packet),
-        AudioFormat::F32 => convert_packet_to_f32_internal::<f32>(packet),
+        AudioFormat::U8 => convert_packet_to_f32_internal::<u8, E>(packet, sample_size, result),
+        AudioFormat::I16 => convert_packet_to_f32_internal::<i16, E>(packet, sample_size, result),
+        AudioFormat::I24 => convert_packet_to_f32_internal::<I24, E>(packet, sample_size, result),
+        AudioFormat::I32 => convert_packet_to_f32_internal::<i32, E>(packet, sample_size, result),
+        AudioFormat::F32 => convert_packet_to_f32_internal::<f32, E>(packet, sample_size, result),
     }
 }
 
-fn convert_packet_to_f32_internal<F>(packet: &AudioPacketMessage) -> anyhow::Result<Vec<Vec<f32>>>
-where
-    F: cpal::SizedSample + AudioBytes + std::fmt::Debug + 'static,
+fn convert_packet_to_f32_internal<F, E>(
+    packet: &AudioPacketMessage,
+    sample_size: usize,
+    result: &mut Vec<Vec<f32>>,
+) where
+    F: AudioBytes,
+    E: ByteOrder,
 {
-    let audio_format: AudioFormat = AudioFormat::from_android_format(packet.audio_format).unwrap();
     let channel_count = packet.channel_count as usize;
-    let samples_per_channel = packet.buffer.len() / (audio_format.sample_size() * channel_count);
 
-    // Initialize a vector to hold the results for each channel
-    let mut result = vec![Vec::with_capacity(samples_per_channel); channel_count];
+    result.resize_with(channel_count, Vec::new);
+    for channel in result.iter_mut() {
+        channel.clear();
+    }
 
-    for buf in packet.buffer.chunks_exact(audio_format.sample_size()) {
-        for result in &mut result {
-            let sample = F::from_bytes(buf).to_f32();
-            result.push(sample);
+    // samples are interleaved, one frame holds a sample of each channel.
+    // No partial frame is left, `check_packet` rejects them.
+    for frame in packet.buffer.chunks_exact(sample_size * channel_count) {
+        for (channel, buf) in result.iter_mut().zip(frame.chunks_exact(sample_size)) {
+            channel.push(F::read_bytes::<E>(buf).to_f32());
         }
     }
-
-    Ok(result)
 }
 
 fn convert_packet_to_f32_mono(packet: &AudioPacketMessage) -> anyhow::Result<Vec<f32>> {
-    let audio_format = AudioFormat::from_android_format(packet.audio_format).unwrap();
+    let (audio_format, endianness) = check_packet(packet)?;
+    Ok(match endianness {
+        Endianness::Little => {
+            convert_packet_to_f32_mono_ordered::<LittleEndian>(packet, &audio_format)
+        }
+        Endianness::Big => convert_packet_to_f32_mono_ordered::<BigEndian>(packet, &audio_format),
+    })
+}
+
+fn convert_packet_to_f32_mono_ordered<E: ByteOrder>(
+    packet: &AudioPacketMessage,
+    audio_format: &AudioFormat,
+) -> Vec<f32> {
+    let sample_size = audio_format.sample_size();
     match audio_format {
-        AudioFormat::U8 => convert_packet_to_f32_mono_internal::<u8>(packet),
-        AudioFormat::I16 => convert_packet_to_f32_mono_internal::<i16>(packet),
-        AudioFormat::I24 => convert_packet_to_f32_mono_internal::<f32>(packet),
-        AudioFormat::I32 => convert_packet_to_f32_mono_internal::<i32>(packet),
-        AudioFormat::F32 => convert_packet_to_f32_mono_internal::<f32>(packet),
+        AudioFormat::U8 => convert_packet_to_f32_mono_internal::<u8, E>(packet, sample_size),
+        AudioFormat::I16 => convert_packet_to_f32_mono_internal::<i16, E>(packet, sample_size),
+        AudioFormat::I24 => convert_packet_to_f32_mono_internal::<I24, E>(packet, sample_size),
+        AudioFormat::I32 => convert_packet_to_f32_mono_internal::<i32, E>(packet, sample_size),
+        AudioFormat::F32 => convert_packet_to_f32_mono_internal::<f32, E>(packet, sample_size),
     }
 }
 
-fn convert_packet_to_f32_mono_internal<F>(packet: &AudioPacketMessage) -> anyhow::Result<Vec<f32>>
+fn convert_packet_to_f32_mono_internal<F, E>(
+    packet: &AudioPacketMessage,
+    sample_size: usize,
+) -> Vec<f32>
 where
-    F: cpal::SizedSample + AudioBytes + std::fmt::Debug + 'static,
+    F: AudioBytes,
+    E: ByteOrder,
 {
-    let audio_format: AudioFormat = AudioFormat::from_android_format(packet.audio_format).unwrap();
     let channel_count = packet.channel_count as usize;
 
-    let mut result = Vec::<f32>::with_capacity(
-        packet.buffer.len() / (audio_format.sample_size() * channel_count),
-    );
+    let mut result = Vec::<f32>::with_capacity(packet.buffer.len() / (sample_size * channel_count));
 
-    for buf in packet
-        .buffer
-        .chunks_exact(audio_format.sample_size() * channel_count)
-    {
+    for buf in packet.buffer.chunks_exact(sample_size * channel_count) {
         if channel_count == 1 {
             // For mono, there's just one sample
-            result.push(F::from_bytes(buf).to_f32());
+            result.push(F::read_bytes::<E>(buf).to_f32());
         } else {
             // For stereo, we merge the two samples into one
-            let left = F::from_bytes(&buf[0..audio_format.sample_size()]).to_f32();
-            let right = F::from_bytes(&buf[audio_format.sample_size()..]).to_f32();
+            let left = F::read_bytes::<E>(&buf[0..sample_size]).to_f32();
+            let right = F::read_bytes::<E>(&buf[sample_size..]).to_f32();
 
             result.push((left + right) / 2.0); // Mix the two channels
         }
     }
 
-    Ok(result)
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ANDROID_I16: u32 = 2;
+    const ANDROID_F32: u32 = 4;
+    const ANDROID_I24: u32 = 21;
+
+    fn packet(
+        buffer: Vec<u8>,
+        channel_count: u32,
+        audio_format: u32,
+        endianness: Endianness,
+    ) -> AudioPacketMessage {
+        AudioPacketMessage {
+            buffer,
+            sample_rate: 48000,
+            channel_count,
+            audio_format,
+            endianness: endianness as i32,
+        }
+    }
+
+    #[test]
+    fn i16_stereo_in_both_byte_orders() {
+        let expected = vec![vec![16384.0 / 32767.0], vec![-16384.0 / 32767.0]];
+
+        let little = packet(
+            vec![0x00, 0x40, 0x00, 0xC0],
+            2,
+            ANDROID_I16,
+            Endianness::Little,
+        );
+        let big = packet(
+            vec![0x40, 0x00, 0xC0, 0x00],
+            2,
+            ANDROID_I16,
+            Endianness::Big,
+        );
+
+        assert_eq!(convert_packet_to_f32(&little).unwrap(), expected);
+        assert_eq!(convert_packet_to_f32(&big).unwrap(), expected);
+    }
+
+    #[test]
+    fn packed_i24_in_both_byte_orders() {
+        let expected = vec![vec![0x123456 as f32 / ((1 << 23) - 1) as f32]];
+
+        let little = packet(vec![0x56, 0x34, 0x12], 1, ANDROID_I24, Endianness::Little);
+        let big = packet(vec![0x12, 0x34, 0x56], 1, ANDROID_I24, Endianness::Big);
+
+        assert_eq!(convert_packet_to_f32(&little).unwrap(), expected);
+        assert_eq!(convert_packet_to_f32(&big).unwrap(), expected);
+    }
+
+    #[test]
+    fn f32_in_both_byte_orders() {
+        let little = packet(
+            (-0.25_f32).to_le_bytes().to_vec(),
+            1,
+            ANDROID_F32,
+            Endianness::Little,
+        );
+        let big = packet(
+            (-0.25_f32).to_be_bytes().to_vec(),
+            1,
+            ANDROID_F32,
+            Endianness::Big,
+        );
+
+        assert_eq!(convert_packet_to_f32(&little).unwrap(), vec![vec![-0.25]]);
+        assert_eq!(convert_packet_to_f32(&big).unwrap(), vec![vec![-0.25]]);
+    }
+
+    #[test]
+    fn multichannel_is_deinterleaved() {
+        let samples: [i16; 6] = [1, 2, 3, -1, -2, -3];
+        let buffer = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let three_channels = packet(buffer, 3, ANDROID_I16, Endianness::Little);
+
+        let channels = convert_packet_to_f32(&three_channels).unwrap();
+        let expected: Vec<Vec<f32>> = [[1, -1], [2, -2], [3, -3]]
+            .iter()
+            .map(|channel| channel.iter().map(|s| *s as f32 / 32767.0).collect())
+            .collect();
+        assert_eq!(channels, expected);
+    }
+
+    #[test]
+    fn partial_frames_are_rejected() {
+        // one byte short of two i16 frames
+        let odd_length = packet(vec![0; 3], 1, ANDROID_I16, Endianness::Little);
+        assert!(convert_packet_to_f32(&odd_length).is_err());
+
+        // a stereo frame and half of the next one
+        let half_frame = packet(vec![0; 6], 2, ANDROID_I16, Endianness::Little);
+        assert!(convert_packet_to_f32(&half_frame).is_err());
+    }
+
+    #[test]
+    fn missing_endianness_means_little_endian() {
+        use prost::Message;
+
+        // encoded by a sender which predates the endianness field
+        let mut old_packet = packet(vec![0x01, 0x00], 1, ANDROID_I16, Endianness::Little);
+        let decoded = AudioPacketMessage::decode(old_packet.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded.endianness(), Endianness::Little);
+
+        old_packet.endianness = 7;
+        assert!(convert_packet_to_f32(&old_packet).is_err());
+        assert_eq!(
+            convert_packet_to_f32(&decoded).unwrap(),
+            vec![vec![1.0 / 32767.0]]
+        );
+    }
+
+    #[test]
+    fn malformed_packets_are_rejected() {
+        let partial_frame = packet(vec![0x00, 0x40, 0x00], 2, ANDROID_I16, Endianness::Little);
+        let no_channel = packet(vec![0x00, 0x40], 0, ANDROID_I16, Endianness::Little);
+
+        assert!(convert_packet_to_f32(&partial_frame).is_err());
+        assert!(convert_packet_to_f32(&no_channel).is_err());
+    }
 }
@@ -1,34 +1,96 @@
 use rubato::{Indexing, Resampler};
 
+use crate::config::ResamplerQuality;
+
 pub struct ResamplerCache {
+    quality: ResamplerQuality,
     input_rate: usize,
     output_rate: usize,
     nb_channels: usize,
     unprocessed_buffer: Vec<Vec<f32>>,
-    resampler: rubato::Fft<f32>,
+    resampler: Box<dyn Resampler<f32> + Send>,
     result: Vec<Vec<f32>>,
 }
 
-const CHUNK_SIZE: usize = 1024;
+impl ResamplerCache {
+    /// Frames buffered before the first output, plus the delay of the algorithm,
+    /// in frames of the input
+    pub fn latency(&self) -> usize {
+        self.quality.chunk_size()
+            + self.resampler.output_delay() * self.input_rate / self.output_rate
+    }
+}
 
-pub fn resample_f32_stream_owned(
-    data: &[Vec<f32>],
+fn create_resampler(
+    quality: ResamplerQuality,
+    input_sample_rate: usize,
+    output_sample_rate: usize,
+    nb_channel: usize,
+) -> anyhow::Result<Box<dyn Resampler<f32> + Send>> {
+    let ratio = output_sample_rate as f64 / input_sample_rate as f64;
+    let chunk_size = quality.chunk_size();
+
+    let resampler: Box<dyn Resampler<f32> + Send> = match quality {
+        ResamplerQuality::LowLatency => Box::new(rubato::Async::<f32>::new_poly(
+            ratio,
+            1.0,
+            rubato::PolynomialDegree::Cubic,
+            chunk_size,
+            nb_channel,
+            rubato::FixedAsync::Input,
+        )?),
+        ResamplerQuality::Balanced => {
+            let sinc_len = 64;
+            let window = rubato::WindowFunction::BlackmanHarris2;
+            let parameters = rubato::SincInterpolationParameters {
+                sinc_len,
+                f_cutoff: rubato::calculate_cutoff(sinc_len, window),
+                interpolation: rubato::SincInterpolationType::Linear,
+                oversampling_factor: 128,
+                window,
+            };
+            Box::new(rubato::Async::<f32>::new_sinc(
+                ratio,
+                1.0,
+                &parameters,
+                chunk_size,
+                nb_channel,
+                rubato::FixedAsync::Input,
+            )?)
+        }
+        ResamplerQuality::HighQuality => Box::new(rubato::Fft::<f32>::new(
+            input_sample_rate,
+            output_sample_rate,
+            chunk_size,
+            1,
+            nb_channel,
+            rubato::FixedSync::Both,
+        )?),
+    };
+
+    Ok(resampler)
+}
+
+/// Resample `data` in place. The previous buffer is kept by the cache
+/// and reused as the output of the next call.
+pub fn resample_f32_stream_in_place(
+    data: &mut Vec<Vec<f32>>,
+    quality: ResamplerQuality,
     input_sample_rate: usize,
     output_sample_rate: usize,
     cache: &mut Option<ResamplerCache>,
-) -> anyhow::Result<Vec<Vec<f32>>> {
-    resample_f32_stream(data, input_sample_rate, output_sample_rate, cache)?;
+) -> anyhow::Result<()> {
+    resample_f32_stream(data, quality, input_sample_rate, output_sample_rate, cache)?;
 
     let cache = cache.as_mut().unwrap();
+    std::mem::swap(data, &mut cache.result);
 
-    Ok(std::mem::replace(
-        &mut cache.result,
-        vec![Vec::new(); cache.nb_channels],
-    ))
+    Ok(())
 }
 
 pub fn resample_f32_stream<'a>(
     data: &[Vec<f32>],
+    quality: ResamplerQuality,
     input_sample_rate: usize,
     output_sample_rate: usize,
     cache: &'a mut Option<ResamplerCache>,
@@ -38,29 +100,35 @@ pub fn resample_f32_stream<'a>(
 
     if match cache {
         Some(c) => {
-            c.input_rate != input_sample_rate
+            c.quality != quality
+                || c.input_rate != input_sample_rate
                 || c.output_rate != output_sample_rate
                 || c.nb_channels != data.len()
         }
         None => true,
     } {
-        let resampler = rubato::Fft::new(
-            input_sample_rate,
-            output_sample_rate,
-            CHUNK_SIZE,
-            1,
-            nb_channel,
-            rubato::FixedSync::Both,
-        )?;
+        let resampler =
+            create_resampler(quality, input_sample_rate, output_sample_rate, nb_channel)?;
 
-        *cache = Some(ResamplerCache {
+        let new_cache = ResamplerCache {
+            quality,
             input_rate: input_sample_rate,
             output_rate: output_sample_rate,
             nb_channels: nb_channel,
             unprocessed_buffer: vec![Vec::with_capacity(resampler.input_frames_max()); nb_channel],
             resampler,
             result: vec![Vec::new(); nb_channel],
-        });
+        };
+
+        info!(
+            "resampler {:?} {} -> {} Hz, latency {} frames",
+            quality,
+            input_sample_rate,
+            output_sample_rate,
+            new_cache.latency()
+        );
+
+        *cache = Some(new_cache);
     };
 
     let cache = cache.as_mut().unwrap();
@@ -6,10 +6,11 @@ use crate::audio::{AudioProcessParams, chunked_ring_buffer::ChunkedRingBuffer};
 // apparently, speexdsp is optimized for low sample rate (8000, 16000), according to chatgpt,
 // but 16000 just doesn't work on my end
 pub const SPEEXDSP_SAMPLE_RATE: u32 = 48000;
-const FRAME_SIZE: usize = (SPEEXDSP_SAMPLE_RATE as f32 * 0.02) as usize; // 20 ms frame
+pub const SPEEXDSP_FRAME_SIZE: usize = (SPEEXDSP_SAMPLE_RATE as f32 * 0.02) as usize; // 20 ms frame
 
 pub struct SpeexdspCache {
-    sample_buffer: Vec<ChunkedRingBuffer<i16>>,
+    sample_buffer: Vec<ChunkedRingBuffer<f32>>,
+    frame_i16: Vec<i16>,
     denoisers: Vec<SpeexPreprocess>,
     config_denoise_enabled: bool,
     config_noise_suppress: i32,
@@ -37,11 +38,13 @@ impl SpeexdspCache {
     }
 }
 
+/// Process `data` into `output`, reusing the buffers of `output` and of the cache
 pub fn process_speex_f32_stream(
     data: &[Vec<f32>],
+    output: &mut Vec<Vec<f32>>,
     config: &AudioProcessParams,
     cache: &mut Option<SpeexdspCache>,
-) -> anyhow::Result<Vec<Vec<f32>>> {
+) -> anyhow::Result<()> {
     if match cache {
         Some(c) => {
             if data.len() != c.denoisers.len() || c.is_config_changed(config) {
@@ -54,14 +57,19 @@ pub fn process_speex_f32_stream(
     } {
         *cache = Some(SpeexdspCache {
             sample_buffer: vec![
-                ChunkedRingBuffer::new((data[0].len() / FRAME_SIZE) + 1, FRAME_SIZE);
+                ChunkedRingBuffer::new(
+                    (data[0].len() / SPEEXDSP_FRAME_SIZE) + 1,
+                    SPEEXDSP_FRAME_SIZE
+                );
                 data.len()
             ],
+            frame_i16: vec![0; SPEEXDSP_FRAME_SIZE],
             denoisers: data
                 .iter()
                 .map(|_| {
                     let mut st =
-                        SpeexPreprocess::new(FRAME_SIZE, SPEEXDSP_SAMPLE_RATE as usize).unwrap();
+                        SpeexPreprocess::new(SPEEXDSP_FRAME_SIZE, SPEEXDSP_SAMPLE_RATE as usize)
+                            .unwrap();
 
                     st.set_denoise(config.is_speex_denoise_enabled());
                     st.set_noise_suppress(config.speex_noise_suppress);
@@ -91,46 +99,35 @@ pub fn process_speex_f32_stream(
 
     let cache = cache.as_mut().unwrap();
 
-    // Convert f32 to i16
-    let data_i16: Vec<Vec<i16>> = data
-        .iter()
-        .map(|channel| {
-            channel
-                .iter()
-                .map(|&x| (x * i16::MAX as f32).clamp(i16::MIN as f32, i16::MAX as f32) as i16)
-                .collect()
-        })
-        .collect();
-
     // Append new data into the cache
-    for channel_idx in 0..data_i16.len() {
-        cache.sample_buffer[channel_idx].extend(&data_i16[channel_idx]);
+    for (ring_buffer, channel) in cache.sample_buffer.iter_mut().zip(data) {
+        ring_buffer.extend(channel);
     }
 
-    let mut output: Vec<Vec<f32>> =
-        vec![Vec::with_capacity(cache.sample_buffer[0].number_of_chunk() * FRAME_SIZE); data.len()];
+    output.resize_with(data.len(), Vec::new);
+    for channel in output.iter_mut() {
+        channel.clear();
+    }
 
     while cache.sample_buffer[0].has_chunk_available() {
         for channel_idx in 0..data.len() {
             let ring_buffer = &mut cache.sample_buffer[channel_idx];
 
-            let chunk = ring_buffer.first_chunk_mut();
+            // Convert f32 to i16
+            for (dst, &x) in cache.frame_i16.iter_mut().zip(ring_buffer.first_chunk()) {
+                *dst = (x * i16::MAX as f32).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+            }
 
-            if !cache.denoisers[channel_idx].preprocess_run(chunk) {
-                chunk.fill(0);
+            if !cache.denoisers[channel_idx].preprocess_run(&mut cache.frame_i16) {
+                cache.frame_i16.fill(0);
             }
 
             // Scale back to -1.0 to 1.0 range
-            output[channel_idx].extend_from_slice(
-                &chunk
-                    .iter()
-                    .map(|&x| x as f32 / i16::MAX as f32)
-                    .collect::<Vec<f32>>(),
-            );
+            output[channel_idx].extend(cache.frame_i16.iter().map(|&x| x as f32 / i16::MAX as f32));
 
             ring_buffer.remove_first_chunk();
         }
     }
 
-    Ok(output)
+    Ok(())
 }
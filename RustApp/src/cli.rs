@@ -0,0 +1,238 @@
+//! Command line access to the config file: `--set key=value` overrides
+//! and the `config export` / `config import` subcommands.
+//! Also `diagnostics`, to create a bundle for bug reports.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, anyhow, bail};
+use clap::Subcommand;
+use toml::{Table, Value};
+use zconf::ConfigManager;
+
+use crate::{config::Config, diagnostics};
+
+/// Keys still accepted from older config files, but never written back
+const LEGACY_KEYS: &[&str] = &["post_effect"];
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Read or replace the settings without starting the app
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Create a zip with the logs, the config and the audio devices, to attach to a bug report
+    Diagnostics {
+        /// Defaults to the downloads folder
+        path: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Write the current settings as TOML, to stdout if no path is given
+    Export { path: Option<PathBuf> },
+    /// Load settings from a TOML file, keys missing from the file are left unchanged
+    Import { path: PathBuf },
+}
+
+/// Parse `key=value` for `--set`
+pub fn parse_key_value(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("expected key=value, got \"{arg}\"")),
+    }
+}
+
+/// A TOML value, or a plain string when it doesn't parse,
+/// so `--set denoise_kind=Speex` works without quotes
+fn parse_value(raw: &str) -> Value {
+    toml::from_str::<Table>(&format!("value = {raw}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+fn to_table(config: &Config) -> anyhow::Result<Table> {
+    match Value::try_from(config)? {
+        Value::Table(table) => Ok(table),
+        _ => bail!("config is not a table"),
+    }
+}
+
+impl Config {
+    /// Set one field from its config file key
+    pub fn set_key(&mut self, key: &str, value: &str) -> anyhow::Result<()> {
+        let mut values = Table::new();
+        values.insert(key.to_string(), parse_value(value));
+        *self = self.merge_table(values)?;
+        Ok(())
+    }
+
+    /// Overlay `values` on top of this config. Each key is checked on its own,
+    /// so the error names the field that is wrong.
+    fn merge_table(&self, values: Table) -> anyhow::Result<Config> {
+        let mut table = to_table(self)?;
+
+        for (key, value) in values {
+            let mut candidate = table.clone();
+            candidate.insert(key.clone(), value);
+            let config: Config = Value::Table(candidate.clone())
+                .try_into()
+                .map_err(|e| anyhow!("invalid value for \"{key}\": {e}"))?;
+
+            // unknown keys are silently dropped by serde, find them with a round trip.
+            // Legacy keys are read, for `migrate`, but never written back.
+            if !LEGACY_KEYS.contains(&key.as_str()) && !to_table(&config)?.contains_key(&key) {
+                bail!("unknown config field \"{key}\"");
+            }
+            config.validate()?;
+            table = candidate;
+        }
+
+        Ok(Value::Table(table).try_into()?)
+    }
+
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    /// Parse a TOML document on top of this config
+    pub fn merge_toml(&self, content: &str) -> anyhow::Result<Config> {
+        let values: Table = toml::from_str(content)?;
+        // a file from before the effect chain replaces the current chain with its effect
+        let legacy_effect_only =
+            values.contains_key("post_effect") && !values.contains_key("post_effects");
+        let mut config = self.merge_table(values)?;
+        if legacy_effect_only {
+            config.post_effects.clear();
+        }
+        config.migrate();
+        Ok(config)
+    }
+}
+
+pub fn run(command: Command, config_file_path: &Path, log_dir: &Path) -> anyhow::Result<()> {
+    let mut config: ConfigManager<Config> = ConfigManager::new(config_file_path.to_path_buf());
+
+    let action = match command {
+        Command::Config { action } => action,
+        Command::Diagnostics { path } => {
+            let path = path.unwrap_or_else(diagnostics::default_bundle_path);
+            diagnostics::create_bundle(&path, config.data(), log_dir, &Default::default())
+                .with_context(|| format!("can't create {}", path.display()))?;
+            println!("created {}", path.display());
+            return Ok(());
+        }
+    };
+
+    match action {
+        ConfigAction::Export { path } => {
+            let content = config.data().to_toml()?;
+            match path {
+                Some(path) => std::fs::write(&path, content)
+                    .with_context(|| format!("can't write {}", path.display()))?,
+                None => print!("{content}"),
+            }
+        }
+        ConfigAction::Import { path } => {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("can't read {}", path.display()))?;
+            let imported = config
+                .data()
+                .merge_toml(&content)
+                .with_context(|| format!("invalid config {}", path.display()))?;
+            config.update(|config| *config = imported);
+            println!("imported {}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AudioEffect, EffectChainEntry};
+
+    #[test]
+    fn set_key_names_the_bad_field() {
+        let mut config = Config::default();
+
+        config.set_key("port", "1234").unwrap();
+        config.set_key("denoise", "true").unwrap();
+        config.set_key("ip", "192.168.1.2").unwrap();
+        assert_eq!(config.port, 1234);
+        assert!(config.denoise);
+        assert_eq!(config.ip, Some("192.168.1.2".parse().unwrap()));
+
+        let error = config.set_key("port", "not a port").unwrap_err();
+        assert!(error.to_string().contains("\"port\""), "{error}");
+
+        let error = config.set_key("prot", "1234").unwrap_err();
+        assert!(error.to_string().contains("\"prot\""), "{error}");
+        assert_eq!(config.port, 1234);
+    }
+
+    #[test]
+    fn export_import_round_trip() {
+        let config = Config {
+            amplify: true,
+            amplify_value: 2.5,
+            ..Default::default()
+        };
+
+        let imported = Config::default()
+            .merge_toml(&config.to_toml().unwrap())
+            .unwrap();
+        assert!(imported.amplify);
+        assert_eq!(imported.amplify_value, 2.5);
+
+        let error = Config::default()
+            .merge_toml("speex_vad_threshold = -3")
+            .unwrap_err();
+        assert!(error.to_string().contains("speex_vad_threshold"), "{error}");
+    }
+
+    #[test]
+    fn out_of_range_values_name_the_field() {
+        let mut config = Config::default();
+
+        for (key, value) in [
+            ("speex_noise_suppress", "10"),
+            ("speex_vad_threshold", "150"),
+            ("amplify_value", "-1.0"),
+        ] {
+            let error = config.set_key(key, value).unwrap_err();
+            assert!(error.to_string().contains(key), "{error}");
+        }
+
+        let error = Config::default()
+            .merge_toml("speex_dereverb_level = 2.0")
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("speex_dereverb_level"),
+            "{error}"
+        );
+
+        config.set_key("speex_vad_threshold", "100").unwrap();
+        assert_eq!(config.speex_vad_threshold, 100);
+    }
+
+    #[test]
+    fn import_legacy_post_effect() {
+        let mut config = Config::default();
+        config.set_key("amplify", "true").unwrap();
+        config
+            .post_effects
+            .extend(EffectChainEntry::new(AudioEffect::Robot));
+
+        let imported = config.merge_toml("post_effect = \"Echo\"").unwrap();
+        let presets: Vec<_> = imported.post_effects.iter().map(|e| e.preset).collect();
+        assert_eq!(presets, [AudioEffect::Echo]);
+        assert_eq!(imported.post_effect, None);
+        assert!(imported.amplify);
+    }
+}
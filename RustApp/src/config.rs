@@ -1,11 +1,23 @@
-use std::{fmt::Display, net::IpAddr};
+use std::{
+    fmt::Display,
+    net::IpAddr,
+    ops::RangeInclusive,
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
+use anyhow::bail;
 use clap::Parser;
 use light_enum::Values;
 use local_ip_address::local_ip;
 use serde::{Deserialize, Serialize};
 
-use crate::{fl, streamer::DEFAULT_PC_PORT};
+use crate::{
+    cli::{self, Command},
+    fl,
+    profile::Profile,
+    streamer::DEFAULT_PC_PORT,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
@@ -13,13 +25,29 @@ pub struct Config {
     pub connection_mode: ConnectionMode,
     pub ip: Option<IpAddr>,
     pub port: u16,
+    /// Serial of the phone used in ADB mode, needed when several are plugged
+    pub adb_serial: Option<String>,
+    /// Open the Android app in ADB mode, so it starts streaming by itself
+    pub adb_launch_app: bool,
+    /// Phone used in USB mode, any phone of a known vendor when `None`
+    pub usb_device: Option<UsbDeviceId>,
     pub audio_format: AudioFormat,
     pub channel_count: ChannelCount,
     pub sample_rate: SampleRate,
+    pub resampler_quality: ResamplerQuality,
+    /// Applied when the output format is integer
+    pub dither: DitherMode,
     pub device_id: Option<String>,
     pub start_at_login: bool,
+    /// Linux only, ignored in Flatpak where the Background portal is used
+    pub autostart_method: AutostartMethod,
     pub start_minimized: bool,
     pub auto_connect: bool,
+    pub notify_connected: bool,
+    pub notify_disconnected: bool,
+    pub notify_connect_failed: bool,
+    pub notify_buffer_underruns: bool,
+    pub notify_output_device_lost: bool,
     pub denoise: bool,
     pub denoise_kind: DenoiseKind,
     /// range: [-100, 0]
@@ -34,12 +62,23 @@ pub struct Config {
     /// range: [0.0, 1.0]
     pub speex_dereverb_level: f32,
     pub theme: AppTheme,
+    /// Ignored when `RUST_LOG` is set
+    pub log_level: LogLevel,
     pub amplify: bool,
     pub amplify_value: f32,
-    pub post_effect: AudioEffect,
+    /// Ordered effect chain, applied after the equalizer
+    pub post_effects: Vec<EffectChainEntry>,
+    /// Single effect of older config files, moved into `post_effects` by [`Config::migrate`]
+    #[serde(skip_serializing)]
+    pub post_effect: Option<AudioEffect>,
+    pub equalizer: bool,
+    pub equalizer_bands: Vec<EqBand>,
+    pub profiles: Vec<Profile>,
+    /// Last profile saved or switched to
+    pub active_profile: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Values, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Values, PartialEq, Eq)]
 pub enum AudioEffect {
     NoEffect,
     // Environment effects:
@@ -78,6 +117,213 @@ impl Display for AudioEffect {
     }
 }
 
+impl AudioEffect {
+    /// Named parameter set of this preset
+    pub fn preset(&self) -> Option<EffectParams> {
+        let params = match self {
+            AudioEffect::NoEffect => return None,
+            AudioEffect::Echo => EffectParams::Echo(EchoParams {
+                delay_ms: 300,
+                decay: 0.5,
+                cutoff: 0.3,
+                mix: 0.25,
+            }),
+            AudioEffect::ReverbIntimate => EffectParams::Reverb(ReverbParams {
+                room_size: 0.5,
+                damping: 0.8,
+                mix: 0.15,
+            }),
+            AudioEffect::ReverbSpatious => EffectParams::Reverb(ReverbParams {
+                room_size: 0.85,
+                damping: 0.5,
+                mix: 0.3,
+            }),
+            AudioEffect::Spaceship => EffectParams::Flanger(FlangerParams {
+                rate_hz: 0.25,
+                min_delay_ms: 1.0,
+                depth_ms: 6.0,
+                feedback: 0.8,
+                mix: 0.5,
+            }),
+            AudioEffect::Underwater => EffectParams::Phaser(PhaserParams {
+                rate_hz: 1.5,
+                f_min: 150.0,
+                f_max: 1200.0,
+                feedback: 0.6,
+                mix: 0.7,
+            }),
+            AudioEffect::PitchUp => EffectParams::PitchShift(PitchShiftParams {
+                pitch_ratio: 1.5,
+                mix: 1.0,
+            }),
+            AudioEffect::PitchDown => EffectParams::PitchShift(PitchShiftParams {
+                pitch_ratio: 0.75,
+                mix: 1.0,
+            }),
+            AudioEffect::Demon => EffectParams::PitchShift(PitchShiftParams {
+                pitch_ratio: 0.8,
+                mix: 0.65,
+            }),
+            AudioEffect::Walkie => EffectParams::WalkieTalkie(WalkieTalkieParams {
+                center_freq: 1200.0,
+                q: 1.5,
+                drive: 5.0,
+                mix: 1.0,
+            }),
+            AudioEffect::Popstar => EffectParams::Popstar(PopstarParams {
+                rms_threshold: 0.02,
+                mix: 0.8,
+            }),
+            // NOTE: this vocoder preset does not sound great, but I have no idea how to improve it further
+            // Leave it here for now and maybe one day there will be a better solution
+            AudioEffect::Robot => EffectParams::Vocoder(VocoderParams {
+                num_bands: 4,
+                carrier_freq: 120.0,
+                q_factor: 2.8,
+                mix: 0.9,
+            }),
+        };
+
+        Some(params)
+    }
+}
+
+pub const MAX_POST_EFFECTS: usize = 8;
+
+/// Identifies an entry of the effect chain while the app runs,
+/// so its effect keeps its state when other entries change
+pub type EffectId = u64;
+
+static NEXT_EFFECT_ID: AtomicU64 = AtomicU64::new(1);
+
+fn next_effect_id() -> EffectId {
+    NEXT_EFFECT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct EffectChainEntry {
+    /// Not saved, entries get a new one when loaded
+    #[serde(skip, default = "next_effect_id")]
+    pub id: EffectId,
+    /// Preset this entry was created from
+    pub preset: AudioEffect,
+    pub params: EffectParams,
+    pub bypass: bool,
+}
+
+impl EffectChainEntry {
+    pub fn new(preset: AudioEffect) -> Option<Self> {
+        Some(Self {
+            id: next_effect_id(),
+            preset,
+            params: preset.preset()?,
+            bypass: false,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum EffectParams {
+    Echo(EchoParams),
+    Reverb(ReverbParams),
+    PitchShift(PitchShiftParams),
+    Flanger(FlangerParams),
+    Phaser(PhaserParams),
+    WalkieTalkie(WalkieTalkieParams),
+    Popstar(PopstarParams),
+    Vocoder(VocoderParams),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct EchoParams {
+    /// range: [20, 1000] ms
+    pub delay_ms: u32,
+    /// range: [0.0, 0.95], echo feedback
+    pub decay: f32,
+    /// range: [0.0, 1.0], high frequency loss of each echo
+    pub cutoff: f32,
+    /// range: [0.0, 1.0]
+    pub mix: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct ReverbParams {
+    /// range: [0.0, 1.0]
+    pub room_size: f32,
+    /// range: [0.0, 1.0]
+    pub damping: f32,
+    /// range: [0.0, 1.0]
+    pub mix: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct PitchShiftParams {
+    /// range: [0.5, 2.0], >1.0 for pitch up, <1.0 for pitch down
+    pub pitch_ratio: f32,
+    /// range: [0.0, 1.0]
+    pub mix: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct FlangerParams {
+    /// range: [0.1, 10.0] Hz
+    pub rate_hz: f32,
+    /// range: [0.1, 10.0] ms
+    pub min_delay_ms: f32,
+    /// range: [0.0, 10.0] ms
+    pub depth_ms: f32,
+    /// range: [0.0, 0.9]
+    pub feedback: f32,
+    /// range: [0.0, 1.0]
+    pub mix: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct PhaserParams {
+    /// range: [0.1, 10.0] Hz
+    pub rate_hz: f32,
+    /// range: [50, 1000] Hz
+    pub f_min: f32,
+    /// range: [500, 5000] Hz
+    pub f_max: f32,
+    /// range: [0.0, 0.9]
+    pub feedback: f32,
+    /// range: [0.0, 1.0]
+    pub mix: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct WalkieTalkieParams {
+    /// range: [300, 3000] Hz
+    pub center_freq: f32,
+    /// range: [0.5, 5.0]
+    pub q: f32,
+    /// range: [1.0, 10.0]
+    pub drive: f32,
+    /// range: [0.0, 1.0]
+    pub mix: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct PopstarParams {
+    /// range: [0.0, 0.2], minimum RMS required to attempt pitch detection
+    pub rms_threshold: f32,
+    /// range: [0.0, 1.0]
+    pub mix: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct VocoderParams {
+    /// range: [1, 24]
+    pub num_bands: usize,
+    /// range: [50, 1000] Hz
+    pub carrier_freq: f32,
+    /// range: [1.0, 10.0]
+    pub q_factor: f32,
+    /// range: [0.0, 1.0]
+    pub mix: f32,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default, Values, PartialEq)]
 pub enum DenoiseKind {
     #[default]
@@ -96,21 +342,220 @@ impl Display for DenoiseKind {
     }
 }
 
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, Values, PartialEq, Eq)]
+pub enum ResamplerQuality {
+    /// Polynomial interpolation
+    LowLatency,
+    /// Sinc interpolation
+    Balanced,
+    /// Fft based, the most accurate
+    #[default]
+    HighQuality,
+}
+
+impl ResamplerQuality {
+    /// Number of input frames the resampler waits for before producing output
+    pub fn chunk_size(&self) -> usize {
+        match self {
+            ResamplerQuality::LowLatency => 128,
+            ResamplerQuality::Balanced => 256,
+            ResamplerQuality::HighQuality => 1024,
+        }
+    }
+}
+
+impl Display for ResamplerQuality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            ResamplerQuality::LowLatency => fl!("resampler_low_latency"),
+            ResamplerQuality::Balanced => fl!("resampler_balanced"),
+            ResamplerQuality::HighQuality => fl!("resampler_high_quality"),
+        };
+        write!(f, "{}", str)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, Values, PartialEq, Eq)]
+pub enum DitherMode {
+    /// Plain rounding
+    Off,
+    /// Triangular dither
+    #[default]
+    Tpdf,
+    /// Triangular dither, with the noise moved to high frequencies
+    NoiseShaped,
+}
+
+impl Display for DitherMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            DitherMode::Off => fl!("dither_off"),
+            DitherMode::Tpdf => fl!("dither_tpdf"),
+            DitherMode::NoiseShaped => fl!("dither_noise_shaped"),
+        };
+        write!(f, "{}", str)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, Values, PartialEq, Eq)]
+pub enum AutostartMethod {
+    /// XDG autostart entry, started with the desktop session
+    #[default]
+    DesktopEntry,
+    /// systemd user unit, also works without a desktop session
+    SystemdUnit,
+}
+
+impl Display for AutostartMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            AutostartMethod::DesktopEntry => fl!("autostart_desktop_entry"),
+            AutostartMethod::SystemdUnit => fl!("autostart_systemd_unit"),
+        };
+        write!(f, "{}", str)
+    }
+}
+
+/// Desktop notifications, each one can be turned off in the settings
+#[derive(Debug, Clone, Copy, Values, PartialEq, Eq)]
+pub enum NotificationKind {
+    Connected,
+    Disconnected,
+    ConnectFailed,
+    BufferUnderruns,
+    OutputDeviceLost,
+}
+
+impl Display for NotificationKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            NotificationKind::Connected => fl!("notify_connected"),
+            NotificationKind::Disconnected => fl!("notify_disconnected"),
+            NotificationKind::ConnectFailed => fl!("notify_connect_failed"),
+            NotificationKind::BufferUnderruns => fl!("notify_buffer_underruns"),
+            NotificationKind::OutputDeviceLost => fl!("notify_output_device_lost"),
+        };
+        write!(f, "{}", str)
+    }
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Values,
+    strum::Display,
+    strum::EnumString,
+    serde_with::DeserializeFromStr,
+    Serialize,
+)]
+#[strum(ascii_case_insensitive)]
+pub enum LogLevel {
+    Error,
+    #[default]
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub fn to_level_filter(self) -> log::LevelFilter {
+        match self {
+            LogLevel::Error => log::LevelFilter::Error,
+            LogLevel::Warn => log::LevelFilter::Warn,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Debug => log::LevelFilter::Debug,
+            LogLevel::Trace => log::LevelFilter::Trace,
+        }
+    }
+}
+
+pub const MAX_EQ_BANDS: usize = 10;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct EqBand {
+    pub kind: EqBandKind,
+    /// range: [20, 20000] Hz
+    pub frequency: f32,
+    /// range: [-24, 24] dB, ignored by pass filters
+    pub gain: f32,
+    /// range: [0.1, 10.0]
+    pub q: f32,
+}
+
+impl Default for EqBand {
+    fn default() -> Self {
+        Self {
+            kind: EqBandKind::Peaking,
+            frequency: 1000.0,
+            gain: 0.0,
+            q: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Values, PartialEq, Eq)]
+pub enum EqBandKind {
+    LowShelf,
+    HighShelf,
+    Peaking,
+    HighPass,
+    LowPass,
+}
+
+impl EqBandKind {
+    pub fn has_gain(&self) -> bool {
+        matches!(
+            self,
+            EqBandKind::LowShelf | EqBandKind::HighShelf | EqBandKind::Peaking
+        )
+    }
+}
+
+impl Display for EqBandKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            EqBandKind::LowShelf => fl!("eq_low_shelf"),
+            EqBandKind::HighShelf => fl!("eq_high_shelf"),
+            EqBandKind::Peaking => fl!("eq_peaking"),
+            EqBandKind::HighPass => fl!("eq_high_pass"),
+            EqBandKind::LowPass => fl!("eq_low_pass"),
+        };
+        write!(f, "{}", str)
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             connection_mode: Default::default(),
             ip: None,
             port: DEFAULT_PC_PORT,
+            adb_serial: None,
+            adb_launch_app: false,
+            usb_device: None,
             audio_format: Default::default(),
             channel_count: Default::default(),
             sample_rate: Default::default(),
+            resampler_quality: Default::default(),
+            dither: Default::default(),
             device_id: None,
             start_at_login: false,
+            autostart_method: Default::default(),
             auto_connect: false,
+            notify_connected: true,
+            notify_disconnected: true,
+            notify_connect_failed: true,
+            notify_buffer_underruns: true,
+            notify_output_device_lost: true,
             denoise: false,
             denoise_kind: Default::default(),
             theme: Default::default(),
+            log_level: Default::default(),
             amplify: false,
             amplify_value: 2.0,
             speex_noise_suppress: -30,
@@ -120,8 +565,13 @@ impl Default for Config {
             speex_agc_target: 8000,
             speex_dereverb_enabled: false,
             speex_dereverb_level: 0.5,
-            post_effect: AudioEffect::NoEffect,
+            post_effects: Vec::new(),
+            post_effect: None,
             start_minimized: false,
+            equalizer: false,
+            equalizer_bands: Vec::new(),
+            profiles: Vec::new(),
+            active_profile: None,
         }
     }
 }
@@ -137,11 +587,105 @@ impl Config {
         self.speex_dereverb_level = 0.5;
     }
 
+    pub fn notification_enabled(&self, kind: NotificationKind) -> bool {
+        match kind {
+            NotificationKind::Connected => self.notify_connected,
+            NotificationKind::Disconnected => self.notify_disconnected,
+            NotificationKind::ConnectFailed => self.notify_connect_failed,
+            NotificationKind::BufferUnderruns => self.notify_buffer_underruns,
+            NotificationKind::OutputDeviceLost => self.notify_output_device_lost,
+        }
+    }
+
+    pub fn set_notification_enabled(&mut self, kind: NotificationKind, enabled: bool) {
+        let toggle = match kind {
+            NotificationKind::Connected => &mut self.notify_connected,
+            NotificationKind::Disconnected => &mut self.notify_disconnected,
+            NotificationKind::ConnectFailed => &mut self.notify_connect_failed,
+            NotificationKind::BufferUnderruns => &mut self.notify_buffer_underruns,
+            NotificationKind::OutputDeviceLost => &mut self.notify_output_device_lost,
+        };
+        *toggle = enabled;
+    }
+
+    /// Upgrade fields of config files written by older versions
+    pub fn migrate(&mut self) {
+        if let Some(post_effect) = self.post_effect.take()
+            && self.post_effects.is_empty()
+            && let Some(entry) = EffectChainEntry::new(post_effect)
+        {
+            self.post_effects.push(entry);
+        }
+    }
+
+    /// Check the ranges the settings window enforces, for values from `--set` or an imported file.
+    /// The error names the bad field.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        fn check<T: PartialOrd + Display>(
+            field: &str,
+            value: T,
+            range: RangeInclusive<T>,
+        ) -> anyhow::Result<()> {
+            if !range.contains(&value) {
+                bail!(
+                    "invalid value for \"{field}\": {value} is not in [{}, {}]",
+                    range.start(),
+                    range.end()
+                );
+            }
+            Ok(())
+        }
+
+        check("speex_noise_suppress", self.speex_noise_suppress, -100..=0)?;
+        check("speex_vad_threshold", self.speex_vad_threshold, 0..=100)?;
+        check("speex_agc_target", self.speex_agc_target, 8000..=65535)?;
+        check("speex_dereverb_level", self.speex_dereverb_level, 0.0..=1.0)?;
+        check("amplify_value", self.amplify_value, 0.0..=10.0)?;
+        for band in &self.equalizer_bands {
+            check("equalizer_bands.frequency", band.frequency, 20.0..=20000.0)?;
+            check("equalizer_bands.gain", band.gain, -24.0..=24.0)?;
+            check("equalizer_bands.q", band.q, 0.1..=10.0)?;
+        }
+        Ok(())
+    }
+
     pub fn ip_or_default(&self) -> Option<IpAddr> {
         self.ip.or(local_ip().ok())
     }
 }
 
+/// A USB device, found again with its ids after a restart
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UsbDeviceId {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// Kept in accessory mode, when the other ids change
+    pub serial: Option<String>,
+    /// Only for display
+    pub name: Option<String>,
+}
+
+impl UsbDeviceId {
+    /// The product id changes with the USB mode of the phone (file transfer, tethering...),
+    /// it is only compared when there is no serial
+    pub fn matches(&self, vendor_id: u16, product_id: u16, serial: Option<&str>) -> bool {
+        self.vendor_id == vendor_id
+            && match &self.serial {
+                Some(selected_serial) => serial == Some(selected_serial.as_str()),
+                None => self.product_id == product_id,
+            }
+    }
+}
+
+impl Display for UsbDeviceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(name) = &self.name {
+            write!(f, "{name} ")?;
+        }
+        write!(f, "({:04x}:{:04x})", self.vendor_id, self.product_id)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Values)]
 pub enum AppTheme {
     System,
@@ -191,6 +735,13 @@ pub struct Args {
     #[arg(short = 'd', long = "device", id = "output device")]
     pub output_device: Option<String>,
 
+    #[arg(
+        long = "adb-device",
+        id = "adb serial",
+        help = "serial of the phone used in ADB mode, as listed by `adb devices`"
+    )]
+    pub adb_serial: Option<String>,
+
     #[arg(
         short = 'f',
         long = "format",
@@ -206,7 +757,7 @@ pub struct Args {
         short = 's',
         long = "sample",
         id = "sample rate",
-        help = "16000, 44100, ..."
+        help = "any rate supported by the output device, example: 48000"
     )]
     pub sample_rate: Option<SampleRate>,
 
@@ -218,8 +769,34 @@ pub struct Args {
     )]
     pub show_supported_audio_config: bool,
 
+    #[arg(
+        short = 'p',
+        long = "profile",
+        id = "profile name",
+        help = "switch to a saved profile, also works when the app is already running"
+    )]
+    pub profile: Option<String>,
+
+    #[arg(
+        long = "set",
+        value_name = "KEY=VALUE",
+        value_parser = cli::parse_key_value,
+        help = "set any config file field, can be repeated, example: --set denoise=true"
+    )]
+    pub set: Vec<(String, String)>,
+
+    #[arg(
+        long = "log-level",
+        id = "log level",
+        help = "error, warn, info, debug or trace, also works when the app is already running"
+    )]
+    pub log_level: Option<LogLevel>,
+
     #[arg(long, hide = true, default_value_t = false)]
     pub launched_automatically: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
 }
 
 #[derive(
@@ -245,6 +822,28 @@ pub enum ConnectionMode {
     Usb,
 }
 
+impl ConnectionMode {
+    pub const VALUES: &[ConnectionMode] = &[
+        ConnectionMode::Tcp,
+        ConnectionMode::Udp,
+        #[cfg(feature = "adb")]
+        ConnectionMode::Adb,
+        #[cfg(feature = "usb")]
+        ConnectionMode::Usb,
+    ];
+
+    pub fn label(&self) -> String {
+        match self {
+            ConnectionMode::Tcp => fl!("connection_tcp"),
+            ConnectionMode::Udp => fl!("connection_udp"),
+            #[cfg(feature = "adb")]
+            ConnectionMode::Adb => fl!("connection_adb"),
+            #[cfg(feature = "usb")]
+            ConnectionMode::Usb => fl!("connection_usb"),
+        }
+    }
+}
+
 #[derive(
     Debug,
     Clone,
@@ -327,6 +926,14 @@ impl AudioFormat {
         }
     }
 
+    /// Size of a sample in the output buffer, where 24-bit samples are unpacked
+    pub fn output_sample_size(&self) -> usize {
+        match self {
+            AudioFormat::I24 => 4,
+            _ => self.sample_size(),
+        }
+    }
+
     pub fn from_android_format(format: u32) -> Option<Self> {
         match format {
             3 => Some(AudioFormat::U8),
@@ -339,10 +946,10 @@ impl AudioFormat {
     }
 
     pub fn from_cpal_format(format: cpal::SampleFormat) -> Option<Self> {
-        // no i24 in cpal ?
         match format {
             cpal::SampleFormat::U8 => Some(AudioFormat::U8),
             cpal::SampleFormat::I16 => Some(AudioFormat::I16),
+            cpal::SampleFormat::I24 => Some(AudioFormat::I24),
             cpal::SampleFormat::I32 => Some(AudioFormat::I32),
             cpal::SampleFormat::F32 => Some(AudioFormat::F32),
             _ => None,
@@ -356,72 +963,101 @@ impl PartialEq<cpal::SampleFormat> for AudioFormat {
             AudioFormat::U8 => *other == cpal::SampleFormat::U8,
             AudioFormat::I16 => *other == cpal::SampleFormat::I16,
             AudioFormat::I32 => *other == cpal::SampleFormat::I32,
-            AudioFormat::I24 => *other == cpal::SampleFormat::F32,
+            AudioFormat::I24 => *other == cpal::SampleFormat::I24,
             AudioFormat::F32 => *other == cpal::SampleFormat::F32,
         }
     }
 }
 
-#[derive(
-    Debug,
-    Clone,
-    PartialEq,
-    Default,
-    Values,
-    strum::Display,
-    strum::EnumString,
-    serde_with::SerializeDisplay,
-    serde_with::DeserializeFromStr,
-)]
-pub enum SampleRate {
-    #[strum(serialize = "8000")]
-    S8000,
-    #[strum(serialize = "11025")]
-    S11025,
-    #[strum(serialize = "16000")]
-    S16000,
-    #[strum(serialize = "22050")]
-    S22050,
-    #[default]
-    #[strum(serialize = "44100")]
-    S44100,
-    #[strum(serialize = "48000")]
-    S48000,
-    #[strum(serialize = "88200")]
-    S88200,
-    #[strum(serialize = "96600")]
-    S96600,
-    #[strum(serialize = "176400")]
-    S176400,
-    #[strum(serialize = "192000")]
-    S192000,
-    #[strum(serialize = "352800")]
-    S352800,
-    #[strum(serialize = "384000")]
-    S384000,
-}
+/// Sample rate in Hz. Any rate is accepted, as long as the output device supports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(transparent)]
+pub struct SampleRate(u32);
 
 impl SampleRate {
+    pub const MIN: u32 = 1000;
+    pub const MAX: u32 = 1_536_000;
+
+    /// Rates offered in the settings
+    pub const VALUES: &[SampleRate] = &[
+        SampleRate(8000),
+        SampleRate(11025),
+        SampleRate(16000),
+        SampleRate(22050),
+        SampleRate(44100),
+        SampleRate(48000),
+        SampleRate(88200),
+        SampleRate(96000),
+        SampleRate(176400),
+        SampleRate(192000),
+        SampleRate(352800),
+        SampleRate(384000),
+    ];
+
     pub fn to_number(&self) -> u32 {
-        self.to_string().parse().unwrap()
+        self.0
     }
 
     pub fn from_number(value: u32) -> Option<Self> {
-        match value {
-            8000 => Some(SampleRate::S8000),
-            11025 => Some(SampleRate::S11025),
-            16000 => Some(SampleRate::S16000),
-            22050 => Some(SampleRate::S22050),
-            44100 => Some(SampleRate::S44100),
-            48000 => Some(SampleRate::S48000),
-            88200 => Some(SampleRate::S88200),
-            96600 => Some(SampleRate::S96600),
-            176400 => Some(SampleRate::S176400),
-            192000 => Some(SampleRate::S192000),
-            352800 => Some(SampleRate::S352800),
-            384000 => Some(SampleRate::S384000),
-            _ => None,
+        (Self::MIN..=Self::MAX)
+            .contains(&value)
+            .then_some(SampleRate(value))
+    }
+}
+
+impl Default for SampleRate {
+    fn default() -> Self {
+        SampleRate(44100)
+    }
+}
+
+impl Display for SampleRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for SampleRate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s
+            .trim()
+            .parse()
+            .map_err(|e| format!("invalid sample rate {s:?}: {e}"))?;
+
+        Self::from_number(value).ok_or_else(|| {
+            format!(
+                "sample rate {value} is out of range [{}, {}]",
+                Self::MIN,
+                Self::MAX
+            )
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for SampleRate {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        // old config files store the rate as a string,
+        // and could contain the misspelled "96600"
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(u32),
+            String(String),
         }
+
+        let value = match Raw::deserialize(deserializer)? {
+            Raw::Number(value) => value,
+            Raw::String(value) => value.trim().parse().map_err(serde::de::Error::custom)?,
+        };
+        let value = if value == 96600 { 96000 } else { value };
+
+        Self::from_number(value)
+            .ok_or_else(|| serde::de::Error::custom(format!("sample rate {value} is out of range")))
     }
 }
 
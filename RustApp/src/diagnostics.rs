@@ -0,0 +1,244 @@
+//! Diagnostics bundle: a zip with everything needed to investigate a "no audio" report,
+//! created from the logs menu or with `android-mic diagnostics`.
+
+use std::{
+    fmt::Write as _,
+    fs::File,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{self, Write},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use chrono::Local;
+use directories::UserDirs;
+use local_ip_address::list_afinet_netifas;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use crate::{audio::player, config::Config, logging, utils::APP};
+
+/// Counters of the audio path since the app started
+#[derive(Debug)]
+pub struct PipelineStats {
+    pub connections: AtomicU64,
+    pub packets_received: AtomicU64,
+    pub bytes_received: AtomicU64,
+    /// Packets dropped because the DSP thread was behind
+    pub packets_dropped: AtomicU64,
+    pub output_underruns: AtomicU64,
+    pub underrun_storms: AtomicU64,
+    pub output_devices_lost: AtomicU64,
+}
+
+pub static PIPELINE_STATS: PipelineStats = PipelineStats {
+    connections: AtomicU64::new(0),
+    packets_received: AtomicU64::new(0),
+    bytes_received: AtomicU64::new(0),
+    packets_dropped: AtomicU64::new(0),
+    output_underruns: AtomicU64::new(0),
+    underrun_storms: AtomicU64::new(0),
+    output_devices_lost: AtomicU64::new(0),
+};
+
+impl PipelineStats {
+    fn report(&self) -> String {
+        [
+            ("connections", &self.connections),
+            ("packets received", &self.packets_received),
+            ("bytes received", &self.bytes_received),
+            ("packets dropped", &self.packets_dropped),
+            ("output underruns", &self.output_underruns),
+            ("underrun storms", &self.underrun_storms),
+            ("output devices lost", &self.output_devices_lost),
+        ]
+        .iter()
+        .fold(String::new(), |mut report, (name, counter)| {
+            let _ = writeln!(report, "{name}: {}", counter.load(Ordering::Relaxed));
+            report
+        })
+    }
+}
+
+/// What only a running app knows
+#[derive(Debug, Default)]
+pub struct SessionInfo<'a> {
+    pub connection_errors: &'a [String],
+    pub output_format: Option<String>,
+}
+
+/// Private addresses are kept, they tell which network is used without identifying anyone
+fn sanitize_ip(ip: IpAddr) -> Option<IpAddr> {
+    match ip {
+        IpAddr::V4(ip) if ip.is_private() || ip.is_loopback() || ip.is_link_local() => {
+            Some(ip.into())
+        }
+        _ => None,
+    }
+}
+
+/// Device serials identify a phone. A hash still tells whether two of them are the same.
+fn sanitize_serial(serial: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    serial.hash(&mut hasher);
+    format!("<serial {:08x}>", hasher.finish() as u32)
+}
+
+fn sanitized_config(config: &Config) -> Config {
+    let mut config = config.clone();
+    config.ip = config.ip.and_then(sanitize_ip);
+    config.adb_serial = config.adb_serial.as_deref().map(sanitize_serial);
+    if let Some(usb_device) = &mut config.usb_device {
+        usb_device.serial = usb_device.serial.as_deref().map(sanitize_serial);
+    }
+    for profile in &mut config.profiles {
+        if let Some(connection) = &mut profile.connection {
+            connection.ip = connection.ip.and_then(sanitize_ip);
+            connection.adb_serial = connection.adb_serial.as_deref().map(sanitize_serial);
+            if let Some(usb_device) = &mut connection.usb_device {
+                usb_device.serial = usb_device.serial.as_deref().map(sanitize_serial);
+            }
+        }
+    }
+    config
+}
+
+fn network_adapters_report() -> String {
+    match list_afinet_netifas() {
+        Ok(adapters) => adapters
+            .iter()
+            .fold(String::new(), |mut report, (name, ip)| {
+                let ip = match sanitize_ip(*ip) {
+                    Some(ip) => ip.to_string(),
+                    None => "<public address>".into(),
+                };
+                let _ = writeln!(report, "{name}: {ip}");
+                report
+            }),
+        Err(e) => format!("can't list network adapters: {e}\n"),
+    }
+}
+
+fn system_report(session: &SessionInfo) -> String {
+    let mut report = String::new();
+    let _ = writeln!(report, "app version: {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(
+        report,
+        "os: {} {}",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    let _ = writeln!(report, "created: {}", Local::now().to_rfc3339());
+    if let Some(output_format) = &session.output_format {
+        let _ = writeln!(report, "output format: {output_format}");
+    }
+    report
+}
+
+/// In the downloads folder when there is one
+pub fn default_bundle_path() -> PathBuf {
+    let dir = UserDirs::new()
+        .and_then(|dirs| dirs.download_dir().map(Path::to_path_buf))
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_default();
+    dir.join(format!(
+        "{APP}-diagnostics-{}.zip",
+        Local::now().format("%Y%m%d-%H%M%S")
+    ))
+}
+
+pub fn create_bundle(
+    path: &Path,
+    config: &Config,
+    log_dir: &Path,
+    session: &SessionInfo,
+) -> anyhow::Result<()> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let add_text = |zip: &mut ZipWriter<File>, name: &str, content: &str| -> anyhow::Result<()> {
+        zip.start_file(name, options)?;
+        zip.write_all(content.as_bytes())?;
+        Ok(())
+    };
+
+    add_text(&mut zip, "system.txt", &system_report(session))?;
+    add_text(
+        &mut zip,
+        "config.toml",
+        &sanitized_config(config).to_toml()?,
+    )?;
+    add_text(
+        &mut zip,
+        "audio_devices.txt",
+        &player::supported_configs_report(),
+    )?;
+    add_text(&mut zip, "network_adapters.txt", &network_adapters_report())?;
+    add_text(
+        &mut zip,
+        "connection_errors.txt",
+        &session.connection_errors.join("\n"),
+    )?;
+    add_text(&mut zip, "pipeline_stats.txt", &PIPELINE_STATS.report())?;
+
+    for log_file in logging::log_files(log_dir) {
+        let Some(name) = log_file.file_name() else {
+            continue;
+        };
+        zip.start_file(format!("logs/{}", name.to_string_lossy()), options)?;
+        io::copy(&mut File::open(&log_file)?, &mut zip)?;
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::config::UsbDeviceId;
+
+    #[test]
+    fn public_addresses_are_removed() {
+        let mut config = Config {
+            ip: Some(Ipv4Addr::new(192, 168, 1, 79).into()),
+            ..Default::default()
+        };
+        assert_eq!(sanitized_config(&config).ip, config.ip);
+
+        config.ip = Some(Ipv4Addr::new(8, 8, 8, 8).into());
+        assert_eq!(sanitized_config(&config).ip, None);
+    }
+
+    #[test]
+    fn serials_are_hashed() {
+        let mut config = Config {
+            adb_serial: Some("R58M12345".into()),
+            usb_device: Some(UsbDeviceId {
+                vendor_id: 0x04e8,
+                product_id: 0x6860,
+                serial: Some("R58M12345".into()),
+                name: Some("Galaxy S10".into()),
+            }),
+            ..Default::default()
+        };
+        config.save_profile("desk", true, false);
+        let sanitized = sanitized_config(&config);
+
+        let adb_serial = sanitized.adb_serial.unwrap();
+        assert!(!adb_serial.contains("R58M12345"), "{adb_serial}");
+        let usb_device = sanitized.usb_device.unwrap();
+        // the same phone in both modes
+        assert_eq!(usb_device.serial, Some(adb_serial.clone()));
+        assert_eq!(usb_device.name.as_deref(), Some("Galaxy S10"));
+
+        let connection = sanitized.profiles[0].connection.as_ref().unwrap();
+        assert_eq!(connection.adb_serial, Some(adb_serial.clone()));
+        assert_eq!(
+            connection.usb_device.as_ref().unwrap().serial,
+            Some(adb_serial)
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! Log files, rotated so the log of a previous session survives a restart.
+//! `AndroidMic.log` is the current file, `AndroidMic.1.log` the previous one, and so on.
+
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{Local, NaiveDate};
+
+use crate::{config::LogLevel, utils::APP};
+
+/// Current file included
+pub const MAX_LOG_FILES: usize = 5;
+const MAX_LOG_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+pub fn log_file_path(dir: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        dir.join(format!("{APP}.log"))
+    } else {
+        dir.join(format!("{APP}.{index}.log"))
+    }
+}
+
+/// Existing log files, newest first
+pub fn log_files(dir: &Path) -> Vec<PathBuf> {
+    (0..MAX_LOG_FILES)
+        .map(|index| log_file_path(dir, index))
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+/// Shift every file by one, the oldest is deleted
+fn rotate(dir: &Path) -> io::Result<()> {
+    let oldest = log_file_path(dir, MAX_LOG_FILES - 1);
+    if oldest.exists() {
+        fs::remove_file(oldest)?;
+    }
+    for index in (0..MAX_LOG_FILES - 1).rev() {
+        let path = log_file_path(dir, index);
+        if path.exists() {
+            fs::rename(path, log_file_path(dir, index + 1))?;
+        }
+    }
+    Ok(())
+}
+
+/// Starts a new file at each session, when it gets too big, and every day
+pub struct RotatingFile {
+    dir: PathBuf,
+    /// Closed while rotating, Windows can't rename an open file
+    file: Option<File>,
+    size: u64,
+    opened_on: NaiveDate,
+}
+
+impl RotatingFile {
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        let mut file = Self {
+            dir: dir.to_path_buf(),
+            file: None,
+            size: 0,
+            opened_on: Local::now().date_naive(),
+        };
+        file.start_new_file()?;
+        Ok(file)
+    }
+
+    pub fn path(&self) -> PathBuf {
+        log_file_path(&self.dir, 0)
+    }
+
+    fn start_new_file(&mut self) -> io::Result<()> {
+        self.file = None;
+        rotate(&self.dir)?;
+        self.file = Some(File::create(self.path())?);
+        self.size = 0;
+        self.opened_on = Local::now().date_naive();
+        Ok(())
+    }
+
+    fn needs_rotation(&self) -> bool {
+        self.size >= MAX_LOG_FILE_SIZE || Local::now().date_naive() != self.opened_on
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.file.is_none() || self.needs_rotation() {
+            self.start_new_file()?;
+        }
+        let file = self.file.as_mut().expect("log file is open");
+        let written = file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+struct DualWriter {
+    file: Option<RotatingFile>,
+}
+
+impl Write for DualWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(file) = &mut self.file {
+            file.write_all(buf)?;
+        }
+        io::stdout().write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(file) = &mut self.file {
+            file.flush()?;
+        }
+        io::stdout().flush()
+    }
+}
+
+/// Log to stdout, and to `file` if any.
+/// Everything passes the filter, unless `RUST_LOG` is set: the level is chosen with [`set_level`].
+pub fn init(file: Option<RotatingFile>) {
+    env_logger::Builder::new()
+        .format(|buf, record| {
+            writeln!(
+                buf,
+                "[{} {} {}] {}",
+                Local::now().format("%Y-%m-%dT%H:%M:%S"),
+                record.level(),
+                record.target(),
+                record.args()
+            )
+        })
+        .target(env_logger::Target::Pipe(Box::new(DualWriter { file })))
+        .filter_level(log::LevelFilter::Trace)
+        .parse_default_env()
+        .init();
+    set_level(LogLevel::default());
+}
+
+/// Can be called at any time, ignored when `RUST_LOG` is set
+pub fn set_level(level: LogLevel) {
+    if std::env::var_os("RUST_LOG").is_some() {
+        debug!("RUST_LOG is set, log level {level} ignored");
+        return;
+    }
+    log::set_max_level(level.to_level_filter());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_keeps_the_last_files() {
+        let dir = std::env::temp_dir().join(format!("android-mic-log-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        for session in 0..MAX_LOG_FILES + 2 {
+            let mut file = RotatingFile::open(&dir).unwrap();
+            write!(file, "session {session}").unwrap();
+        }
+
+        let files = log_files(&dir);
+        assert_eq!(files.len(), MAX_LOG_FILES);
+        assert_eq!(files[0], log_file_path(&dir, 0));
+        // newest first
+        for (index, path) in files.iter().enumerate() {
+            let content = fs::read_to_string(path).unwrap();
+            assert_eq!(content, format!("session {}", MAX_LOG_FILES + 1 - index));
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,10 +1,8 @@
 // to not launch a console on Windows, only in release because it blocks all logs
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use android_mic::{localize, single_instance};
-use chrono::Local;
-use std::io::Write;
-use std::{fs::File, path::Path};
+use android_mic::{audio::player, cli, localize, logging, single_instance};
+use std::path::Path;
 
 use android_mic::config::{Args, Config};
 use android_mic::ui::app::run_ui;
@@ -19,23 +17,6 @@ use android_mic::ui::app::Flags;
 #[macro_use]
 extern crate log;
 
-struct DualWriter {
-    file: Box<File>,
-}
-
-impl Write for DualWriter {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        let bytes_written = self.file.write(buf)?;
-        std::io::stdout().write_all(buf)?;
-        Ok(bytes_written)
-    }
-
-    fn flush(&mut self) -> std::io::Result<()> {
-        self.file.flush()?;
-        std::io::stdout().flush()
-    }
-}
-
 fn main() {
     let _ = fix_path_env::fix();
     utils::setup_wgpu();
@@ -48,27 +29,38 @@ fn main() {
         project_dirs.cache_dir()
     };
     std::fs::create_dir_all(log_path).expect("Failed to create log directory");
-    let log_file_path = log_path.join(format!("{}.log", APP));
-
-    // setup log file
-    let target = Box::new(File::create(log_file_path.clone()).expect("Can't create log file"));
-    env_logger::Builder::new()
-        .format(|buf, record| {
-            writeln!(
-                buf,
-                "[{} {} {}] {}",
-                Local::now().format("%Y-%m-%dT%H:%M:%S"),
-                record.level(),
-                record.target(),
-                record.args()
-            )
-        })
-        .target(env_logger::Target::Pipe(Box::new(DualWriter {
-            file: target,
-        })))
-        .filter_level(log::LevelFilter::Warn)
-        .parse_default_env()
-        .init();
+
+    let args = Args::parse();
+
+    let config_path = if cfg!(debug_assertions) {
+        Path::new("config")
+    } else {
+        project_dirs.config_dir()
+    };
+    std::fs::create_dir_all(config_path).expect("Failed to create config directory");
+    let config_file_path = config_path.join(format!("{APP}.toml"));
+
+    if args.show_supported_audio_config {
+        print!("{}", player::supported_configs_report());
+        return;
+    }
+
+    if let Some(command) = args.command {
+        if let Err(e) = cli::run(command, &config_file_path, log_path) {
+            eprintln!("{e:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // invalid values fail before the window opens, like with a command
+    let mut checked_config = Config::default();
+    for (key, value) in &args.set {
+        if let Err(e) = checked_config.set_key(key, value) {
+            eprintln!("--set {key}={value}: {e:#}");
+            std::process::exit(1);
+        }
+    }
 
     // ensure single instance
     let instance_lock_path = if cfg!(debug_assertions) {
@@ -78,17 +70,35 @@ fn main() {
     };
     let mut app_lock = LockFile::open(&instance_lock_path).expect("Failed to open app lock file");
     if !app_lock.try_lock_with_pid().unwrap_or(false) {
+        // the log files belong to the running instance
+        logging::init(None);
         info!(
             "Another instance is already running. PID can be found in {:?}",
             instance_lock_path
         );
 
-        if let Err(e) = single_instance::send_event(single_instance::IpcEvent::Show) {
-            error!("can't send ipc event {e}");
+        let mut events = Vec::new();
+        if let Some(profile) = args.profile {
+            events.push(single_instance::IpcEvent::SwitchProfile(profile));
+        }
+        if let Some(log_level) = args.log_level {
+            events.push(single_instance::IpcEvent::SetLogLevel(log_level));
+        }
+        if events.is_empty() {
+            events.push(single_instance::IpcEvent::Show);
+        }
+        for event in events {
+            if let Err(e) = single_instance::send_event(event) {
+                error!("can't send ipc event {e}");
+            }
         }
         return;
     }
 
+    let log_file = logging::RotatingFile::open(log_path).expect("Can't create log file");
+    let log_file_path = log_file.path();
+    logging::init(Some(log_file));
+
     // generated from https://patorjk.com/software/taag/#p=display&h=2&f=Doom&t=AndroidMic
     info!(
         r"
@@ -101,19 +111,17 @@ fn main() {
     "
     );
 
-    let config_path = if cfg!(debug_assertions) {
-        Path::new("config")
-    } else {
-        project_dirs.config_dir()
-    };
-    std::fs::create_dir_all(config_path).expect("Failed to create config directory");
-    let config_file_path = config_path.join(format!("{APP}.toml"));
-
     let mut config: ConfigManager<Config> = ConfigManager::new(config_file_path.clone());
-
-    let args = Args::parse();
+    config.update_without_write(|config| config.migrate());
 
     config.update_without_write(|config| {
+        // explicit flags below override the profile
+        if let Some(profile) = &args.profile
+            && !config.apply_profile(profile)
+        {
+            error!("profile {profile} doesn't exist");
+        }
+
         if let Some(ip) = args.ip {
             config.ip.replace(ip);
         }
@@ -126,6 +134,10 @@ fn main() {
             config.device_id.replace(output_device);
         }
 
+        if let Some(adb_serial) = args.adb_serial {
+            config.adb_serial.replace(adb_serial);
+        }
+
         if let Some(audio_format) = args.audio_format {
             config.audio_format = audio_format;
         }
@@ -136,8 +148,21 @@ fn main() {
         if let Some(sample_rate) = args.sample_rate {
             config.sample_rate = sample_rate;
         }
+
+        if let Some(log_level) = args.log_level {
+            config.log_level = log_level;
+        }
+
+        // checked above
+        for (key, value) in &args.set {
+            if let Err(e) = config.set_key(key, value) {
+                error!("--set {key}={value}: {e}");
+            }
+        }
     });
 
+    logging::set_level(config.data().log_level);
+
     localize::localize();
 
     let flags = Flags {
@@ -0,0 +1,235 @@
+//! Named snapshots of the settings, to switch between setups in one click.
+//! A profile can hold the connection settings, the processing settings, or both.
+
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{
+    AudioFormat, ChannelCount, Config, ConnectionMode, DenoiseKind, DitherMode, EffectChainEntry,
+    EqBand, ResamplerQuality, SampleRate, UsbDeviceId,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    /// `None` when switching to this profile keeps the current connection settings
+    pub connection: Option<ConnectionSettings>,
+    /// `None` when switching to this profile keeps the current processing settings
+    pub dsp: Option<DspSettings>,
+}
+
+/// Where the audio comes from and where it goes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionSettings {
+    pub connection_mode: ConnectionMode,
+    pub ip: Option<IpAddr>,
+    pub port: u16,
+    pub device_id: Option<String>,
+    pub audio_format: AudioFormat,
+    pub channel_count: ChannelCount,
+    pub sample_rate: SampleRate,
+    // missing from profiles saved by older versions
+    #[serde(default)]
+    pub adb_serial: Option<String>,
+    #[serde(default)]
+    pub adb_launch_app: bool,
+    #[serde(default)]
+    pub usb_device: Option<UsbDeviceId>,
+}
+
+impl ConnectionSettings {
+    fn from_config(config: &Config) -> Self {
+        Self {
+            connection_mode: config.connection_mode,
+            ip: config.ip,
+            port: config.port,
+            device_id: config.device_id.clone(),
+            audio_format: config.audio_format.clone(),
+            channel_count: config.channel_count.clone(),
+            sample_rate: config.sample_rate,
+            adb_serial: config.adb_serial.clone(),
+            adb_launch_app: config.adb_launch_app,
+            usb_device: config.usb_device.clone(),
+        }
+    }
+
+    fn apply(&self, config: &mut Config) {
+        config.connection_mode = self.connection_mode;
+        config.ip = self.ip;
+        config.port = self.port;
+        config.device_id = self.device_id.clone();
+        config.audio_format = self.audio_format.clone();
+        config.channel_count = self.channel_count.clone();
+        config.sample_rate = self.sample_rate;
+        config.adb_serial = self.adb_serial.clone();
+        config.adb_launch_app = self.adb_launch_app;
+        config.usb_device = self.usb_device.clone();
+    }
+}
+
+/// Everything the audio pipeline does to the samples
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DspSettings {
+    pub resampler_quality: ResamplerQuality,
+    pub dither: DitherMode,
+    pub denoise: bool,
+    pub denoise_kind: DenoiseKind,
+    pub speex_noise_suppress: i32,
+    pub speex_vad_enabled: bool,
+    pub speex_vad_threshold: u32,
+    pub speex_agc_enabled: bool,
+    pub speex_agc_target: u32,
+    pub speex_dereverb_enabled: bool,
+    pub speex_dereverb_level: f32,
+    pub amplify: bool,
+    pub amplify_value: f32,
+    pub post_effects: Vec<EffectChainEntry>,
+    pub equalizer: bool,
+    pub equalizer_bands: Vec<EqBand>,
+}
+
+impl DspSettings {
+    fn from_config(config: &Config) -> Self {
+        Self {
+            resampler_quality: config.resampler_quality,
+            dither: config.dither,
+            denoise: config.denoise,
+            denoise_kind: config.denoise_kind.clone(),
+            speex_noise_suppress: config.speex_noise_suppress,
+            speex_vad_enabled: config.speex_vad_enabled,
+            speex_vad_threshold: config.speex_vad_threshold,
+            speex_agc_enabled: config.speex_agc_enabled,
+            speex_agc_target: config.speex_agc_target,
+            speex_dereverb_enabled: config.speex_dereverb_enabled,
+            speex_dereverb_level: config.speex_dereverb_level,
+            amplify: config.amplify,
+            amplify_value: config.amplify_value,
+            post_effects: config.post_effects.clone(),
+            equalizer: config.equalizer,
+            equalizer_bands: config.equalizer_bands.clone(),
+        }
+    }
+
+    fn apply(&self, config: &mut Config) {
+        config.resampler_quality = self.resampler_quality;
+        config.dither = self.dither;
+        config.denoise = self.denoise;
+        config.denoise_kind = self.denoise_kind.clone();
+        config.speex_noise_suppress = self.speex_noise_suppress;
+        config.speex_vad_enabled = self.speex_vad_enabled;
+        config.speex_vad_threshold = self.speex_vad_threshold;
+        config.speex_agc_enabled = self.speex_agc_enabled;
+        config.speex_agc_target = self.speex_agc_target;
+        config.speex_dereverb_enabled = self.speex_dereverb_enabled;
+        config.speex_dereverb_level = self.speex_dereverb_level;
+        config.amplify = self.amplify;
+        config.amplify_value = self.amplify_value;
+        config.post_effects = self.post_effects.clone();
+        config.equalizer = self.equalizer;
+        config.equalizer_bands = self.equalizer_bands.clone();
+    }
+}
+
+impl Config {
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    pub fn profile_names(&self) -> Vec<String> {
+        self.profiles
+            .iter()
+            .map(|profile| profile.name.clone())
+            .collect()
+    }
+
+    /// Save the current settings under `name`, replacing a profile with the same name
+    pub fn save_profile(&mut self, name: &str, connection: bool, dsp: bool) {
+        let profile = Profile {
+            name: name.to_string(),
+            connection: connection.then(|| ConnectionSettings::from_config(self)),
+            dsp: dsp.then(|| DspSettings::from_config(self)),
+        };
+
+        match self.profiles.iter_mut().find(|p| p.name == name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+        self.active_profile = Some(name.to_string());
+    }
+
+    /// Returns `false` if there is no profile with this name
+    pub fn apply_profile(&mut self, name: &str) -> bool {
+        let Some(profile) = self.profile(name).cloned() else {
+            return false;
+        };
+
+        if let Some(connection) = &profile.connection {
+            connection.apply(self);
+        }
+        if let Some(dsp) = &profile.dsp {
+            dsp.apply(self);
+        }
+        self.active_profile = Some(profile.name);
+        true
+    }
+
+    pub fn delete_profile(&mut self, name: &str) {
+        self.profiles.retain(|profile| profile.name != name);
+        if self.active_profile.as_deref() == Some(name) {
+            self.active_profile = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dsp_only_profile_keeps_connection() {
+        let mut config = Config {
+            denoise: true,
+            port: 1000,
+            ..Default::default()
+        };
+        config.save_profile("podcast", false, true);
+
+        config.denoise = false;
+        config.port = 2000;
+        assert!(config.apply_profile("podcast"));
+
+        assert!(config.denoise);
+        assert_eq!(config.port, 2000);
+        assert_eq!(config.active_profile.as_deref(), Some("podcast"));
+        assert!(!config.apply_profile("meeting"));
+    }
+
+    #[test]
+    fn connection_profile_selects_the_phone() {
+        let usb_device = UsbDeviceId {
+            vendor_id: 0x18d1,
+            product_id: 0x4ee1,
+            serial: Some("0123456789ABCDEF".into()),
+            name: Some("Pixel 7".into()),
+        };
+        let mut config = Config {
+            adb_serial: Some("0123456789ABCDEF".into()),
+            adb_launch_app: true,
+            usb_device: Some(usb_device.clone()),
+            ..Default::default()
+        };
+        config.save_profile("desk", true, false);
+
+        // through the config file
+        let mut config: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        config.adb_serial = Some("R58M12345".into());
+        config.adb_launch_app = false;
+        config.usb_device = None;
+        assert!(config.apply_profile("desk"));
+
+        assert_eq!(config.adb_serial.as_deref(), Some("0123456789ABCDEF"));
+        assert!(config.adb_launch_app);
+        assert_eq!(config.usb_device, Some(usb_device));
+    }
+}
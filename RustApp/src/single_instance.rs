@@ -1,35 +1,71 @@
-use byteorder::WriteBytesExt;
+use std::io::Write;
+
+use byteorder::{LittleEndian, WriteBytesExt};
 use futures::stream::Stream;
 use interprocess::local_socket::traits::Stream as InterprocessStreamTrait;
 use interprocess::local_socket::traits::tokio::Listener as TokioListener;
 use interprocess::local_socket::{GenericNamespaced, ListenerOptions, ToNsName};
 use interprocess::local_socket::{Name, Stream as InterprocessStream};
 
-use tokio::io::AsyncReadExt;
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 use async_stream::stream;
 
+use crate::config::LogLevel;
+
 #[derive(Debug, Clone)]
 pub enum IpcEvent {
     Show,
+    SwitchProfile(String),
+    SetLogLevel(LogLevel),
 }
 
-impl TryFrom<u8> for IpcEvent {
-    type Error = ();
+const IPC_SHOW: u8 = 0;
+/// Followed by the name: length as u16 le, then utf8 bytes
+const IPC_SWITCH_PROFILE: u8 = 1;
+/// Followed by the index of the level in `LogLevel::VALUES`, as u8
+const IPC_SET_LOG_LEVEL: u8 = 2;
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value {
-            0 => Ok(IpcEvent::Show),
-            _ => Err(()),
+impl IpcEvent {
+    /// `Ok(None)` for an unknown event, the rest of the message is unreadable
+    async fn read(client: &mut (impl AsyncRead + Unpin)) -> std::io::Result<Option<Self>> {
+        match client.read_u8().await? {
+            IPC_SHOW => Ok(Some(IpcEvent::Show)),
+            IPC_SWITCH_PROFILE => {
+                let len = client.read_u16_le().await?;
+                let mut name = vec![0; len as usize];
+                client.read_exact(&mut name).await?;
+                Ok(String::from_utf8(name).ok().map(IpcEvent::SwitchProfile))
+            }
+            IPC_SET_LOG_LEVEL => {
+                let index = client.read_u8().await?;
+                Ok(LogLevel::VALUES
+                    .get(index as usize)
+                    .copied()
+                    .map(IpcEvent::SetLogLevel))
+            }
+            _ => Ok(None),
         }
     }
-}
 
-impl From<IpcEvent> for u8 {
-    fn from(value: IpcEvent) -> Self {
-        match value {
-            IpcEvent::Show => 0,
+    fn write(&self, stream: &mut impl Write) -> anyhow::Result<()> {
+        match self {
+            IpcEvent::Show => stream.write_u8(IPC_SHOW)?,
+            IpcEvent::SwitchProfile(name) => {
+                stream.write_u8(IPC_SWITCH_PROFILE)?;
+                stream.write_u16::<LittleEndian>(name.len().try_into()?)?;
+                stream.write_all(name.as_bytes())?;
+            }
+            IpcEvent::SetLogLevel(level) => {
+                let index = LogLevel::VALUES
+                    .iter()
+                    .position(|l| l == level)
+                    .unwrap_or(0);
+                stream.write_u8(IPC_SET_LOG_LEVEL)?;
+                stream.write_u8(index as u8)?;
+            }
         }
+        Ok(())
     }
 }
 
@@ -50,13 +86,12 @@ pub fn stream() -> anyhow::Result<impl Stream<Item = IpcEvent>> {
             match listener.accept().await {
                 Ok(mut client) => {
                     loop {
-                        match client.read_u8().await {
-                            Ok(byte) => match byte.try_into() {
-                                Ok(event) => yield event,
-                                Err(_) => {
-                                    error!("can't parse ipc event");
-                                }
-                            },
+                        match IpcEvent::read(&mut client).await {
+                            Ok(Some(event)) => yield event,
+                            Ok(None) => {
+                                error!("can't parse ipc event");
+                                break;
+                            }
                             Err(e) => {
                                 if e.kind() == std::io::ErrorKind::UnexpectedEof {
                                 } else {
@@ -83,7 +118,7 @@ pub fn send_event(event: IpcEvent) -> anyhow::Result<()> {
 
     let mut stream = InterprocessStream::connect(name)?;
 
-    stream.write_u8(event.into())?;
+    event.write(&mut stream)?;
 
     Ok(())
 }
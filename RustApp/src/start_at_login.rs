@@ -1,20 +1,22 @@
+//! Each call runs in a `Task`, the UI updates the config with the result.
+//! Errors are strings, to be sent in messages.
+
 #[cfg(target_os = "windows")]
-pub use windows::start_at_login;
+pub use windows::set_start_at_login;
 
 #[cfg(target_os = "linux")]
-pub use linux::start_at_login;
+pub use linux::{move_start_at_login, set_start_at_login};
 
 #[cfg(target_os = "macos")]
-pub use macos::start_at_login;
+pub use macos::set_start_at_login;
 
 #[cfg(target_os = "windows")]
 mod windows {
     use std::{env, fs, path::Path};
 
     use directories::BaseDirs;
-    use zconf::ConfigManager;
 
-    use crate::config::Config;
+    use crate::config::AutostartMethod;
 
     fn create_shortcut(lnk: &Path) -> anyhow::Result<()> {
         let target = env::current_exe()?;
@@ -32,44 +34,304 @@ mod windows {
         Ok(())
     }
 
-    pub fn start_at_login(start_at_login: bool, config: &mut ConfigManager<Config>) {
+    /// The method only matters on Linux
+    pub async fn set_start_at_login(
+        _method: AutostartMethod,
+        start_at_login: bool,
+    ) -> Result<(), String> {
         let dirs = BaseDirs::new().unwrap();
         let file_path = dirs
             .data_dir()
             .join("Microsoft/Windows/Start Menu/Programs/Startup/AndroidMic.lnk");
 
         if start_at_login {
-            if let Err(e) = create_shortcut(&file_path) {
-                error!("can't create shortcut: {e}");
-                config.update(|s| s.start_at_login = false);
-            } else {
-                config.update(|s| s.start_at_login = true);
-            }
+            create_shortcut(&file_path).map_err(|e| format!("can't create shortcut: {e}"))
         } else {
-            if let Err(e) = remove_shortcut(&file_path) {
-                error!("can't remove shortcut: {e}");
-            }
-            config.update(|s| s.start_at_login = false);
+            remove_shortcut(&file_path).map_err(|e| format!("can't remove shortcut: {e}"))
         }
     }
 }
 
 #[cfg(target_os = "linux")]
 mod linux {
-    use crate::config::Config;
-    use zconf::ConfigManager;
+    use std::{
+        collections::HashMap,
+        env, fs,
+        path::{Path, PathBuf},
+        process,
+        time::Duration,
+    };
 
-    pub fn start_at_login(_start_at_login: bool, _config: &mut ConfigManager<Config>) {
-        todo!()
+    use anyhow::{Context, bail};
+    use directories::BaseDirs;
+    use futures::StreamExt;
+    use tokio::process::Command;
+    use zbus::{
+        Connection, Proxy,
+        zvariant::{OwnedObjectPath, OwnedValue, Value},
+    };
+
+    use crate::{config::AutostartMethod, fl, utils::APP_ID};
+
+    const LAUNCH_ARG: &str = "--launched-automatically";
+    const SYSTEMD_UNIT: &str = "android-mic.service";
+    /// The portal may wait for the user to answer a dialog
+    const PORTAL_TIMEOUT: Duration = Duration::from_secs(60);
+
+    fn executable() -> anyhow::Result<PathBuf> {
+        // the executable of an AppImage lives in a temporary mount point
+        match env::var_os("APPIMAGE") {
+            Some(app_image) => Ok(app_image.into()),
+            None => Ok(env::current_exe()?),
+        }
+    }
+
+    /// Quote an argument of the `Exec` key, following the desktop entry spec.
+    /// The result is also escaped as a string value, so backslashes are doubled.
+    fn desktop_exec_arg(arg: &str) -> String {
+        const RESERVED: &str = " \t\n\"'\\><~|&;$*?#()`";
+
+        let quoted = if arg.chars().any(|c| RESERVED.contains(c)) {
+            let mut quoted = String::from("\"");
+            for c in arg.chars() {
+                if matches!(c, '"' | '`' | '$' | '\\') {
+                    quoted.push('\\');
+                }
+                quoted.push(c);
+            }
+            quoted.push('"');
+            quoted
+        } else {
+            arg.to_string()
+        };
+
+        quoted.replace('\\', "\\\\").replace('%', "%%")
+    }
+
+    /// Quote an argument of `ExecStart`, `%` and `$` are expanded by systemd
+    fn systemd_exec_arg(arg: &str) -> String {
+        let escaped = arg
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('%', "%%")
+            .replace('$', "$$");
+        format!("\"{escaped}\"")
+    }
+
+    fn config_dir() -> anyhow::Result<PathBuf> {
+        // honors $XDG_CONFIG_HOME
+        Ok(BaseDirs::new()
+            .context("no home directory")?
+            .config_dir()
+            .to_path_buf())
+    }
+
+    fn write_file(path: &Path, content: &str) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)?;
+        Ok(())
+    }
+
+    fn remove_file(path: &Path) -> anyhow::Result<()> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn set_desktop_entry(enable: bool) -> anyhow::Result<()> {
+        let path = config_dir()?
+            .join("autostart")
+            .join(format!("{APP_ID}.desktop"));
+
+        if !enable {
+            return remove_file(&path);
+        }
+
+        let exec = desktop_exec_arg(&executable()?.to_string_lossy());
+        let content = format!(
+            "[Desktop Entry]\n\
+             Type=Application\n\
+             Name=AndroidMic\n\
+             Exec={exec} {LAUNCH_ARG}\n\
+             Icon={APP_ID}\n\
+             Terminal=false\n\
+             X-GNOME-Autostart-enabled=true\n"
+        );
+        write_file(&path, &content)
+    }
+
+    async fn systemctl(args: &[&str]) -> anyhow::Result<()> {
+        let status = Command::new("systemctl")
+            .arg("--user")
+            .args(args)
+            .status()
+            .await
+            .context("can't run systemctl")?;
+
+        if !status.success() {
+            bail!("systemctl --user {} failed: {status}", args.join(" "));
+        }
+        Ok(())
+    }
+
+    async fn set_systemd_unit(enable: bool) -> anyhow::Result<()> {
+        let path = config_dir()?.join("systemd/user").join(SYSTEMD_UNIT);
+
+        if !enable {
+            if path.exists() {
+                systemctl(&["disable", SYSTEMD_UNIT]).await?;
+            }
+            remove_file(&path)?;
+            return systemctl(&["daemon-reload"]).await;
+        }
+
+        let exec = systemd_exec_arg(&executable()?.to_string_lossy());
+        let content = format!(
+            "[Unit]\n\
+             Description=AndroidMic\n\
+             After=pipewire.service pulseaudio.service\n\
+             \n\
+             [Service]\n\
+             ExecStart={exec} {LAUNCH_ARG}\n\
+             Restart=on-failure\n\
+             \n\
+             [Install]\n\
+             WantedBy=default.target\n"
+        );
+        write_file(&path, &content)?;
+        systemctl(&["daemon-reload"]).await?;
+        systemctl(&["enable", SYSTEMD_UNIT]).await
+    }
+
+    const PORTAL_NAME: &str = "org.freedesktop.portal.Desktop";
+    const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+
+    /// Inside Flatpak, the host autostart folder is not writable:
+    /// the Background portal creates the entry for us.
+    /// https://flatpak.github.io/xdg-desktop-portal/docs/doc-org.freedesktop.portal.Background.html
+    async fn request_background(enable: bool) -> anyhow::Result<()> {
+        let connection = Connection::session().await?;
+
+        // subscribe to the response before the call, so it can't be missed
+        let token = format!("android_mic_{}", process::id());
+        let sender = connection
+            .unique_name()
+            .context("no unique name on the session bus")?
+            .as_str()
+            .trim_start_matches(':')
+            .replace('.', "_");
+        let request_path = format!("{PORTAL_PATH}/request/{sender}/{token}");
+        let request = Proxy::new(
+            &connection,
+            PORTAL_NAME,
+            request_path.as_str(),
+            "org.freedesktop.portal.Request",
+        )
+        .await?;
+        let mut responses = request.receive_signal("Response").await?;
+
+        let mut options: HashMap<&str, Value> = HashMap::new();
+        options.insert("handle_token", Value::from(token.as_str()));
+        options.insert("reason", Value::from(fl!("autostart_reason")));
+        options.insert("autostart", Value::from(enable));
+        options.insert("commandline", Value::from(vec!["android-mic", LAUNCH_ARG]));
+
+        let background = Proxy::new(
+            &connection,
+            PORTAL_NAME,
+            PORTAL_PATH,
+            "org.freedesktop.portal.Background",
+        )
+        .await?;
+        let _: OwnedObjectPath = background.call("RequestBackground", &("", options)).await?;
+
+        // the portal may ask the user first
+        let response = tokio::time::timeout(PORTAL_TIMEOUT, responses.next())
+            .await
+            .context("the portal didn't answer in time")?
+            .context("no response from the portal")?;
+        let (code, results): (u32, HashMap<String, OwnedValue>) = response.body().deserialize()?;
+        if code != 0 {
+            bail!("the request was denied by the portal");
+        }
+
+        let autostart = results
+            .get("autostart")
+            .and_then(|value| bool::try_from(value).ok())
+            .unwrap_or(false);
+        if autostart != enable {
+            bail!("the portal didn't change the autostart setting");
+        }
+        Ok(())
+    }
+
+    async fn set_autostart(method: AutostartMethod, enable: bool) -> anyhow::Result<()> {
+        if cfg!(ANDROID_MIC_FORMAT = "flatpak") {
+            return request_background(enable).await;
+        }
+
+        match method {
+            AutostartMethod::DesktopEntry => set_desktop_entry(enable),
+            AutostartMethod::SystemdUnit => set_systemd_unit(enable).await,
+        }
+    }
+
+    pub async fn set_start_at_login(method: AutostartMethod, enable: bool) -> Result<(), String> {
+        set_autostart(method, enable).await.map_err(|e| {
+            if enable {
+                format!("can't enable start at login: {e:#}")
+            } else {
+                format!("can't disable start at login: {e:#}")
+            }
+        })
+    }
+
+    /// Move the autostart entry to the new method, when start at login is enabled
+    pub async fn move_start_at_login(
+        from: AutostartMethod,
+        to: AutostartMethod,
+    ) -> Result<(), String> {
+        if let Err(e) = set_start_at_login(from, false).await {
+            error!("{e}");
+        }
+        set_start_at_login(to, true).await
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn exec_quoting() {
+            assert_eq!(
+                desktop_exec_arg("/usr/bin/android-mic"),
+                "/usr/bin/android-mic"
+            );
+            assert_eq!(
+                desktop_exec_arg("/home/me/My Apps/android-mic"),
+                "\"/home/me/My Apps/android-mic\""
+            );
+            assert_eq!(desktop_exec_arg("/opt/$x"), "\"/opt/\\\\$x\"");
+            assert_eq!(desktop_exec_arg("/opt/100%"), "/opt/100%%");
+            assert_eq!(
+                systemd_exec_arg("/opt/my app/100%"),
+                "\"/opt/my app/100%%\""
+            );
+        }
     }
 }
 
 #[cfg(target_os = "macos")]
 mod macos {
-    use crate::config::Config;
-    use zconf::ConfigManager;
+    use crate::config::AutostartMethod;
 
-    pub fn start_at_login(_start_at_login: bool, _config: &mut ConfigManager<Config>) {
+    pub async fn set_start_at_login(
+        _method: AutostartMethod,
+        _start_at_login: bool,
+    ) -> Result<(), String> {
         todo!()
     }
 }
@@ -0,0 +1,66 @@
+//! Calls to the adb binary, used when the adb server is not running.
+//! The binary starts the server, later calls go through [`super::protocol`].
+
+use tokio::process::Command;
+
+use super::{AdbDevice, parse_devices};
+use crate::streamer::ConnectError;
+
+pub async fn devices() -> Result<Vec<AdbDevice>, ConnectError> {
+    let mut cmd = Command::new("adb");
+    cmd.arg("devices").arg("-l");
+
+    Ok(parse_devices(&exec_cmd(cmd).await?))
+}
+
+pub async fn reverse(serial: &str, port: u16) -> Result<(), ConnectError> {
+    let mut cmd = Command::new("adb");
+    cmd.arg("-s")
+        .arg(serial)
+        .arg("reverse")
+        .arg(format!("tcp:{}", port))
+        .arg(format!("tcp:{}", port));
+
+    exec_cmd(cmd).await?;
+
+    Ok(())
+}
+
+pub async fn remove_reverse(serial: &str, port: u16) -> Result<(), ConnectError> {
+    let mut cmd = Command::new("adb");
+    cmd.arg("-s")
+        .arg(serial)
+        .arg("reverse")
+        .arg("--remove")
+        .arg(format!("tcp:{}", port));
+
+    exec_cmd(cmd).await?;
+
+    Ok(())
+}
+
+pub async fn shell(serial: &str, command: &str) -> Result<String, ConnectError> {
+    let mut cmd = Command::new("adb");
+    cmd.arg("-s").arg(serial).arg("shell").arg(command);
+
+    exec_cmd(cmd).await
+}
+
+async fn exec_cmd(mut cmd: Command) -> Result<String, ConnectError> {
+    // https://learn.microsoft.com/en-us/windows/win32/procthread/process-creation-flags
+    #[cfg(target_os = "windows")]
+    cmd.creation_flags(0x08000000);
+
+    let status = cmd.output().await.map_err(ConnectError::CommandFailed)?;
+
+    if !status.status.success() {
+        let stderr = String::from_utf8_lossy(&status.stderr).to_string();
+
+        return Err(ConnectError::AdbStatusCommand {
+            code: status.status.code(),
+            stderr,
+        });
+    }
+    let stdout = String::from_utf8_lossy(&status.stdout).trim().to_string();
+    Ok(stdout)
+}
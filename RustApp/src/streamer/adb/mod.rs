@@ -0,0 +1,270 @@
+//! ADB, through the adb server when it runs, else with the adb binary
+
+use std::{fmt::Display, time::Duration};
+
+use tokio::{
+    sync::mpsc,
+    time::{MissedTickBehavior, interval},
+};
+
+use super::ConnectError;
+use protocol::AdbConnection;
+
+mod binary;
+mod protocol;
+
+/// Release, then nightly and debug builds
+const ANDROID_PACKAGES: [&str; 3] = [
+    "io.github.teamclouday.AndroidMic",
+    "io.github.teamclouday.AndroidMic.nightly",
+    "io.github.teamclouday.AndroidMic.debug",
+];
+const ANDROID_ACTIVITY: &str = "io.github.teamclouday.androidMic.ui.MainActivity";
+/// Handled by `MainActivity`, with the `mode` and `port` extras
+const START_STREAM_ACTION: &str = "START_STREAM_ACTION";
+
+/// When the server can't track devices for us
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A line of `adb devices -l`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdbDevice {
+    pub serial: String,
+    /// `device` when usable, else `unauthorized`, `offline`, ...
+    pub state: String,
+    pub model: Option<String>,
+}
+
+impl AdbDevice {
+    pub fn is_online(&self) -> bool {
+        self.state == "device"
+    }
+}
+
+impl Display for AdbDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.model {
+            Some(model) => write!(f, "{model} ({})", self.serial)?,
+            None => write!(f, "{}", self.serial)?,
+        }
+        if !self.is_online() {
+            write!(f, " - {}", self.state)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse the output of `adb devices -l`, for example:
+/// `0123456789ABCDEF       device usb:1-1 product:panther model:Pixel_7 device:panther transport_id:1`
+fn parse_devices(output: &str) -> Vec<AdbDevice> {
+    output
+        .lines()
+        .filter(|line| !line.starts_with("List of devices") && !line.starts_with('*'))
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let serial = parts.next()?.to_string();
+            let state = parts.next()?.to_string();
+            let model = parts
+                .find_map(|part| part.strip_prefix("model:"))
+                .map(|model| model.replace('_', " "));
+            Some(AdbDevice {
+                serial,
+                state,
+                model,
+            })
+        })
+        .collect()
+}
+
+/// The device to forward to: the configured one, or the only one plugged
+pub fn select_device<'a>(
+    devices: &'a [AdbDevice],
+    serial: Option<&str>,
+) -> Result<&'a AdbDevice, ConnectError> {
+    let mut online = devices.iter().filter(|device| device.is_online());
+
+    match serial {
+        Some(serial) => online
+            .find(|device| device.serial == serial)
+            .ok_or_else(|| ConnectError::AdbDeviceNotFound(serial.to_string())),
+        None => match (online.next(), online.next()) {
+            (Some(device), None) => Ok(device),
+            (Some(_), Some(_)) => Err(ConnectError::SeveralAdbDevices),
+            (None, _) => Err(ConnectError::NoAdbDevice),
+        },
+    }
+}
+
+/// Through the server when it runs, else with the binary
+pub async fn devices() -> Result<Vec<AdbDevice>, ConnectError> {
+    match AdbConnection::connect().await {
+        Ok(connection) => connection.devices().await,
+        Err(e) => {
+            debug!("adb server not reachable: {e}, using the adb binary");
+            binary::devices().await
+        }
+    }
+}
+
+pub async fn reverse(serial: &str, port: u16) -> Result<(), ConnectError> {
+    match AdbConnection::connect().await {
+        Ok(connection) => connection.reverse(serial, port).await,
+        Err(e) => {
+            debug!("adb server not reachable: {e}, using the adb binary");
+            binary::reverse(serial, port).await
+        }
+    }
+}
+
+pub async fn remove_reverse(serial: &str, port: u16) -> Result<(), ConnectError> {
+    match AdbConnection::connect().await {
+        Ok(connection) => connection.remove_reverse(serial, port).await,
+        Err(e) => {
+            debug!("adb server not reachable: {e}, using the adb binary");
+            binary::remove_reverse(serial, port).await
+        }
+    }
+}
+
+pub async fn shell(serial: &str, command: &str) -> Result<String, ConnectError> {
+    match AdbConnection::connect().await {
+        Ok(connection) => connection.shell(serial, command).await,
+        Err(e) => {
+            debug!("adb server not reachable: {e}, using the adb binary");
+            binary::shell(serial, command).await
+        }
+    }
+}
+
+/// The first of [`ANDROID_PACKAGES`] in the output of `pm list packages`
+fn installed_package(packages: &str) -> Option<&'static str> {
+    ANDROID_PACKAGES.into_iter().find(|package| {
+        packages
+            .lines()
+            .any(|line| line.trim().strip_prefix("package:") == Some(*package))
+    })
+}
+
+/// Opens the app on the phone, it starts streaming to `port` in ADB mode.
+/// An activity rather than the service: Android doesn't let a service
+/// started in the background use the microphone.
+pub async fn launch_app(serial: &str, port: u16) -> Result<(), ConnectError> {
+    let packages = shell(serial, &format!("pm list packages {}", ANDROID_PACKAGES[0])).await?;
+    let package = installed_package(&packages)
+        .ok_or_else(|| ConnectError::AndroidAppNotInstalled(serial.to_string()))?;
+
+    let output = shell(
+        serial,
+        &format!(
+            "am start -n {package}/{ANDROID_ACTIVITY} -a {START_STREAM_ACTION} --es mode ADB --ei port {port}"
+        ),
+    )
+    .await?;
+    // am exits with 0 even when it fails
+    if let Some(error) = output.lines().find(|line| line.starts_with("Error")) {
+        return Err(ConnectError::CantLaunchAndroidApp(error.to_string()));
+    }
+    info!("started {package} on device {serial}");
+    Ok(())
+}
+
+/// Sends the device list each time it changes, the current one first.
+/// Stops when the receiver is dropped.
+pub fn watch_devices() -> mpsc::Receiver<Vec<AdbDevice>> {
+    let (sender, receiver) = mpsc::channel(4);
+
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = sender.closed() => {}
+            _ = send_device_changes(&sender) => {}
+        }
+    });
+
+    receiver
+}
+
+async fn send_device_changes(sender: &mpsc::Sender<Vec<AdbDevice>>) {
+    match track_devices(sender).await {
+        Ok(()) => return,
+        Err(e) => warn!("can't track adb devices: {e}, polling instead"),
+    }
+
+    let mut poll = interval(DEVICE_POLL_INTERVAL);
+    poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut last = None;
+
+    loop {
+        poll.tick().await;
+        match devices().await {
+            Ok(devices) if last.as_ref() != Some(&devices) => {
+                last = Some(devices.clone());
+                if sender.send(devices).await.is_err() {
+                    return;
+                }
+            }
+            Ok(_) => {}
+            Err(e) => warn!("can't list adb devices: {e}"),
+        }
+    }
+}
+
+/// Returns `Ok` when the receiver is dropped
+async fn track_devices(sender: &mpsc::Sender<Vec<AdbDevice>>) -> Result<(), ConnectError> {
+    let mut tracker = AdbConnection::connect()
+        .await
+        .map_err(ConnectError::AdbServer)?
+        .track_devices()
+        .await?;
+
+    loop {
+        let devices = tracker.next().await?;
+        if sender.send(devices).await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEVICES: &str = "List of devices attached
+0123456789ABCDEF       device usb:1-1 product:panther model:Pixel_7 device:panther transport_id:1
+emulator-5554          unauthorized transport_id:2
+R58M12345              device product:beyond1 model:SM_G973F device:beyond1 transport_id:3";
+
+    #[test]
+    fn pick_the_configured_device() {
+        let devices = parse_devices(DEVICES);
+        assert_eq!(devices.len(), 3);
+        assert_eq!(devices[0].model.as_deref(), Some("Pixel 7"));
+        assert!(!devices[1].is_online());
+
+        let device = select_device(&devices, Some("R58M12345")).unwrap();
+        assert_eq!(device.serial, "R58M12345");
+
+        assert!(matches!(
+            select_device(&devices, None),
+            Err(ConnectError::SeveralAdbDevices)
+        ));
+        assert!(matches!(
+            select_device(&devices, Some("emulator-5554")),
+            Err(ConnectError::AdbDeviceNotFound(_))
+        ));
+        assert!(select_device(&devices[..2], None).is_ok());
+    }
+
+    #[test]
+    fn find_the_installed_app() {
+        let packages = "package:io.github.teamclouday.AndroidMic.debug\npackage:io.github.teamclouday.AndroidMic.nightly\n";
+        assert_eq!(
+            installed_package(packages),
+            Some("io.github.teamclouday.AndroidMic.nightly")
+        );
+        assert_eq!(
+            installed_package("package:io.github.teamclouday.AndroidMicro"),
+            None
+        );
+        assert_eq!(installed_package(""), None);
+    }
+}
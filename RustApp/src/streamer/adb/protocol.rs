@@ -0,0 +1,329 @@
+//! Client of the adb server, on TCP 5037.
+//! https://android.googlesource.com/platform/packages/modules/adb/+/refs/heads/main/SERVICES.TXT
+//!
+//! A request is its length as 4 hex digits, then the request itself.
+//! The server answers `OKAY`, or `FAIL` followed by a message prefixed the same way.
+//! The server closes the connection after most requests, so each one uses a new connection.
+
+use std::net::{Ipv4Addr, SocketAddr};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use super::{AdbDevice, parse_devices};
+use crate::streamer::ConnectError;
+
+const DEFAULT_SERVER_PORT: u16 = 5037;
+
+/// `ANDROID_ADB_SERVER_PORT` is also read by the adb binary
+pub fn server_address() -> SocketAddr {
+    let port = std::env::var("ANDROID_ADB_SERVER_PORT")
+        .ok()
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(DEFAULT_SERVER_PORT);
+    (Ipv4Addr::LOCALHOST, port).into()
+}
+
+pub struct AdbConnection {
+    stream: TcpStream,
+}
+
+impl AdbConnection {
+    /// Fails when the server is not running
+    pub async fn connect() -> std::io::Result<Self> {
+        Self::connect_to(server_address()).await
+    }
+
+    pub async fn connect_to(address: SocketAddr) -> std::io::Result<Self> {
+        Ok(Self {
+            stream: TcpStream::connect(address).await?,
+        })
+    }
+
+    async fn send(&mut self, request: &str) -> Result<(), ConnectError> {
+        let message = format!("{:04x}{request}", request.len());
+        self.stream
+            .write_all(message.as_bytes())
+            .await
+            .map_err(ConnectError::AdbServer)
+    }
+
+    async fn read_status(&mut self) -> Result<(), ConnectError> {
+        let mut status = [0u8; 4];
+        self.stream
+            .read_exact(&mut status)
+            .await
+            .map_err(ConnectError::AdbServer)?;
+
+        match &status {
+            b"OKAY" => Ok(()),
+            b"FAIL" => Err(ConnectError::AdbFailed(self.read_string().await?)),
+            _ => Err(ConnectError::AdbProtocol(format!(
+                "unexpected status {:?}",
+                String::from_utf8_lossy(&status)
+            ))),
+        }
+    }
+
+    async fn read_string(&mut self) -> Result<String, ConnectError> {
+        let mut len = [0u8; 4];
+        self.stream
+            .read_exact(&mut len)
+            .await
+            .map_err(ConnectError::AdbServer)?;
+        let len = std::str::from_utf8(&len)
+            .ok()
+            .and_then(|len| usize::from_str_radix(len, 16).ok())
+            .ok_or_else(|| ConnectError::AdbProtocol(format!("invalid length {len:?}")))?;
+
+        let mut content = vec![0u8; len];
+        self.stream
+            .read_exact(&mut content)
+            .await
+            .map_err(ConnectError::AdbServer)?;
+        Ok(String::from_utf8_lossy(&content).into_owned())
+    }
+
+    async fn request(&mut self, request: &str) -> Result<(), ConnectError> {
+        self.send(request).await?;
+        self.read_status().await
+    }
+
+    pub async fn devices(mut self) -> Result<Vec<AdbDevice>, ConnectError> {
+        self.request("host:devices-l").await?;
+        Ok(parse_devices(&self.read_string().await?))
+    }
+
+    /// Next requests of this connection go to the device
+    async fn transport(&mut self, serial: &str) -> Result<(), ConnectError> {
+        self.request(&format!("host:transport:{serial}")).await
+    }
+
+    /// Forward connections to `port` on the device, to `port` on this computer
+    pub async fn reverse(mut self, serial: &str, port: u16) -> Result<(), ConnectError> {
+        self.transport(serial).await?;
+        // the first status accepts the request, the second is its result
+        self.request(&format!("reverse:forward:tcp:{port};tcp:{port}"))
+            .await?;
+        self.read_status().await
+    }
+
+    pub async fn remove_reverse(mut self, serial: &str, port: u16) -> Result<(), ConnectError> {
+        self.transport(serial).await?;
+        self.request(&format!("reverse:killforward:tcp:{port}"))
+            .await?;
+        self.read_status().await
+    }
+
+    /// Runs `command` on the device, returns what it printed
+    pub async fn shell(mut self, serial: &str, command: &str) -> Result<String, ConnectError> {
+        self.transport(serial).await?;
+        self.request(&format!("shell:{command}")).await?;
+
+        // the server closes the connection when the command ends
+        let mut output = Vec::new();
+        self.stream
+            .read_to_end(&mut output)
+            .await
+            .map_err(ConnectError::AdbServer)?;
+        Ok(String::from_utf8_lossy(&output).trim().to_string())
+    }
+
+    /// The connection stays open, the server sends the list each time it changes
+    pub async fn track_devices(mut self) -> Result<DeviceTracker, ConnectError> {
+        self.request("host:track-devices-l").await?;
+        Ok(DeviceTracker { connection: self })
+    }
+}
+
+pub struct DeviceTracker {
+    connection: AdbConnection,
+}
+
+impl DeviceTracker {
+    /// The current list first, then one per change. Not cancel safe.
+    pub async fn next(&mut self) -> Result<Vec<AdbDevice>, ConnectError> {
+        Ok(parse_devices(&self.connection.read_string().await?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::{io::AsyncRead, net::TcpListener, sync::Notify};
+
+    use super::*;
+
+    const PIXEL: &str = "0123456789ABCDEF       device usb:1-1 product:panther model:Pixel_7 device:panther transport_id:1\n";
+    const SAMSUNG: &str = "R58M12345              device product:beyond1 model:SM_G973F device:beyond1 transport_id:3\n";
+
+    /// Stands in for the adb server, with the two devices above
+    #[derive(Default)]
+    struct FakeServer {
+        reverse_ports: Mutex<Vec<(String, u16)>>,
+        shell_commands: Mutex<Vec<String>>,
+        unplugged: Mutex<bool>,
+        unplug: Notify,
+    }
+
+    fn message(content: &str) -> String {
+        format!("{:04x}{content}", content.len())
+    }
+
+    async fn read_request(stream: &mut (impl AsyncRead + Unpin)) -> Option<String> {
+        let mut len = [0u8; 4];
+        stream.read_exact(&mut len).await.ok()?;
+        let len = usize::from_str_radix(std::str::from_utf8(&len).ok()?, 16).ok()?;
+        let mut request = vec![0u8; len];
+        stream.read_exact(&mut request).await.ok()?;
+        String::from_utf8(request).ok()
+    }
+
+    impl FakeServer {
+        fn devices(&self) -> String {
+            if *self.unplugged.lock().unwrap() {
+                PIXEL.to_string()
+            } else {
+                format!("{PIXEL}{SAMSUNG}")
+            }
+        }
+
+        async fn serve(self: Arc<Self>, mut stream: TcpStream) {
+            let mut serial = None;
+
+            while let Some(request) = read_request(&mut stream).await {
+                let response = if request == "host:devices-l" {
+                    format!("OKAY{}", message(&self.devices()))
+                } else if request == "host:track-devices-l" {
+                    let _ = stream
+                        .write_all(format!("OKAY{}", message(&self.devices())).as_bytes())
+                        .await;
+                    self.unplug.notified().await;
+                    message(&self.devices())
+                } else if let Some(device) = request.strip_prefix("host:transport:") {
+                    if self.devices().contains(device) {
+                        serial = Some(device.to_string());
+                        "OKAY".to_string()
+                    } else {
+                        format!("FAIL{}", message(&format!("device '{device}' not found")))
+                    }
+                } else if let Some(ports) = request.strip_prefix("reverse:forward:") {
+                    let port = ports.split(';').next().unwrap();
+                    let port = port.strip_prefix("tcp:").unwrap().parse().unwrap();
+                    let serial = serial.clone().unwrap();
+                    self.reverse_ports.lock().unwrap().push((serial, port));
+                    "OKAYOKAY".to_string()
+                } else if let Some(port) = request.strip_prefix("reverse:killforward:tcp:") {
+                    let port: u16 = port.parse().unwrap();
+                    let mut reverse_ports = self.reverse_ports.lock().unwrap();
+                    let len = reverse_ports.len();
+                    reverse_ports.retain(|(s, p)| Some(s) != serial.as_ref() || *p != port);
+                    if reverse_ports.len() < len {
+                        "OKAYOKAY".to_string()
+                    } else {
+                        format!(
+                            "OKAYFAIL{}",
+                            message(&format!("listener 'tcp:{port}' not found"))
+                        )
+                    }
+                } else if let Some(command) = request.strip_prefix("shell:") {
+                    self.shell_commands
+                        .lock()
+                        .unwrap()
+                        .push(command.to_string());
+                    let _ = stream
+                        .write_all(b"OKAYpackage:io.github.teamclouday.AndroidMic\n")
+                        .await;
+                    return;
+                } else {
+                    format!("FAIL{}", message("unknown host service"))
+                };
+
+                if stream.write_all(response.as_bytes()).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn start_fake_server() -> (SocketAddr, Arc<FakeServer>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = Arc::new(FakeServer::default());
+
+        let server_clone = server.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(server_clone.clone().serve(stream));
+            }
+        });
+        (address, server)
+    }
+
+    async fn connect(address: SocketAddr) -> AdbConnection {
+        AdbConnection::connect_to(address).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn fake_adb_server() {
+        let (address, server) = start_fake_server().await;
+
+        let devices = connect(address).await.devices().await.unwrap();
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[1].serial, "R58M12345");
+        assert_eq!(devices[1].model.as_deref(), Some("SM G973F"));
+
+        connect(address)
+            .await
+            .reverse("R58M12345", 54345)
+            .await
+            .unwrap();
+        assert_eq!(
+            *server.reverse_ports.lock().unwrap(),
+            vec![("R58M12345".to_string(), 54345)]
+        );
+
+        // only for this device
+        let error = connect(address)
+            .await
+            .remove_reverse("0123456789ABCDEF", 54345)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("not found"), "{error}");
+        connect(address)
+            .await
+            .remove_reverse("R58M12345", 54345)
+            .await
+            .unwrap();
+        assert!(server.reverse_ports.lock().unwrap().is_empty());
+
+        let error = connect(address)
+            .await
+            .reverse("unknown", 54345)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("not found"), "{error}");
+
+        let output = connect(address)
+            .await
+            .shell("R58M12345", "pm list packages io.github.teamclouday")
+            .await
+            .unwrap();
+        assert_eq!(output, "package:io.github.teamclouday.AndroidMic");
+        assert_eq!(
+            *server.shell_commands.lock().unwrap(),
+            vec!["pm list packages io.github.teamclouday"]
+        );
+
+        let mut tracker = connect(address).await.track_devices().await.unwrap();
+        assert_eq!(tracker.next().await.unwrap().len(), 2);
+        *server.unplugged.lock().unwrap() = true;
+        server.unplug.notify_one();
+        let devices = tracker.next().await.unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].serial, "0123456789ABCDEF");
+    }
+}
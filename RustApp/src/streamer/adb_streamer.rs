@@ -1,5 +1,5 @@
 use anyhow::Result;
-use tokio::process::Command;
+use tokio::sync::mpsc;
 
 use crate::{
     config::ConnectionMode,
@@ -7,95 +7,87 @@ use crate::{
 };
 
 use super::{
-    AudioStream, ConnectError, StreamerTrait,
+    AudioStream, ConnectError, StreamerTrait, adb,
     tcp_streamer::{TcpStreamer, TcpStreamerState},
 };
 
 pub struct AdbStreamer {
     tcp_streamer: TcpStreamer,
+    /// Only this device is forwarded to us
+    serial: String,
+    /// To stop when the phone is unplugged
+    devices: mpsc::Receiver<Vec<adb::AdbDevice>>,
 }
 
-async fn get_connected_devices() -> Result<Vec<String>, ConnectError> {
-    let mut cmd = Command::new("adb");
-    cmd.arg("devices");
+pub async fn new(
+    port: u16,
+    serial: Option<String>,
+    launch_app: bool,
+    stream_config: AudioStream,
+) -> Result<AdbStreamer, ConnectError> {
+    let tcp_streamer = tcp_streamer::new("127.0.0.1".parse().unwrap(), port, stream_config).await?;
 
-    let output = exec_cmd(cmd).await?;
-    let mut devices = Vec::new();
+    let devices = adb::devices().await?;
+    let serial = adb::select_device(&devices, serial.as_deref())?
+        .serial
+        .clone();
 
-    // Skip the first line which is "List of devices attached"
-    for line in output.lines().skip(1) {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() >= 2 {
-            devices.push(parts[0].to_string());
-        }
+    if let Err(e) = adb::remove_reverse(&serial, tcp_streamer.port).await
+        && !e.to_string().contains("not found")
+    {
+        warn!("cannot remove adb proxy for device {serial}: {e}");
     }
-
-    Ok(devices)
-}
-
-async fn remove_adb_reverse_proxy(device_id: &str, port: u16) -> Result<(), ConnectError> {
-    let mut cmd = Command::new("adb");
-    cmd.arg("-s")
-        .arg(device_id)
-        .arg("reverse")
-        .arg("--remove")
-        .arg(format!("tcp:{}", port));
-
-    exec_cmd(cmd).await?;
-
-    Ok(())
-}
-
-async fn exec_cmd(mut cmd: Command) -> Result<String, ConnectError> {
-    // https://learn.microsoft.com/en-us/windows/win32/procthread/process-creation-flags
-    #[cfg(target_os = "windows")]
-    cmd.creation_flags(0x08000000);
-
-    let status = cmd.output().await.map_err(ConnectError::CommandFailed)?;
-
-    if !status.status.success() {
-        let stderr = String::from_utf8_lossy(&status.stderr).to_string();
-
-        return Err(ConnectError::AdbStatusCommand {
-            code: status.status.code(),
-            stderr,
-        });
+    adb::reverse(&serial, tcp_streamer.port).await?;
+    info!("adb reverse forwarding for device {serial}");
+
+    // built before launching the app, so `Drop` removes the forwarding if it fails
+    let streamer = AdbStreamer {
+        tcp_streamer,
+        serial,
+        devices: adb::watch_devices(),
+    };
+
+    if launch_app {
+        adb::launch_app(&streamer.serial, streamer.tcp_streamer.port).await?;
     }
-    let stdout = String::from_utf8_lossy(&status.stdout).trim().to_string();
-    Ok(stdout)
-}
 
-pub async fn new(port: u16, stream_config: AudioStream) -> Result<AdbStreamer, ConnectError> {
-    let tcp_streamer = tcp_streamer::new("127.0.0.1".parse().unwrap(), port, stream_config).await?;
-
-    let devices = get_connected_devices().await?;
-    if devices.is_empty() {
-        return Err(ConnectError::NoAdbDevice);
-    }
+    Ok(streamer)
+}
 
-    for device_id in &devices {
-        if let Err(e) = remove_adb_reverse_proxy(device_id, tcp_streamer.port).await
-            && !e.to_string().contains("not found")
+impl AdbStreamer {
+    fn check_device(&self, devices: &[adb::AdbDevice]) -> Result<(), ConnectError> {
+        if devices
+            .iter()
+            .any(|device| device.serial == self.serial && device.is_online())
         {
-            warn!("cannot remove adb proxy for device {device_id}: {e}");
+            Ok(())
+        } else {
+            Err(ConnectError::AdbDeviceGone(self.serial.clone()))
         }
-
-        let mut cmd = Command::new("adb");
-        cmd.arg("-s")
-            .arg(device_id)
-            .arg("reverse")
-            .arg(format!("tcp:{}", tcp_streamer.port))
-            .arg(format!("tcp:{}", tcp_streamer.port));
-        exec_cmd(cmd).await?;
     }
-
-    let streamer = AdbStreamer { tcp_streamer };
-    Ok(streamer)
 }
 
 impl StreamerTrait for AdbStreamer {
     async fn next(&mut self) -> Result<Option<StreamerMsg>, ConnectError> {
-        self.tcp_streamer.next().await
+        // accepting a connection and its handshake must not be cancelled,
+        // the phone can come back while we listen anyway
+        let streaming = matches!(self.tcp_streamer.state, TcpStreamerState::Streaming { .. });
+
+        tokio::select! {
+            msg = self.tcp_streamer.next() => match msg? {
+                // the peer is adb itself, on localhost
+                Some(StreamerMsg::Connected { .. }) => Ok(Some(self.status())),
+                msg => Ok(msg),
+            },
+            Some(mut devices) = self.devices.recv(), if streaming => {
+                // lists received while listening are outdated
+                while let Ok(latest) = self.devices.try_recv() {
+                    devices = latest;
+                }
+                self.check_device(&devices)?;
+                Ok(None)
+            }
+        }
     }
 
     fn reconfigure_stream(&mut self, config: AudioStream) {
@@ -112,6 +104,7 @@ impl StreamerTrait for AdbStreamer {
                 ip: None,
                 port: None,
                 mode: ConnectionMode::Adb,
+                peer: None,
             },
         }
     }
@@ -120,13 +113,10 @@ impl StreamerTrait for AdbStreamer {
 impl Drop for AdbStreamer {
     fn drop(&mut self) {
         let port = self.tcp_streamer.port;
+        let serial = self.serial.clone();
         tokio::spawn(async move {
-            let devices: Vec<String> = get_connected_devices().await.unwrap_or_default();
-
-            for device_id in devices {
-                if let Err(e) = remove_adb_reverse_proxy(&device_id, port).await {
-                    warn!("cannot remove adb proxy for device {device_id}: {e}");
-                }
+            if let Err(e) = adb::remove_reverse(&serial, port).await {
+                warn!("cannot remove adb proxy for device {serial}: {e}");
             }
         });
     }
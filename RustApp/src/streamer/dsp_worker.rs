@@ -0,0 +1,184 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use rtrb::{Consumer, Producer, RingBuffer};
+
+use crate::{audio::process::ProcessCache, diagnostics::PIPELINE_STATS};
+
+use super::{AudioPacketMessage, AudioStream, StreamerMsg};
+
+/// Packets waiting for the DSP thread. When it falls behind, new packets are dropped
+/// instead of blocking the network side.
+const PACKET_QUEUE_SIZE: usize = 64;
+
+/// Audio waves waiting to be sent to the UI
+const WAVE_QUEUE_SIZE: usize = 8;
+
+/// Longest run without sleeping, well below the RLIMIT_RTTIME set in `rtkit`
+const MAX_BUSY_TIME: Duration = Duration::from_millis(20);
+/// A sleep, not a park: the network side has likely left an unpark pending
+const BUSY_PAUSE: Duration = Duration::from_micros(100);
+
+/// Runs the audio processing of received packets on a dedicated thread,
+/// so a slow packet never delays socket reads.
+pub struct DspWorker {
+    packets: Producer<AudioPacketMessage>,
+    reconfigure: mpsc::Sender<AudioStream>,
+    waves: Consumer<Vec<(f32, f32)>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DspWorker {
+    pub fn new(stream_config: AudioStream) -> Self {
+        let (packets, packets_consumer) = RingBuffer::new(PACKET_QUEUE_SIZE);
+        let (waves_producer, waves) = RingBuffer::new(WAVE_QUEUE_SIZE);
+        let (reconfigure, reconfigure_receiver) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("dsp".into())
+                .spawn(move || {
+                    run(
+                        stream_config,
+                        packets_consumer,
+                        reconfigure_receiver,
+                        waves_producer,
+                        stop,
+                    )
+                })
+                .expect("failed to spawn the dsp thread")
+        };
+
+        Self {
+            packets,
+            reconfigure,
+            waves,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// Queue a packet for processing, without blocking.
+    /// Returns the latest audio wave computed by the DSP thread, if any.
+    pub fn push(&mut self, packet: AudioPacketMessage) -> Option<StreamerMsg> {
+        PIPELINE_STATS
+            .packets_received
+            .fetch_add(1, Ordering::Relaxed);
+        PIPELINE_STATS
+            .bytes_received
+            .fetch_add(packet.buffer.len() as u64, Ordering::Relaxed);
+
+        if let Err(rtrb::PushError::Full(packet)) = self.packets.push(packet) {
+            PIPELINE_STATS
+                .packets_dropped
+                .fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "dsp thread is falling behind, dropped {} audio bytes",
+                packet.buffer.len()
+            );
+        }
+        self.wake();
+
+        let mut latest = None;
+        while let Ok(data) = self.waves.pop() {
+            latest = Some(data);
+        }
+        latest.map(|data| StreamerMsg::UpdateAudioWave { data })
+    }
+
+    pub fn reconfigure(&mut self, stream_config: AudioStream) {
+        if self.reconfigure.send(stream_config).is_err() {
+            error!("dsp thread is not running");
+        }
+        self.wake();
+    }
+
+    fn wake(&self) {
+        if let Some(thread) = &self.thread {
+            thread.thread().unpark();
+        }
+    }
+}
+
+impl Drop for DspWorker {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        self.wake();
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            error!("dsp thread panicked");
+        }
+    }
+}
+
+fn run(
+    mut stream_config: AudioStream,
+    mut packets: Consumer<AudioPacketMessage>,
+    reconfigure: mpsc::Receiver<AudioStream>,
+    mut waves: Producer<Vec<(f32, f32)>>,
+    stop: Arc<AtomicBool>,
+) {
+    promote_current_thread();
+
+    let mut cache = ProcessCache::new();
+    let mut busy_since = Instant::now();
+
+    while !stop.load(Ordering::Acquire) {
+        // only the latest config matters
+        if let Some(new_config) = reconfigure.try_iter().last() {
+            cache.reconfigure(&stream_config.audio_params, &new_config.audio_params);
+            stream_config = new_config;
+        }
+
+        match packets.pop() {
+            Ok(packet) => {
+                let sample_rate = packet.sample_rate;
+
+                if let Ok(Some(buffer)) = stream_config.process_audio_packet(packet, &mut cache) {
+                    // the UI only needs the latest waves, drop them if it's late
+                    let _ = waves.push(AudioPacketMessage::to_wave_data(&buffer, sample_rate));
+                }
+
+                // the real-time budget only resets when the thread sleeps,
+                // a catch-up burst must not use all of it
+                if busy_since.elapsed() >= MAX_BUSY_TIME {
+                    thread::sleep(BUSY_PAUSE);
+                    busy_since = Instant::now();
+                }
+            }
+            // woken up by the network side
+            Err(_) => {
+                thread::park();
+                busy_since = Instant::now();
+            }
+        }
+    }
+}
+
+/// Give the DSP thread the highest priority we are allowed to
+fn promote_current_thread() {
+    #[cfg(target_os = "linux")]
+    match super::rtkit::make_current_thread_realtime(super::rtkit::DSP_THREAD_PRIORITY) {
+        Ok(priority) => {
+            info!("dsp thread uses real-time priority {priority}");
+            return;
+        }
+        Err(e) => warn!("can't get real-time priority from rtkit: {e}"),
+    }
+
+    match thread_priority::set_current_thread_priority(thread_priority::ThreadPriority::Max) {
+        Ok(()) => info!("dsp thread uses max priority"),
+        Err(e) => warn!("can't raise dsp thread priority: {e:?}"),
+    }
+}
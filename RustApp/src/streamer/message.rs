@@ -9,6 +9,9 @@ pub struct AudioPacketMessage {
     pub channel_count: u32,
     #[prost(uint32, tag = "4")]
     pub audio_format: u32,
+    /// Little endian when not set, like the senders which predate this field
+    #[prost(enumeration = "Endianness", tag = "5")]
+    pub endianness: i32,
 }
 #[derive(Clone, PartialEq, Eq, Hash, ::prost::Message)]
 pub struct AudioPacketMessageOrdered {
@@ -34,3 +37,30 @@ pub mod message_wrapper {
         Connect(super::ConnectMessage),
     }
 }
+/// Byte order of the PCM samples in `buffer`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum Endianness {
+    Little = 0,
+    Big = 1,
+}
+impl Endianness {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Little => "ENDIANNESS_LITTLE",
+            Self::Big => "ENDIANNESS_BIG",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "ENDIANNESS_LITTLE" => Some(Self::Little),
+            "ENDIANNESS_BIG" => Some(Self::Big),
+            _ => None,
+        }
+    }
+}
@@ -7,12 +7,17 @@ use tcp_streamer::TcpStreamer;
 use thiserror::Error;
 use udp_streamer::UdpStreamer;
 
+#[cfg(feature = "adb")]
+mod adb;
 #[cfg(feature = "adb")]
 mod adb_streamer;
 #[cfg(feature = "adb")]
 use adb_streamer::AdbStreamer;
 
+mod dsp_worker;
 mod message;
+#[cfg(target_os = "linux")]
+mod rtkit;
 mod streamer_runner;
 mod tcp_streamer;
 mod udp_streamer;
@@ -24,13 +29,29 @@ mod usb_streamer;
 #[cfg(feature = "usb")]
 use crate::streamer::usb_streamer::UsbStreamer;
 
-pub use message::AudioPacketMessage;
+#[cfg(feature = "adb")]
+pub use adb::AdbDevice;
+pub use message::{AudioPacketMessage, Endianness};
 pub use streamer_runner::{ConnectOption, StreamerCommand, StreamerMsg, sub};
 
+#[cfg(feature = "usb")]
+use crate::config::UsbDeviceId;
 use crate::{audio::AudioProcessParams, config::AudioFormat};
 
 pub const DEFAULT_PC_PORT: u16 = 54345;
 
+/// Devices seen by adb, for the device picker
+#[cfg(feature = "adb")]
+pub async fn adb_devices() -> Result<Vec<AdbDevice>, String> {
+    adb::devices().await.map_err(|e| e.to_string())
+}
+
+/// USB devices for the device picker
+#[cfg(feature = "usb")]
+pub async fn usb_devices() -> Result<Vec<UsbDeviceId>, String> {
+    usb::filter::list_devices().await.map_err(|e| e.to_string())
+}
+
 const CHECK_1: &str = "AndroidMic1";
 const CHECK_2: &str = "AndroidMic2";
 
@@ -103,6 +124,22 @@ enum ConnectError {
     NoUsbDevice(io::Error),
     #[error("no adb device found")]
     NoAdbDevice,
+    #[error("adb device {0} not found")]
+    AdbDeviceNotFound(String),
+    #[error("several adb devices are connected, select one")]
+    SeveralAdbDevices,
+    #[error("adb device {0} was disconnected")]
+    AdbDeviceGone(String),
+    #[error("can't talk to the adb server: {0}")]
+    AdbServer(io::Error),
+    #[error("adb server: {0}")]
+    AdbFailed(String),
+    #[error("unexpected adb server response: {0}")]
+    AdbProtocol(String),
+    #[error("AndroidMic is not installed on adb device {0}")]
+    AndroidAppNotInstalled(String),
+    #[error("can't start AndroidMic on the phone: {0}")]
+    CantLaunchAndroidApp(String),
     #[cfg(feature = "usb")]
     #[error("can't open usb handle: {0}")]
     CantOpenUsbHandle(io::Error),
@@ -115,6 +152,9 @@ enum ConnectError {
     #[cfg(feature = "usb")]
     #[error("can't switch usb device to aoa mode: {0}")]
     CantSwitchUsbAOAMode(io::Error),
+    #[cfg(feature = "usb")]
+    #[error("usb device doesn't support android accessory mode: {0}")]
+    UsbAoaUnsupported(io::Error),
     #[error("device disconnected")]
     Disconnected,
     #[error(transparent)]
@@ -168,7 +208,8 @@ impl StreamerTrait for DummyStreamer {
 impl AudioPacketMessage {
     fn to_wave_data(buffer: &[f32], sample_rate: u32) -> Vec<(f32, f32)> {
         const DEFAULT_WINDOW_DURATION_MS: f32 = 10.0; // 10ms window
-        let window_size = ((sample_rate as f32 * DEFAULT_WINDOW_DURATION_MS) / 1000.0) as usize;
+        let window_size =
+            (((sample_rate as f32 * DEFAULT_WINDOW_DURATION_MS) / 1000.0) as usize).max(1);
 
         buffer
             .chunks_exact(window_size)
@@ -183,10 +224,12 @@ impl AudioPacketMessage {
     fn sub_packets(&self, samples: usize) -> Vec<Self> {
         let mut packets = Vec::new();
         let channel_count = self.channel_count as usize;
-        let audio_format = AudioFormat::from_android_format(self.audio_format).unwrap();
+        let Some(audio_format) = AudioFormat::from_android_format(self.audio_format) else {
+            return vec![self.clone()];
+        };
 
         // calculate the size of each packet
-        let packet_size = audio_format.sample_size() * channel_count * samples;
+        let packet_size = (audio_format.sample_size() * channel_count * samples).max(1);
 
         // split the buffer into packets of the specified size
         for chunk in self.buffer.chunks(packet_size) {
@@ -0,0 +1,66 @@
+//! Real-time scheduling through RealtimeKit, which works without root privileges.
+//! https://gitlab.freedesktop.org/pipewire/rtkit
+
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use zbus::blocking::{Connection, Proxy};
+
+const RTKIT_NAME: &str = "org.freedesktop.RealtimeKit1";
+const RTKIT_PATH: &str = "/org/freedesktop/RealtimeKit1";
+
+/// Below the priorities used by PipeWire and JACK (88 and higher),
+/// we only feed their buffers
+pub const DSP_THREAD_PRIORITY: u32 = 10;
+
+/// The real-time thread, demoted by [`on_sigxcpu`]. 0 when none
+static REALTIME_THREAD: AtomicI32 = AtomicI32::new(0);
+
+/// Sent when the soft limit of RLIMIT_RTTIME is reached, the hard limit kills the process.
+/// Going back to SCHED_OTHER stops the count.
+extern "C" fn on_sigxcpu(_: libc::c_int) {
+    let thread_id = REALTIME_THREAD.load(Ordering::Relaxed);
+    if thread_id != 0 {
+        let param = libc::sched_param { sched_priority: 0 };
+        // async-signal-safe, a plain syscall
+        unsafe { libc::sched_setscheduler(thread_id, libc::SCHED_OTHER, &param) };
+    }
+}
+
+fn handle_sigxcpu() -> std::io::Result<()> {
+    let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+    action.sa_sigaction = on_sigxcpu as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    action.sa_flags = libc::SA_RESTART;
+    if unsafe { libc::sigaction(libc::SIGXCPU, &action, std::ptr::null_mut()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Ask rtkit to switch the current thread to SCHED_RR.
+/// Returns the priority that was granted.
+pub fn make_current_thread_realtime(priority: u32) -> anyhow::Result<u32> {
+    let connection = Connection::system()?;
+    let proxy = Proxy::new(&connection, RTKIT_NAME, RTKIT_PATH, RTKIT_NAME)?;
+
+    let max_priority: i32 = proxy.get_property("MaxRealtimePriority")?;
+    let max_rttime_usec: i64 = proxy.get_property("RTTimeUSecMax")?;
+    let priority = priority.min(max_priority.max(0) as u32);
+
+    // rtkit refuses processes which could starve the system:
+    // RLIMIT_RTTIME must be set, and not above its limit.
+    // The soft limit leaves time to fall back to SCHED_OTHER before being killed.
+    handle_sigxcpu()?;
+    let limit = libc::rlimit {
+        rlim_cur: (max_rttime_usec / 2) as libc::rlim_t,
+        rlim_max: max_rttime_usec as libc::rlim_t,
+    };
+    if unsafe { libc::setrlimit(libc::RLIMIT_RTTIME, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    let thread_id = unsafe { libc::syscall(libc::SYS_gettid) } as libc::pid_t;
+    REALTIME_THREAD.store(thread_id, Ordering::Relaxed);
+    proxy.call_method("MakeThreadRealtime", &(thread_id as u64, priority))?;
+
+    Ok(priority)
+}
@@ -8,11 +8,13 @@ use futures::{
 };
 use rtrb::Producer;
 use std::fmt::Debug;
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 use tokio::sync::mpsc::{self, Sender};
 
 use crate::audio::AudioProcessParams;
 use crate::config::ConnectionMode;
+#[cfg(feature = "usb")]
+use crate::config::UsbDeviceId;
 use crate::streamer::{StreamerTrait, WriteError};
 
 use super::{AudioStream, ConnectError, DummyStreamer, Streamer, tcp_streamer, udp_streamer};
@@ -30,9 +32,16 @@ pub enum ConnectOption {
     #[cfg(feature = "adb")]
     Adb {
         port: u16,
+        /// Device to forward to, the only one plugged when `None`
+        serial: Option<String>,
+        /// Open the Android app, so it starts streaming by itself
+        launch_app: bool,
     },
     #[cfg(feature = "usb")]
-    Usb,
+    Usb {
+        /// Any phone of a known vendor when `None`
+        device: Option<UsbDeviceId>,
+    },
 }
 
 /// App -> Streamer
@@ -94,6 +103,8 @@ pub enum StreamerMsg {
         ip: Option<IpAddr>,
         port: Option<u16>,
         mode: ConnectionMode,
+        /// Address of the phone, when known
+        peer: Option<SocketAddr>,
     },
     Ready(Sender<StreamerCommand>),
 }
@@ -152,21 +163,31 @@ pub fn sub() -> impl Stream<Item = StreamerMsg> {
                                                 .map(Streamer::from)
                                         }
                                         #[cfg(feature = "adb")]
-                                        ConnectOption::Adb { port } => {
-                                            crate::streamer::adb_streamer::new(port, stream_config)
-                                                .await
-                                                .map(Streamer::from)
-                                        }
+                                        ConnectOption::Adb {
+                                            port,
+                                            serial,
+                                            launch_app,
+                                        } => crate::streamer::adb_streamer::new(
+                                            port,
+                                            serial,
+                                            launch_app,
+                                            stream_config,
+                                        )
+                                        .await
+                                        .map(Streamer::from),
                                         ConnectOption::Udp { ip, port } => {
                                             udp_streamer::new(ip, port, stream_config)
                                                 .await
                                                 .map(Streamer::from)
                                         }
                                         #[cfg(feature = "usb")]
-                                        ConnectOption::Usb => {
-                                            crate::streamer::usb_streamer::new(stream_config)
-                                                .await
-                                                .map(Streamer::from)
+                                        ConnectOption::Usb { device } => {
+                                            crate::streamer::usb_streamer::new(
+                                                device,
+                                                stream_config,
+                                            )
+                                            .await
+                                            .map(Streamer::from)
                                         }
                                     };
 
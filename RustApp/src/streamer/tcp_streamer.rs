@@ -1,4 +1,8 @@
-use std::{io, net::IpAddr, time::Duration};
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
 
 use futures::StreamExt;
 use prost::Message;
@@ -9,9 +13,8 @@ use tokio::{
 use tokio_util::codec::{Framed, LengthDelimitedCodec};
 
 use crate::{
-    audio::process::ProcessCache,
     config::ConnectionMode,
-    streamer::{CHECK_1, CHECK_2, StreamerMsg, WriteError},
+    streamer::{CHECK_1, CHECK_2, StreamerMsg, WriteError, dsp_worker::DspWorker},
 };
 
 use super::{AudioPacketMessage, AudioStream, ConnectError, StreamerTrait};
@@ -24,8 +27,7 @@ pub struct TcpStreamer {
     ip: IpAddr,
     pub port: u16,
     pub state: TcpStreamerState,
-    stream_config: AudioStream,
-    process_cache: ProcessCache,
+    dsp: DspWorker,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -35,6 +37,7 @@ pub enum TcpStreamerState {
     },
     Streaming {
         framed: Framed<TcpStream, LengthDelimitedCodec>,
+        peer: SocketAddr,
         disconnect_loop_detecter: u32,
     },
 }
@@ -53,9 +56,8 @@ pub async fn new(
     let streamer = TcpStreamer {
         ip,
         port: addr.port(),
-        stream_config,
+        dsp: DspWorker::new(stream_config),
         state: TcpStreamerState::Listening { listener },
-        process_cache: ProcessCache::new(),
     };
 
     Ok(streamer)
@@ -63,8 +65,7 @@ pub async fn new(
 
 impl StreamerTrait for TcpStreamer {
     fn reconfigure_stream(&mut self, stream_config: AudioStream) {
-        self.stream_config = stream_config;
-        self.process_cache.clear();
+        self.dsp.reconfigure(stream_config);
     }
 
     fn status(&self) -> StreamerMsg {
@@ -73,10 +74,11 @@ impl StreamerTrait for TcpStreamer {
                 ip: Some(self.ip),
                 port: Some(self.port),
             },
-            TcpStreamerState::Streaming { .. } => StreamerMsg::Connected {
+            TcpStreamerState::Streaming { peer, .. } => StreamerMsg::Connected {
                 ip: Some(self.ip),
                 port: Some(self.port),
                 mode: ConnectionMode::Tcp,
+                peer: Some(*peer),
             },
         }
     }
@@ -117,6 +119,7 @@ impl StreamerTrait for TcpStreamer {
 
                 self.state = TcpStreamerState::Streaming {
                     framed: Framed::new(stream, LengthDelimitedCodec::new()),
+                    peer: addr,
                     disconnect_loop_detecter: 0,
                 };
 
@@ -124,33 +127,15 @@ impl StreamerTrait for TcpStreamer {
                     ip: Some(self.ip),
                     port: Some(self.port),
                     mode: ConnectionMode::Tcp,
+                    peer: Some(addr),
                 }))
             }
-            TcpStreamerState::Streaming {
-                framed,
-                disconnect_loop_detecter: _,
-            } => {
+            TcpStreamerState::Streaming { framed, .. } => {
                 match framed.next().await {
                     Some(Ok(frame)) => match AudioPacketMessage::decode(frame) {
                         Ok(packet) => {
-                            let buffer_size = packet.buffer.len();
-                            let sample_rate = packet.sample_rate;
-
-                            match self
-                                .stream_config
-                                .process_audio_packet(packet, &mut self.process_cache)
-                            {
-                                Ok(Some(buffer)) => {
-                                    debug!("received {} bytes", buffer_size);
-                                    Ok(Some(StreamerMsg::UpdateAudioWave {
-                                        data: AudioPacketMessage::to_wave_data(
-                                            &buffer,
-                                            sample_rate,
-                                        ),
-                                    }))
-                                }
-                                _ => Ok(None),
-                            }
+                            debug!("received {} bytes", packet.buffer.len());
+                            Ok(self.dsp.push(packet))
                         }
                         Err(e) => Err(ConnectError::WriteError(WriteError::Deserializer(e))),
                     },
@@ -1,4 +1,8 @@
-use std::{io, net::IpAddr, time::Duration};
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
 
 use futures::StreamExt;
 use prost::Message;
@@ -6,10 +10,10 @@ use tokio::net::UdpSocket;
 use tokio_util::{codec::LengthDelimitedCodec, udp::UdpFramed};
 
 use crate::{
-    audio::process::ProcessCache,
     config::ConnectionMode,
     streamer::{
-        AudioPacketMessage, CHECK_2, WriteError,
+        CHECK_2, WriteError,
+        dsp_worker::DspWorker,
         message::{MessageWrapper, message_wrapper::Payload},
     },
 };
@@ -23,11 +27,12 @@ const DISCONNECT_LOOP_DETECTER_MAX: u32 = 1000;
 pub struct UdpStreamer {
     ip: IpAddr,
     pub port: u16,
-    stream_config: AudioStream,
+    dsp: DspWorker,
     framed: UdpFramed<LengthDelimitedCodec>,
     is_listening: bool,
+    /// Sender of the first packet
+    peer: Option<SocketAddr>,
     tracked_sequence: u32,
-    process_cache: ProcessCache,
 }
 
 pub async fn new(
@@ -44,11 +49,11 @@ pub async fn new(
     let streamer = UdpStreamer {
         ip,
         port: addr.port(),
-        stream_config,
+        dsp: DspWorker::new(stream_config),
         tracked_sequence: 0,
         is_listening: true,
+        peer: None,
         framed: UdpFramed::new(socket, LengthDelimitedCodec::new()),
-        process_cache: ProcessCache::new(),
     };
 
     Ok(streamer)
@@ -56,8 +61,7 @@ pub async fn new(
 
 impl StreamerTrait for UdpStreamer {
     fn reconfigure_stream(&mut self, stream_config: AudioStream) {
-        self.stream_config = stream_config;
-        self.process_cache.clear();
+        self.dsp.reconfigure(stream_config);
     }
 
     fn status(&self) -> StreamerMsg {
@@ -71,6 +75,7 @@ impl StreamerTrait for UdpStreamer {
                 ip: Some(self.ip),
                 port: Some(self.port),
                 mode: ConnectionMode::Udp,
+                peer: self.peer,
             }
         }
     }
@@ -104,27 +109,12 @@ impl StreamerTrait for UdpStreamer {
                                             self.tracked_sequence = packet.sequence_number;
 
                                             let packet = packet.audio_packet.unwrap();
-                                            let buffer_size = packet.buffer.len();
-                                            let sample_rate = packet.sample_rate;
-
-                                            match self.stream_config.process_audio_packet(
-                                                packet,
-                                                &mut self.process_cache,
-                                            ) {
-                                                Ok(Some(buffer)) => {
-                                                    debug!(
-                                                        "From {:?}, received {} bytes",
-                                                        addr, buffer_size
-                                                    );
-                                                    Some(StreamerMsg::UpdateAudioWave {
-                                                        data: AudioPacketMessage::to_wave_data(
-                                                            &buffer,
-                                                            sample_rate,
-                                                        ),
-                                                    })
-                                                }
-                                                _ => None,
-                                            }
+                                            debug!(
+                                                "From {:?}, received {} bytes",
+                                                addr,
+                                                packet.buffer.len()
+                                            );
+                                            self.dsp.push(packet)
                                         }
                                         Payload::Connect(_) => {
                                             self.framed
@@ -141,10 +131,12 @@ impl StreamerTrait for UdpStreamer {
 
                                     if self.is_listening {
                                         self.is_listening = false;
+                                        self.peer = Some(addr);
                                         Ok(Some(StreamerMsg::Connected {
                                             ip: Some(self.ip),
                                             port: Some(self.port),
                                             mode: ConnectionMode::Udp,
+                                            peer: Some(addr),
                                         }))
                                     } else {
                                         Ok(message)
@@ -7,7 +7,7 @@ use std::{
 use nusb::{
     Device, DeviceInfo, Interface,
     descriptors::TransferType,
-    transfer::{ControlIn, ControlOut, ControlType, Direction, Recipient},
+    transfer::{ControlIn, ControlOut, ControlType, Direction, Recipient, TransferError},
 };
 
 const USB_AOA_VID: u16 = 0x18D1;
@@ -111,7 +111,12 @@ impl AccessoryInterfaceExt for Interface {
             length: 2, // 2 byte response
         };
 
-        let res = self.control_in(req, timeout).await?;
+        let res = self.control_in(req, timeout).await.map_err(|e| match e {
+            // the device rejected the request, it doesn't know AOA.
+            // Other errors may not happen next time.
+            TransferError::Stall => Error::new(ErrorKind::Unsupported, e),
+            e => e.into(),
+        })?;
 
         if res.len() != 2 {
             return Err(Error::new(
@@ -193,6 +198,7 @@ impl AccessoryInterfaceExt for Interface {
     async fn start_accessory(&mut self, accessory: &AccessoryStrings) -> Result<u16, Error> {
         let timeout = Duration::from_secs(1);
 
+        // devices that don't know AOA stall this request
         let protocol = self.get_protocol(timeout).await?;
         if protocol < 1 {
             return Err(Error::new(
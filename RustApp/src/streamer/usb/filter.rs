@@ -0,0 +1,81 @@
+//! Which USB devices are asked to switch to accessory mode.
+//! AOA requests are vendor control requests, keyboards or webcams don't expect them.
+
+use nusb::DeviceInfo;
+
+use super::hotplug::UsbDevice;
+use crate::{config::UsbDeviceId, streamer::ConnectError};
+
+const USB_CLASS_HUB: u8 = 0x09;
+
+/// Phone makers, mostly from https://developer.android.com/studio/run/device#VendorIds.
+/// Vendors making many other devices (Intel, Dell, HP, ...) are left out,
+/// their phones can still be selected.
+const ANDROID_VENDOR_IDS: &[u16] = &[
+    0x0502, // Acer
+    0x0b05, // ASUS
+    0x0bb4, // HTC
+    0x0e8d, // MediaTek
+    0x0fce, // Sony Mobile
+    0x1004, // LG
+    0x0482, // Kyocera
+    0x04dd, // Sharp
+    0x04e8, // Samsung
+    0x05c6, // Qualcomm
+    0x0955, // Nvidia
+    0x12d1, // Huawei
+    0x17ef, // Lenovo
+    0x18d1, // Google
+    0x19d2, // ZTE
+    0x22b8, // Motorola
+    0x22d9, // OPPO, Realme
+    0x2717, // Xiaomi
+    0x2a45, // Meizu
+    0x2a70, // OnePlus
+    0x2ae5, // Fairphone
+    0x2d95, // vivo
+    0x2e04, // HMD Global (Nokia)
+];
+
+fn is_android_vendor(vendor_id: u16) -> bool {
+    ANDROID_VENDOR_IDS.contains(&vendor_id)
+}
+
+/// The selected device, or else a phone of a known vendor.
+/// In accessory mode, a phone has the ids of Google: only its serial tells which one it is.
+pub fn is_candidate<D: UsbDevice>(device: &D, selected: Option<&UsbDeviceId>) -> bool {
+    match selected {
+        Some(selected) if device.in_accessory_mode() => {
+            selected.serial.is_none() || selected.serial.as_deref() == device.serial_number()
+        }
+        Some(selected) => selected.matches(
+            device.vendor_id(),
+            device.product_id(),
+            device.serial_number(),
+        ),
+        None => device.in_accessory_mode() || is_android_vendor(device.vendor_id()),
+    }
+}
+
+/// Plugged devices for the device picker, phones of known vendors first
+pub async fn list_devices() -> Result<Vec<UsbDeviceId>, ConnectError> {
+    let mut devices: Vec<DeviceInfo> = nusb::list_devices()
+        .await
+        .map_err(|e| ConnectError::NoUsbDevice(e.into()))?
+        .filter(|device| device.class() != USB_CLASS_HUB)
+        .collect();
+    devices.sort_by_key(|device| !is_android_vendor(device.vendor_id()));
+
+    Ok(devices
+        .iter()
+        .map(|device| UsbDeviceId {
+            vendor_id: device.vendor_id(),
+            product_id: device.product_id(),
+            serial: device.serial_number().map(ToString::to_string),
+            name: device
+                .product_string()
+                .or(device.manufacturer_string())
+                .map(ToString::to_string),
+        })
+        .collect())
+}
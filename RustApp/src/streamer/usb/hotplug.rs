@@ -0,0 +1,320 @@
+//! Waiting for a phone in accessory mode.
+//!
+//! A phone switched to accessory mode disconnects and comes back with another
+//! product id, so we follow plug events instead of listing devices once.
+
+use super::filter;
+use crate::{config::UsbDeviceId, streamer::ConnectError};
+
+/// What we need to know about a USB device
+pub trait UsbDevice: Clone {
+    type Id: PartialEq + Clone;
+
+    fn id(&self) -> Self::Id;
+
+    fn vendor_id(&self) -> u16;
+
+    fn product_id(&self) -> u16;
+
+    fn serial_number(&self) -> Option<&str>;
+
+    fn in_accessory_mode(&self) -> bool;
+}
+
+pub enum DeviceEvent<D: UsbDevice> {
+    Connected(D),
+    Disconnected(D::Id),
+}
+
+/// Where USB devices come from, nusb or a mock in tests
+pub trait UsbDeviceProvider {
+    type Device: UsbDevice;
+
+    /// Devices plugged at startup
+    async fn list(&mut self) -> Result<Vec<Self::Device>, ConnectError>;
+
+    /// Next plug or unplug. Cancel safe.
+    async fn next_event(&mut self) -> Option<DeviceEvent<Self::Device>>;
+
+    /// The device disconnects when it works, and comes back in accessory mode
+    async fn switch_to_accessory(&mut self, device: &Self::Device) -> Result<(), ConnectError>;
+}
+
+/// Tracks plugged devices
+pub struct AccessoryWatcher<P: UsbDeviceProvider> {
+    provider: P,
+    devices: Vec<P::Device>,
+    /// See [`filter::is_candidate`]
+    selected: Option<UsbDeviceId>,
+    /// Devices that don't know AOA, not asked again
+    unsupported: Vec<<P::Device as UsbDevice>::Id>,
+}
+
+impl<P: UsbDeviceProvider> AccessoryWatcher<P> {
+    pub async fn new(mut provider: P, selected: Option<UsbDeviceId>) -> Result<Self, ConnectError> {
+        let devices = provider.list().await?;
+        Ok(Self {
+            provider,
+            devices,
+            selected,
+            unsupported: Vec::new(),
+        })
+    }
+
+    fn is_candidate(&self, device: &P::Device) -> bool {
+        !self.unsupported.contains(&device.id())
+            && filter::is_candidate(device, self.selected.as_ref())
+    }
+
+    /// Fails when the provider has no more events
+    async fn update(&mut self) -> Result<DeviceEvent<P::Device>, ConnectError> {
+        let event = self.provider.next_event().await.ok_or_else(|| {
+            ConnectError::NoUsbDevice(std::io::Error::other("usb hotplug events stopped"))
+        })?;
+
+        match &event {
+            DeviceEvent::Connected(device) => {
+                debug!("usb device connected");
+                self.devices.retain(|d| d.id() != device.id());
+                self.devices.push(device.clone());
+            }
+            DeviceEvent::Disconnected(id) => {
+                debug!("usb device disconnected");
+                self.devices.retain(|d| d.id() != *id);
+                self.unsupported.retain(|unsupported| unsupported != id);
+            }
+        }
+        Ok(event)
+    }
+
+    /// The first device that accepts
+    async fn switch_any(&mut self, devices: Vec<P::Device>) -> Option<P::Device> {
+        for device in devices {
+            match self.provider.switch_to_accessory(&device).await {
+                Ok(()) => return Some(device),
+                Err(e @ ConnectError::UsbAoaUnsupported(_)) => {
+                    debug!("{e}");
+                    self.unsupported.push(device.id());
+                }
+                Err(e) => warn!("cannot switch usb device to accessory mode: {e}"),
+            }
+        }
+        None
+    }
+
+    /// The first device we manage to switch to accessory mode, once it is back.
+    /// Waits as long as needed, for the phone to come back or to be plugged.
+    pub async fn wait_for_accessory(&mut self) -> Result<P::Device, ConnectError> {
+        // the handshake is sent again to devices already in accessory mode,
+        // they don't re-enumerate
+        let mut candidates: Vec<_> = self
+            .devices
+            .iter()
+            .filter(|d| self.is_candidate(d))
+            .cloned()
+            .collect();
+        candidates.sort_by_key(|d| !d.in_accessory_mode());
+
+        match self.switch_any(candidates).await {
+            Some(device) if device.in_accessory_mode() => return Ok(device),
+            Some(_) => {}
+            None => {
+                if let Some(device) = self
+                    .devices
+                    .iter()
+                    .find(|d| d.in_accessory_mode() && self.is_candidate(d))
+                {
+                    return Ok(device.clone());
+                }
+                info!("no usb device switched to accessory mode, waiting for one to be plugged");
+            }
+        }
+
+        loop {
+            if let DeviceEvent::Connected(device) = self.update().await?
+                && self.is_candidate(&device)
+                && let Some(device) = self.switch_any(vec![device]).await
+                && device.in_accessory_mode()
+            {
+                return Ok(device);
+            }
+        }
+    }
+
+    /// Returns when the device is unplugged
+    pub async fn wait_for_unplug(&mut self, id: &<P::Device as UsbDevice>::Id) {
+        loop {
+            match self.update().await {
+                Ok(DeviceEvent::Disconnected(unplugged)) if unplugged == *id => return,
+                Ok(_) => {}
+                // we can't know anymore, the stream will tell
+                Err(_) => return std::future::pending().await,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    const GOOGLE: u16 = 0x18d1;
+    const SAMSUNG: u16 = 0x04e8;
+    const XIAOMI: u16 = 0x2717;
+    const LOGITECH: u16 = 0x046d;
+    const UNKNOWN_VENDOR: u16 = 0x1234;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct FakeDevice {
+        id: u32,
+        vendor_id: u16,
+        serial: String,
+        accessory: bool,
+        /// Fails `REQUEST_GET_PROTOCOL`
+        refuses_aoa: bool,
+    }
+
+    impl UsbDevice for FakeDevice {
+        type Id = u32;
+
+        fn id(&self) -> u32 {
+            self.id
+        }
+
+        fn vendor_id(&self) -> u16 {
+            self.vendor_id
+        }
+
+        fn product_id(&self) -> u16 {
+            if self.accessory { 0x2d01 } else { 1 }
+        }
+
+        fn serial_number(&self) -> Option<&str> {
+            Some(&self.serial)
+        }
+
+        fn in_accessory_mode(&self) -> bool {
+            self.accessory
+        }
+    }
+
+    /// Devices re-enumerate with `id + 100` in accessory mode,
+    /// unless they already are
+    struct FakeProvider {
+        devices: Vec<FakeDevice>,
+        sender: mpsc::UnboundedSender<DeviceEvent<FakeDevice>>,
+        events: mpsc::UnboundedReceiver<DeviceEvent<FakeDevice>>,
+        switch_attempts: Vec<u32>,
+    }
+
+    impl FakeProvider {
+        fn new(devices: Vec<FakeDevice>) -> (Self, mpsc::UnboundedSender<DeviceEvent<FakeDevice>>) {
+            let (sender, events) = mpsc::unbounded_channel();
+            let provider = Self {
+                devices,
+                sender: sender.clone(),
+                events,
+                switch_attempts: Vec::new(),
+            };
+            (provider, sender)
+        }
+    }
+
+    impl UsbDeviceProvider for FakeProvider {
+        type Device = FakeDevice;
+
+        async fn list(&mut self) -> Result<Vec<FakeDevice>, ConnectError> {
+            Ok(self.devices.clone())
+        }
+
+        async fn next_event(&mut self) -> Option<DeviceEvent<FakeDevice>> {
+            self.events.recv().await
+        }
+
+        async fn switch_to_accessory(&mut self, device: &FakeDevice) -> Result<(), ConnectError> {
+            self.switch_attempts.push(device.id);
+            if device.refuses_aoa {
+                return Err(ConnectError::UsbAoaUnsupported(std::io::Error::other(
+                    "no AOA",
+                )));
+            }
+            if device.accessory {
+                return Ok(());
+            }
+            let _ = self.sender.send(DeviceEvent::Disconnected(device.id));
+            let _ = self.sender.send(DeviceEvent::Connected(FakeDevice {
+                id: device.id + 100,
+                vendor_id: GOOGLE,
+                serial: device.serial.clone(),
+                accessory: true,
+                refuses_aoa: false,
+            }));
+            Ok(())
+        }
+    }
+
+    fn device(id: u32, vendor_id: u16) -> FakeDevice {
+        FakeDevice {
+            id,
+            vendor_id,
+            serial: format!("S{id}"),
+            accessory: false,
+            refuses_aoa: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn reconnect_after_re_enumeration_and_unplug() {
+        let refusing_phone = FakeDevice {
+            refuses_aoa: true,
+            ..device(2, SAMSUNG)
+        };
+        let (provider, sender) =
+            FakeProvider::new(vec![device(1, LOGITECH), refusing_phone, device(3, GOOGLE)]);
+        let mut watcher = AccessoryWatcher::new(provider, None).await.unwrap();
+
+        // no second click needed, the keyboard is left alone
+        let accessory = watcher.wait_for_accessory().await.unwrap();
+        assert_eq!(accessory.id, 103);
+        assert_eq!(watcher.provider.switch_attempts, vec![2, 3, 103]);
+        assert!(!watcher.devices.iter().any(|d| d.id == 3));
+
+        // unplugged while streaming, another phone is plugged
+        sender.send(DeviceEvent::Disconnected(103)).unwrap();
+        watcher.wait_for_unplug(&103).await;
+        sender
+            .send(DeviceEvent::Connected(device(4, XIAOMI)))
+            .unwrap();
+
+        // the phone without AOA is not asked again
+        let accessory = watcher.wait_for_accessory().await.unwrap();
+        assert_eq!(accessory.id, 104);
+        assert_eq!(watcher.provider.switch_attempts, vec![2, 3, 103, 4, 104]);
+
+        // already in accessory mode, when connecting again
+        assert_eq!(watcher.wait_for_accessory().await.unwrap().id, 104);
+    }
+
+    #[tokio::test]
+    async fn only_the_selected_device() {
+        let (provider, _sender) =
+            FakeProvider::new(vec![device(3, GOOGLE), device(5, UNKNOWN_VENDOR)]);
+        // selected in another USB mode, the serial is enough
+        let selected = UsbDeviceId {
+            vendor_id: UNKNOWN_VENDOR,
+            product_id: 2,
+            serial: Some("S5".into()),
+            name: None,
+        };
+        let mut watcher = AccessoryWatcher::new(provider, Some(selected))
+            .await
+            .unwrap();
+
+        // found again in accessory mode with its serial
+        let accessory = watcher.wait_for_accessory().await.unwrap();
+        assert_eq!(accessory.id, 105);
+        assert_eq!(watcher.provider.switch_attempts, vec![5, 105]);
+    }
+}
@@ -1 +1,3 @@
 pub mod aoa;
+pub mod filter;
+pub mod hotplug;
@@ -1,35 +1,115 @@
 use std::time::Duration;
 
 use futures::StreamExt;
-use nusb::io::{EndpointRead, EndpointWrite};
+use nusb::{
+    DeviceId, DeviceInfo,
+    hotplug::{HotplugEvent, HotplugWatch},
+    io::{EndpointRead, EndpointWrite},
+};
 use prost::Message;
 use tokio::io::AsyncWriteExt;
 use tokio_util::codec::{FramedRead, LengthDelimitedCodec};
 
 use super::AudioStream;
 use crate::{
-    audio::process::ProcessCache,
-    config::ConnectionMode,
+    config::{ConnectionMode, UsbDeviceId},
     streamer::{
         CHECK_2, WriteError,
+        dsp_worker::DspWorker,
         message::{MessageWrapper, message_wrapper::Payload},
-        usb::aoa::{
-            AccessoryDeviceExt, AccessoryDeviceInfoExt, AccessoryInterfaceExt, AccessoryStrings,
+        usb::{
+            aoa::{
+                AccessoryDeviceExt, AccessoryDeviceInfoExt, AccessoryInterfaceExt, AccessoryStrings,
+            },
+            hotplug::{self, AccessoryWatcher, DeviceEvent, UsbDeviceProvider},
         },
     },
 };
 
-use super::{AudioPacketMessage, ConnectError, StreamerMsg, StreamerTrait};
+use super::{ConnectError, StreamerMsg, StreamerTrait};
 
 const TRANSFER_BUFFER_SIZE: usize = 1024;
+/// Right after re-enumeration, the device may not be ready to be opened
+const OPEN_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Bounds of the delay before opening again a device whose stream failed
+const MIN_STREAM_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_STREAM_RETRY_DELAY: Duration = Duration::from_secs(30);
 
 pub struct UsbStreamer {
-    stream_config: AudioStream,
-    reader: FramedRead<EndpointRead<nusb::transfer::Bulk>, LengthDelimitedCodec>,
-    writer: EndpointWrite<nusb::transfer::Bulk>,
+    dsp: DspWorker,
+    watcher: AccessoryWatcher<NusbDevices>,
+    /// `None` while waiting for the phone
+    connection: Option<UsbConnection>,
     is_listening: bool,
     tracked_sequence: u32,
-    process_cache: ProcessCache,
+    /// Device whose stream failed while it stayed plugged
+    failed_device: Option<DeviceId>,
+    /// Doubles each time the stream fails, until audio is received
+    stream_retry_delay: Duration,
+}
+
+struct UsbConnection {
+    device_id: DeviceId,
+    reader: FramedRead<EndpointRead<nusb::transfer::Bulk>, LengthDelimitedCodec>,
+    writer: EndpointWrite<nusb::transfer::Bulk>,
+}
+
+impl hotplug::UsbDevice for DeviceInfo {
+    type Id = DeviceId;
+
+    fn id(&self) -> DeviceId {
+        DeviceInfo::id(self)
+    }
+
+    fn vendor_id(&self) -> u16 {
+        DeviceInfo::vendor_id(self)
+    }
+
+    fn product_id(&self) -> u16 {
+        DeviceInfo::product_id(self)
+    }
+
+    fn serial_number(&self) -> Option<&str> {
+        DeviceInfo::serial_number(self)
+    }
+
+    fn in_accessory_mode(&self) -> bool {
+        AccessoryDeviceInfoExt::in_accessory_mode(self)
+    }
+}
+
+struct NusbDevices {
+    watch: HotplugWatch,
+}
+
+impl NusbDevices {
+    fn new() -> Result<Self, ConnectError> {
+        Ok(Self {
+            watch: nusb::watch_devices().map_err(|e| ConnectError::NoUsbDevice(e.into()))?,
+        })
+    }
+}
+
+impl UsbDeviceProvider for NusbDevices {
+    type Device = DeviceInfo;
+
+    async fn list(&mut self) -> Result<Vec<DeviceInfo>, ConnectError> {
+        Ok(nusb::list_devices()
+            .await
+            .map_err(|e| ConnectError::NoUsbDevice(e.into()))?
+            .collect())
+    }
+
+    async fn next_event(&mut self) -> Option<DeviceEvent<DeviceInfo>> {
+        self.watch.next().await.map(|event| match event {
+            HotplugEvent::Connected(device) => DeviceEvent::Connected(device),
+            HotplugEvent::Disconnected(id) => DeviceEvent::Disconnected(id),
+        })
+    }
+
+    async fn switch_to_accessory(&mut self, device: &DeviceInfo) -> Result<(), ConnectError> {
+        switch_to_accessory(device).await
+    }
 }
 
 // switch a USB device to accessory mode
@@ -81,7 +161,10 @@ async fn switch_to_accessory(device_info: &nusb::DeviceInfo) -> Result<(), Conne
     iface
         .start_accessory(&strings)
         .await
-        .map_err(ConnectError::CantSwitchUsbAOAMode)?;
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::Unsupported => ConnectError::UsbAoaUnsupported(e),
+            _ => ConnectError::CantSwitchUsbAOAMode(e),
+        })?;
 
     info!(
         "USB device {} (address=0x{:X}) switched to accessory mode",
@@ -93,69 +176,35 @@ async fn switch_to_accessory(device_info: &nusb::DeviceInfo) -> Result<(), Conne
     Ok(())
 }
 
-pub async fn new(stream_config: AudioStream) -> Result<UsbStreamer, ConnectError> {
-    // first try to switch any connected device to accessory mode
-    for device in nusb::list_devices()
+async fn open_accessory(device_info: &DeviceInfo) -> Result<UsbConnection, ConnectError> {
+    let device = device_info
+        .open()
         .await
-        .map_err(|e| ConnectError::NoUsbDevice(e.into()))?
-    {
-        if let Err(error) = switch_to_accessory(&device).await {
-            warn!(
-                "Cannot switch USB device 0x{:X} to accessory mode: {}",
-                device.device_address(),
-                error
-            )
-        } else {
-            break;
-        }
-    }
-
-    let (device_info, endpoints, reader, writer) = {
-        let device_info = match nusb::list_devices()
-            .await
-            .map_err(|e| ConnectError::NoUsbDevice(e.into()))?
-            .find(|d| d.in_accessory_mode())
-        {
-            Some(info) => info,
-            None => {
-                return Err(ConnectError::NoUsbDevice(std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    "No USB device found in accessory mode.\nIf this is your first connection attempt, the device has been switched to accessory mode.\nPlease click Connect again to establish the connection.",
-                )));
-            }
-        };
-
-        let device = device_info
-            .open()
-            .await
-            .map_err(|e| ConnectError::CantOpenUsbHandle(e.into()))?;
-
-        let iface = device
-            .claim_interface(0)
-            .await
-            .map_err(|e| ConnectError::CantClaimUsbInterface(e.into()))?;
-
-        let endpoints = device.get_bulk_endpoints().ok_or_else(|| {
-            ConnectError::CantLoadUsbConfig(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Accessory interface does not have required bulk endpoints",
-            ))
-        })?;
+        .map_err(|e| ConnectError::CantOpenUsbHandle(e.into()))?;
 
-        let reader = iface
-            .endpoint::<nusb::transfer::Bulk, nusb::transfer::In>(endpoints.0)
-            .map_err(|e| ConnectError::CantLoadUsbConfig(e.into()))?
-            .reader(TRANSFER_BUFFER_SIZE)
-            .with_num_transfers(8);
+    let iface = device
+        .claim_interface(0)
+        .await
+        .map_err(|e| ConnectError::CantClaimUsbInterface(e.into()))?;
 
-        let writer = iface
-            .endpoint::<nusb::transfer::Bulk, nusb::transfer::Out>(endpoints.1)
-            .map_err(|e| ConnectError::CantLoadUsbConfig(e.into()))?
-            .writer(TRANSFER_BUFFER_SIZE)
-            .with_num_transfers(8);
-
-        (device_info, endpoints, reader, writer)
-    };
+    let endpoints = device.get_bulk_endpoints().ok_or_else(|| {
+        ConnectError::CantLoadUsbConfig(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Accessory interface does not have required bulk endpoints",
+        ))
+    })?;
+
+    let reader = iface
+        .endpoint::<nusb::transfer::Bulk, nusb::transfer::In>(endpoints.0)
+        .map_err(|e| ConnectError::CantLoadUsbConfig(e.into()))?
+        .reader(TRANSFER_BUFFER_SIZE)
+        .with_num_transfers(8);
+
+    let writer = iface
+        .endpoint::<nusb::transfer::Bulk, nusb::transfer::Out>(endpoints.1)
+        .map_err(|e| ConnectError::CantLoadUsbConfig(e.into()))?
+        .writer(TRANSFER_BUFFER_SIZE)
+        .with_num_transfers(8);
 
     info!(
         "Connected to USB device {} (address=0x{:X}, in=0x{:X} out=0x{:X}))",
@@ -165,22 +214,76 @@ pub async fn new(stream_config: AudioStream) -> Result<UsbStreamer, ConnectError
         endpoints.1
     );
 
-    let streamer = UsbStreamer {
-        stream_config,
+    Ok(UsbConnection {
+        device_id: device_info.id(),
         reader: FramedRead::new(reader, LengthDelimitedCodec::new()),
         writer,
+    })
+}
+
+/// The phone is switched to accessory mode, and opened once it is back, by [`UsbStreamer::next`]
+pub async fn new(
+    device: Option<UsbDeviceId>,
+    stream_config: AudioStream,
+) -> Result<UsbStreamer, ConnectError> {
+    let watcher = AccessoryWatcher::new(NusbDevices::new()?, device).await?;
+
+    let streamer = UsbStreamer {
+        dsp: DspWorker::new(stream_config),
+        watcher,
+        connection: None,
         is_listening: true,
         tracked_sequence: 0,
-        process_cache: ProcessCache::new(),
+        failed_device: None,
+        stream_retry_delay: Duration::ZERO,
     };
 
     Ok(streamer)
 }
 
+impl UsbStreamer {
+    /// Back to waiting for the phone
+    fn disconnect(&mut self) -> StreamerMsg {
+        self.connection = None;
+        self.is_listening = true;
+        self.tracked_sequence = 0;
+        StreamerMsg::Listening {
+            ip: None,
+            port: None,
+        }
+    }
+
+    /// The phone stays in accessory mode when its app stops streaming,
+    /// opening it again at once would fail the same way
+    fn disconnect_after_failure(&mut self, device_id: DeviceId) -> StreamerMsg {
+        self.failed_device = Some(device_id);
+        self.stream_retry_delay =
+            (self.stream_retry_delay * 2).clamp(MIN_STREAM_RETRY_DELAY, MAX_STREAM_RETRY_DELAY);
+        self.disconnect()
+    }
+
+    /// Until the failed device is plugged again, or the retry delay is over
+    async fn wait_before_retry(&mut self) {
+        let Some(device_id) = self.failed_device else {
+            return;
+        };
+        info!(
+            "waiting {:?} or for the USB device to be plugged again",
+            self.stream_retry_delay
+        );
+        tokio::select! {
+            () = self.watcher.wait_for_unplug(&device_id) => {
+                self.stream_retry_delay = Duration::ZERO;
+            }
+            () = tokio::time::sleep(self.stream_retry_delay) => {}
+        }
+        self.failed_device = None;
+    }
+}
+
 impl StreamerTrait for UsbStreamer {
     fn reconfigure_stream(&mut self, stream_config: AudioStream) {
-        self.stream_config = stream_config;
-        self.process_cache.clear();
+        self.dsp.reconfigure(stream_config);
     }
 
     fn status(&self) -> StreamerMsg {
@@ -194,21 +297,42 @@ impl StreamerTrait for UsbStreamer {
                 ip: None,
                 port: None,
                 mode: ConnectionMode::Usb,
+                peer: None,
             }
         }
     }
 
     async fn next(&mut self) -> Result<Option<StreamerMsg>, ConnectError> {
-        match tokio::time::timeout(
-            Duration::from_secs(if self.is_listening {
-                Duration::MAX.as_secs()
-            } else {
-                1
-            }),
-            self.reader.next(),
-        )
-        .await
-        {
+        let Some(connection) = &mut self.connection else {
+            self.wait_before_retry().await;
+            let device_info = self.watcher.wait_for_accessory().await?;
+            match open_accessory(&device_info).await {
+                Ok(connection) => self.connection = Some(connection),
+                Err(e) => {
+                    warn!("{e}");
+                    tokio::time::sleep(OPEN_RETRY_DELAY).await;
+                }
+            }
+            return Ok(None);
+        };
+
+        let device_id = connection.device_id;
+        let read_timeout = Duration::from_secs(if self.is_listening {
+            Duration::MAX.as_secs()
+        } else {
+            1
+        });
+
+        let res = tokio::select! {
+            () = self.watcher.wait_for_unplug(&connection.device_id) => None,
+            res = tokio::time::timeout(read_timeout, connection.reader.next()) => Some(res),
+        };
+        let Some(res) = res else {
+            info!("USB device unplugged, waiting for it to come back");
+            return Ok(Some(self.disconnect()));
+        };
+
+        match res {
             Ok(res) => match res {
                 Some(Ok(frame)) => {
                     match MessageWrapper::decode(frame) {
@@ -227,36 +351,21 @@ impl StreamerTrait for UsbStreamer {
                                             self.tracked_sequence = packet.sequence_number;
 
                                             let packet = packet.audio_packet.unwrap();
-                                            let buffer_size = packet.buffer.len();
-                                            let sample_rate = packet.sample_rate;
-
-                                            match self.stream_config.process_audio_packet(
-                                                packet,
-                                                &mut self.process_cache,
-                                            ) {
-                                                Ok(Some(buffer)) => {
-                                                    debug!("received {} bytes", buffer_size);
-                                                    Some(StreamerMsg::UpdateAudioWave {
-                                                        data: AudioPacketMessage::to_wave_data(
-                                                            &buffer,
-                                                            sample_rate,
-                                                        ),
-                                                    })
-                                                }
-                                                _ => None,
-                                            }
+                                            debug!("received {} bytes", packet.buffer.len());
+                                            self.dsp.push(packet)
                                         }
                                         Payload::Connect(_) => {
                                             info!("Received connect message from device");
-                                            self.writer
+                                            connection
+                                                .writer
                                                 .write_all(CHECK_2.as_bytes())
                                                 .await
                                                 .map_err(|e| {
-                                                    ConnectError::HandShakeFailed("writing", e)
-                                                })?;
-                                            self.writer.flush_end_async().await.map_err(|e| {
-                                                ConnectError::HandShakeFailed("flushing", e)
+                                                ConnectError::HandShakeFailed("writing", e)
                                             })?;
+                                            connection.writer.flush_end_async().await.map_err(
+                                                |e| ConnectError::HandShakeFailed("flushing", e),
+                                            )?;
 
                                             None
                                         }
@@ -264,16 +373,21 @@ impl StreamerTrait for UsbStreamer {
 
                                     if self.is_listening {
                                         self.is_listening = false;
+                                        self.stream_retry_delay = Duration::ZERO;
                                         Ok(Some(StreamerMsg::Connected {
                                             ip: None,
                                             port: None,
                                             mode: ConnectionMode::Usb,
+                                            peer: None,
                                         }))
                                     } else {
                                         Ok(message)
                                     }
                                 }
-                                None => todo!(),
+                                None => {
+                                    warn!("USB message without payload");
+                                    Ok(None)
+                                }
                             }
                         }
                         Err(e) => Err(ConnectError::WriteError(WriteError::Deserializer(e))),
@@ -284,11 +398,15 @@ impl StreamerTrait for UsbStreamer {
                     match e.kind() {
                         std::io::ErrorKind::TimedOut => Ok(None), // timeout use to check for input on stdin
                         std::io::ErrorKind::WouldBlock => Ok(None), // trigger on Linux when there is no stream input
-                        _ => Err(WriteError::Io(e))?,
+                        _ => {
+                            warn!("USB read failed: {e}, waiting for the device");
+                            Ok(Some(self.disconnect_after_failure(device_id)))
+                        }
                     }
                 }
                 None => {
-                    todo!()
+                    info!("USB stream closed, waiting for the device");
+                    Ok(Some(self.disconnect_after_failure(device_id)))
                 }
             },
             Err(_) => {
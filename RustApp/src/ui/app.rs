@@ -1,22 +1,24 @@
 use std::{
+    collections::VecDeque,
     fmt::{Debug, Display},
-    net::{IpAddr, Ipv4Addr},
+    path::Path,
+    sync::{Arc, atomic::Ordering},
+    time::Duration,
 };
 
+use chrono::Local;
 use cpal::{
     Device, Host,
     traits::{DeviceTrait, HostTrait, StreamTrait},
 };
 use local_ip_address::list_afinet_netifas;
-use notify_rust::Notification;
-use rtrb::RingBuffer;
 use tokio::sync::mpsc::Sender;
 
 use cosmic::{
     Application, ApplicationExt, Element,
     app::{Core, Settings, Task},
     executor,
-    iced::{Size, Subscription, futures::StreamExt, window},
+    iced::{Size, Subscription, futures::StreamExt, time, window},
     iced_widget::scrollable::{self, AbsoluteOffset},
     theme,
     widget::markdown,
@@ -27,19 +29,21 @@ use cpal::HostId;
 
 use super::{
     message::{AppMsg, ConfigMsg},
+    notifications::{self, UnderrunMonitor, notify},
     view::{main_window, settings_window},
     wave::AudioWave,
 };
 
-#[cfg(not(target_os = "linux"))]
-use super::tray::{SystemTray, SystemTrayMsg, SystemTrayStream};
+use super::tray::{SystemTray, SystemTrayMsg, SystemTrayStream, TrayState};
 
 use crate::{
-    audio::{AudioPacketFormat, AudioProcessParams},
+    audio::{AudioPacketFormat, AudioProcessParams, player},
     config::{
-        AppTheme, AudioFormat, ChannelCount, Config, ConnectionMode, NetworkAdapter, SampleRate,
+        AppTheme, AudioFormat, ChannelCount, Config, ConnectionMode, EffectChainEntry, EqBand,
+        MAX_EQ_BANDS, MAX_POST_EFFECTS, NetworkAdapter, NotificationKind, SampleRate,
     },
-    fl, single_instance,
+    diagnostics::{self, PIPELINE_STATS, SessionInfo},
+    fl, logging, single_instance,
     streamer::{self, ConnectOption, DEFAULT_PC_PORT, StreamerCommand, StreamerMsg},
     ui::view::{SCROLLABLE_ID, about_window},
     utils::APP_ID,
@@ -47,6 +51,9 @@ use crate::{
 };
 use zconf::ConfigManager;
 
+#[cfg(feature = "usb")]
+use crate::config::UsbDeviceId;
+
 pub fn run_ui(flags: Flags) {
     let settings = Settings::default()
         .no_main_window(true)
@@ -107,8 +114,6 @@ fn get_audio_devices(audio_host: &Host) -> Vec<AudioDevice> {
         .collect()
 }
 
-const SHARED_BUF_SIZE_S: f32 = 1.; // 0.15s
-
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ConnectionState {
     Default,
@@ -137,16 +142,27 @@ pub struct AppState {
     pub connection_state: ConnectionState,
     pub network_adapters: Vec<NetworkAdapter>,
     pub network_adapter: Option<NetworkAdapter>,
+    #[cfg(feature = "adb")]
+    pub adb_devices: Vec<streamer::AdbDevice>,
+    #[cfg(feature = "usb")]
+    pub usb_devices: Vec<UsbDeviceId>,
     pub port_input: String,
+    pub sample_rate_input: String,
+    pub profile_name_input: String,
+    pub profile_include_connection: bool,
+    pub profile_include_dsp: bool,
     pub main_window: Option<CustomWindow>,
     pub settings_window: Option<CustomWindow>,
     pub about_window: Option<CustomWindow>,
     pub logs: Vec<markdown::Item>,
     log_path: String,
-    #[cfg(not(target_os = "linux"))]
     pub system_tray: Option<SystemTray>,
-    #[cfg(not(target_os = "linux"))]
     pub system_tray_stream: Option<SystemTrayStream>,
+    /// Shared with the output stream callbacks
+    pub output: Arc<player::OutputState>,
+    underrun_monitor: UnderrunMonitor,
+    /// Latest first, for the diagnostics bundle
+    connection_errors: VecDeque<String>,
     has_shown_minimize_notification: bool,
     launched_automatically: bool,
 }
@@ -161,21 +177,24 @@ impl AppState {
     }
 
     fn update_audio_stream(&mut self) -> Task<AppMsg> {
+        // most settings of the tray end up here
+        self.update_tray();
+
         if self.connection_state != ConnectionState::Connected {
             return Task::none();
         }
-        let (producer, consumer) = RingBuffer::<u8>::new(self.get_shared_buf_size());
         let config = self.config.data().clone();
 
-        match self.create_audio_stream(consumer, true) {
-            Ok(audio_config) => {
+        match self.create_audio_stream(true) {
+            Ok((audio_config, producer)) => {
+                let log = self.log_output_format(&audio_config);
                 self.send_command(StreamerCommand::ReconfigureStream {
                     buff: producer,
                     audio_params: AudioProcessParams::new(audio_config, config),
                     is_window_visible: self.main_window.is_some(),
                 });
 
-                Task::none()
+                log
             }
             Err(e) => {
                 error!("failed to start audio stream: {e}");
@@ -190,23 +209,109 @@ impl AppState {
         scrollable::scroll_to(SCROLLABLE_ID.clone(), AbsoluteOffset { x: 0., y: f32::MAX })
     }
 
-    fn get_shared_buf_size(&self) -> usize {
-        let size = ((self.config.data().sample_rate.to_number() as f32
-            * self.config.data().channel_count.to_number() as f32
-            * self.config.data().audio_format.sample_size() as f32)
-            * SHARED_BUF_SIZE_S)
-            .ceil() as usize;
-        info!("shared buf size: {size}");
+    /// The rate is kept even when the device doesn't support it,
+    /// the stream then uses the closest supported rate
+    fn set_sample_rate(&mut self, sample_rate: SampleRate) -> Task<AppMsg> {
+        let warning = self.audio_device.as_ref().and_then(|device| {
+            player::check_sample_rate(device, &self.config.data().audio_format, sample_rate).err()
+        });
+
+        self.config.update(|s| s.sample_rate = sample_rate);
+        self.sample_rate_input = sample_rate.to_string();
+        let task = self.update_audio_stream();
+
+        match warning {
+            Some(e) => {
+                warn!("{e}");
+                Task::batch([task, self.add_log(&e.to_string())])
+            }
+            None => task,
+        }
+    }
+
+    fn switch_profile(&mut self, name: &str) -> Task<AppMsg> {
+        if self.config.data().profile(name).is_none() {
+            return self.add_log(&fl!("profile_not_found", name = name));
+        }
+
+        self.config.update(|c| {
+            c.apply_profile(name);
+        });
+
+        let config = self.config.data().clone();
+        self.port_input = config.port.to_string();
+        self.sample_rate_input = config.sample_rate.to_string();
+        if let Some(id) = &config.device_id
+            && let Some(audio_device) = self.audio_devices.iter().find(|d| &d.id == id)
+        {
+            self.audio_device = Some(audio_device.device.clone());
+        }
+        if let Some(ip) = config.ip
+            && let Some(adapter) = self.network_adapters.iter().find(|a| a.ip == ip)
+        {
+            self.network_adapter = Some(adapter.clone());
+        }
+        info!("switched to profile {name}");
+        Task::batch([
+            self.add_log(&fl!("profile_switched", name = name)),
+            self.update_audio_stream(),
+        ])
+    }
+
+    fn tray_state(&self) -> TrayState {
+        let config = self.config.data();
+        let status = match self.connection_state {
+            ConnectionState::Default => fl!("state_disconnected"),
+            ConnectionState::WaitingOnStatus => fl!("waiting"),
+            ConnectionState::Connected => fl!("state_connected"),
+            ConnectionState::Listening => fl!("state_listening"),
+        };
 
-        size
+        TrayState {
+            status,
+            disconnected: self.connection_state == ConnectionState::Default,
+            connection_mode: config.connection_mode,
+            audio_devices: self.audio_devices.clone(),
+            device_id: config.device_id.clone(),
+            denoise: config.denoise,
+            post_effects: config
+                .post_effects
+                .iter()
+                .map(|entry| (entry.preset, !entry.bypass))
+                .collect(),
+            muted: self.output.muted.load(Ordering::Relaxed),
+            profiles: config.profile_names(),
+            active_profile: config.active_profile.clone(),
+        }
+    }
+
+    /// Push the state to the tray, it does nothing when the state didn't change
+    fn update_tray(&mut self) {
+        let state = self.tray_state();
+        if let Some(system_tray) = self.system_tray.as_mut() {
+            system_tray.update(state);
+        }
+    }
+
+    /// Tell the user when the output device can't use the configured format
+    fn log_output_format(&mut self, audio_config: &AudioPacketFormat) -> Task<AppMsg> {
+        let config = self.config.data();
+        if audio_config.sample_rate == config.sample_rate
+            && audio_config.audio_format == config.audio_format
+            && audio_config.channel_count == config.channel_count
+        {
+            return Task::none();
+        }
+
+        let log = fl!("output_format_fallback", format = audio_config.to_string());
+        self.add_log(&log)
     }
 
     fn connect(&mut self) -> Task<AppMsg> {
         let config = self.config.data().clone();
-        let (producer, consumer) = RingBuffer::<u8>::new(self.get_shared_buf_size());
 
-        let audio_config = match self.create_audio_stream(consumer, false) {
-            Ok(audio_config) => audio_config,
+        let (audio_config, producer) = match self.create_audio_stream(false) {
+            Ok(res) => res,
             Err(e) => {
                 error!("failed to start audio stream: {e}");
                 return self.add_log(&e.to_string());
@@ -240,13 +345,20 @@ impl AppState {
                 }
             }
             #[cfg(feature = "adb")]
-            ConnectionMode::Adb => ConnectOption::Adb { port: config.port },
+            ConnectionMode::Adb => ConnectOption::Adb {
+                port: config.port,
+                serial: config.adb_serial.clone(),
+                launch_app: config.adb_launch_app,
+            },
             #[cfg(feature = "usb")]
-            ConnectionMode::Usb => ConnectOption::Usb,
+            ConnectionMode::Usb => ConnectOption::Usb {
+                device: config.usb_device.clone(),
+            },
         };
 
         self.connection_state = ConnectionState::WaitingOnStatus;
 
+        let log = self.log_output_format(&audio_config);
         self.send_command(StreamerCommand::Connect {
             connect_options,
             buff: producer,
@@ -254,7 +366,7 @@ impl AppState {
             is_window_visible: self.main_window.is_some(),
         });
 
-        Task::none()
+        log
     }
 
     fn disconnect(&mut self) -> Task<AppMsg> {
@@ -262,15 +374,52 @@ impl AppState {
         self.connection_state = ConnectionState::Default;
         self.audio_stream = None;
         self.audio_wave.clear();
-
-        #[cfg(not(target_os = "linux"))]
-        if let Some(system_tray) = self.system_tray.as_mut() {
-            system_tray.update_menu_state(true, &fl!("state_disconnected"));
-        }
+        self.update_tray();
 
         Task::none()
     }
 
+    #[cfg(feature = "adb")]
+    fn refresh_adb_devices(&self) -> Task<AppMsg> {
+        Task::perform(streamer::adb_devices(), |devices| {
+            cosmic::Action::App(AppMsg::AdbDevices(devices))
+        })
+    }
+
+    #[cfg(feature = "usb")]
+    fn refresh_usb_devices(&self) -> Task<AppMsg> {
+        Task::perform(streamer::usb_devices(), |devices| {
+            cosmic::Action::App(AppMsg::UsbDevices(devices))
+        })
+    }
+
+    fn create_diagnostics_bundle(&mut self) -> Task<AppMsg> {
+        let path = diagnostics::default_bundle_path();
+        let log_dir = Path::new(&self.log_path).parent().unwrap_or(Path::new("."));
+        let connection_errors: Vec<String> = self.connection_errors.iter().cloned().collect();
+        let session = SessionInfo {
+            connection_errors: &connection_errors,
+            output_format: self
+                .audio_stream
+                .as_ref()
+                .map(|stream| stream.config.to_string()),
+        };
+
+        match diagnostics::create_bundle(&path, self.config.data(), log_dir, &session) {
+            Ok(()) => {
+                info!("diagnostics bundle created: {}", path.display());
+                self.add_log(&fl!(
+                    "diagnostics_bundle_created",
+                    path = path.display().to_string()
+                ))
+            }
+            Err(e) => {
+                error!("can't create diagnostics bundle: {e}");
+                self.add_log(&format!("can't create diagnostics bundle: {e}"))
+            }
+        }
+    }
+
     fn open_main_window(&mut self) -> Task<AppMsg> {
         let mut commands = Vec::new();
         let settings = window::Settings {
@@ -299,6 +448,8 @@ pub struct Flags {
     pub launched_automatically: bool,
 }
 
+const MAX_CONNECTION_ERRORS: usize = 20;
+
 // used because the markdown parsing only detect https links
 const HTTPS_PREFIX_WORKAROUND: &str = "https://-file-";
 
@@ -373,19 +524,6 @@ impl Application for AppState {
             None => None,
         };
 
-        // initialize system tray
-        #[cfg(not(target_os = "linux"))]
-        let (system_tray, system_tray_stream) = match SystemTray::new() {
-            Ok((mut tray, stream)) => {
-                tray.update_menu_state(true, &fl!("state_disconnected"));
-                (Some(tray), Some(stream))
-            }
-            Err(e) => {
-                error!("failed to create system tray: {e}");
-                (None, None)
-            }
-        };
-
         let mut commands = Vec::new();
 
         let config = flags.config.data().clone();
@@ -403,20 +541,39 @@ impl Application for AppState {
             connection_state: ConnectionState::Default,
             network_adapters,
             network_adapter,
+            #[cfg(feature = "adb")]
+            adb_devices: Vec::new(),
+            #[cfg(feature = "usb")]
+            usb_devices: Vec::new(),
             port_input: config.port.to_string(),
+            sample_rate_input: config.sample_rate.to_string(),
+            profile_name_input: String::new(),
+            profile_include_connection: true,
+            profile_include_dsp: true,
             main_window: None,
             settings_window: None,
             about_window: None,
             logs: Vec::new(),
             log_path: flags.log_path.clone(),
-            #[cfg(not(target_os = "linux"))]
-            system_tray,
-            #[cfg(not(target_os = "linux"))]
-            system_tray_stream,
+            system_tray: None,
+            system_tray_stream: None,
+            output: Arc::new(player::OutputState::default()),
+            underrun_monitor: UnderrunMonitor::default(),
+            connection_errors: VecDeque::new(),
             has_shown_minimize_notification: false,
             launched_automatically: flags.launched_automatically,
         };
 
+        match SystemTray::new(app.tray_state()) {
+            Ok((tray, stream)) => {
+                app.system_tray = Some(tray);
+                app.system_tray_stream = Some(stream);
+            }
+            Err(e) => {
+                error!("failed to create system tray: {e}");
+            }
+        }
+
         commands
             .push(app.add_log(format!("app version: `{}`", env!("CARGO_PKG_VERSION")).as_str()));
         commands.push(
@@ -435,18 +592,33 @@ impl Application for AppState {
         info!("config path: {}", flags.config_path);
         info!("log path: {}", flags.log_path);
 
-        #[cfg(not(target_os = "linux"))]
-        if !flags.launched_automatically || !app.config.data().start_minimized {
-            commands.push(app.open_main_window());
+        #[cfg(feature = "adb")]
+        if app.config.data().connection_mode == ConnectionMode::Adb {
+            commands.push(app.refresh_adb_devices());
+        }
+        #[cfg(feature = "usb")]
+        if app.config.data().connection_mode == ConnectionMode::Usb {
+            commands.push(app.refresh_usb_devices());
         }
 
-        #[cfg(target_os = "linux")]
-        commands.push(app.open_main_window());
+        // without tray, a minimized app couldn't be shown again
+        if !flags.launched_automatically
+            || !app.config.data().start_minimized
+            || app.system_tray.is_none()
+        {
+            commands.push(app.open_main_window());
+        }
 
         match single_instance::stream() {
             Ok(stream) => {
                 commands.push(cosmic::iced::task::Task::run(stream, |event| match event {
                     single_instance::IpcEvent::Show => cosmic::Action::App(AppMsg::ShowWindow),
+                    single_instance::IpcEvent::SwitchProfile(name) => {
+                        cosmic::Action::App(AppMsg::Config(ConfigMsg::SwitchProfile(name)))
+                    }
+                    single_instance::IpcEvent::SetLogLevel(level) => {
+                        cosmic::Action::App(AppMsg::Config(ConfigMsg::LogLevel(level)))
+                    }
                 }));
             }
             Err(e) => {
@@ -465,6 +637,16 @@ impl Application for AppState {
                 self.config.update(|config| {
                     config.connection_mode = connection_mode;
                 });
+                self.update_tray();
+
+                #[cfg(feature = "adb")]
+                if connection_mode == ConnectionMode::Adb {
+                    return self.refresh_adb_devices();
+                }
+                #[cfg(feature = "usb")]
+                if connection_mode == ConnectionMode::Usb {
+                    return self.refresh_usb_devices();
+                }
             }
             AppMsg::RefreshAudioDevices => {
                 #[cfg(not(target_os = "linux"))]
@@ -475,6 +657,7 @@ impl Application for AppState {
                     cpal::host_from_id(self.audio_host.id()).unwrap_or(cpal::default_host());
 
                 self.audio_devices = get_audio_devices(&audio_host);
+                self.update_tray();
             }
             AppMsg::RefreshNetworkAdapters => {
                 let network_adapters = list_afinet_netifas()
@@ -490,9 +673,31 @@ impl Application for AppState {
             }
             AppMsg::Streamer(streamer_msg) => match streamer_msg {
                 StreamerMsg::Error(e) => {
+                    match self.connection_state {
+                        // no phone was connected yet
+                        ConnectionState::WaitingOnStatus | ConnectionState::Listening => notify(
+                            config,
+                            NotificationKind::ConnectFailed,
+                            &fl!("notification_connect_failed", error = e.as_str()),
+                        ),
+                        ConnectionState::Connected => notify(
+                            config,
+                            NotificationKind::Disconnected,
+                            &fl!("notification_disconnected", reason = e.as_str()),
+                        ),
+                        ConnectionState::Default => {}
+                    }
+
+                    self.connection_errors.push_front(format!(
+                        "[{}] {e}",
+                        Local::now().format("%Y-%m-%dT%H:%M:%S")
+                    ));
+                    self.connection_errors.truncate(MAX_CONNECTION_ERRORS);
+
                     self.connection_state = ConnectionState::Default;
                     self.audio_stream = None;
                     self.audio_wave.clear();
+                    self.update_tray();
                     return self.add_log(&e);
                 }
                 StreamerMsg::Listening { ip, port } => {
@@ -501,46 +706,50 @@ impl Application for AppState {
                     }
                     self.audio_wave.clear();
 
-                    #[cfg(not(target_os = "linux"))]
-                    if let Some(system_tray) = self.system_tray.as_mut() {
-                        system_tray.update_menu_state(false, &fl!("state_listening"));
+                    // udp has no connection, the phone stopped sending
+                    if self.connection_state == ConnectionState::Connected {
+                        notify(
+                            config,
+                            NotificationKind::Disconnected,
+                            &fl!("notification_connection_lost"),
+                        );
                     }
 
                     self.connection_state = ConnectionState::Listening;
+                    self.update_tray();
                     if let (Some(ip), Some(port)) = (ip, port) {
                         info!("listening on {ip}:{port}");
                         return self.add_log(format!("Listening on `{ip}:{port}`").as_str());
                     }
                 }
-                StreamerMsg::Connected { ip, port, mode: _ } => {
+                StreamerMsg::Connected {
+                    ip,
+                    port,
+                    mode,
+                    peer,
+                } => {
                     if let Err(e) = self.audio_stream.as_ref().unwrap().stream.play() {
                         error!("{e}");
                     }
 
-                    #[cfg(not(target_os = "linux"))]
-                    if let Some(system_tray) = self.system_tray.as_mut() {
-                        system_tray.update_menu_state(false, &fl!("state_connected"));
-                    }
-
+                    // show notification when app is minimized
                     if self.main_window.is_none() {
-                        let address = format!(
-                            "{}:{}",
-                            ip.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
-                            port.unwrap_or_default()
-                        );
-
-                        // show notification when app is minimized
-                        let _ = Notification::new()
-                            .summary("AndroidMic")
-                            .body(format!("Connected on {address}").as_str())
-                            .auto_icon()
-                            .show()
-                            .map_err(|e| {
-                                error!("failed to show notification: {e}");
-                            });
+                        let body = match peer {
+                            Some(peer) => fl!(
+                                "notification_connected_peer",
+                                mode = mode.label(),
+                                peer = peer.to_string()
+                            ),
+                            None => fl!("notification_connected", mode = mode.label()),
+                        };
+                        notify(config, NotificationKind::Connected, &body);
                     }
 
+                    PIPELINE_STATS.connections.fetch_add(1, Ordering::Relaxed);
+                    self.underrun_monitor.reset();
+                    self.output.underruns.store(0, Ordering::Relaxed);
                     self.connection_state = ConnectionState::Connected;
+                    self.update_tray();
                     if let (Some(ip), Some(port)) = (ip, port) {
                         info!("connected on {ip}:{port}");
                         return self.add_log(format!("Connected on `{ip}:{port}`").as_str());
@@ -566,15 +775,89 @@ impl Application for AppState {
             AppMsg::SelectedHost(selected_host) => {
                 self.audio_host = cpal::host_from_id(selected_host).unwrap_or(cpal::default_host());
                 self.audio_devices = get_audio_devices(&self.audio_host);
+                self.update_tray();
             }
             AppMsg::Adapter(adapter) => {
                 self.config.update(|c| c.ip = Some(adapter.ip));
                 self.network_adapter = Some(adapter.clone());
                 return self.add_log(format!("Selected network adapter: {adapter}").as_str());
             }
+            #[cfg(feature = "adb")]
+            AppMsg::RefreshAdbDevices => {
+                return self.refresh_adb_devices();
+            }
+            #[cfg(feature = "adb")]
+            AppMsg::AdbDevices(devices) => match devices {
+                Ok(devices) => self.adb_devices = devices,
+                Err(e) => {
+                    error!("can't list adb devices: {e}");
+                    self.adb_devices.clear();
+                    return self.add_log(&e);
+                }
+            },
+            #[cfg(feature = "adb")]
+            AppMsg::AdbDevice(device) => {
+                self.config
+                    .update(|c| c.adb_serial = Some(device.serial.clone()));
+                return self.add_log(&fl!("adb_device_selected", device = device.to_string()));
+            }
+            #[cfg(feature = "usb")]
+            AppMsg::RefreshUsbDevices => {
+                return self.refresh_usb_devices();
+            }
+            #[cfg(feature = "usb")]
+            AppMsg::UsbDevices(devices) => match devices {
+                Ok(devices) => self.usb_devices = devices,
+                Err(e) => {
+                    error!("can't list usb devices: {e}");
+                    self.usb_devices.clear();
+                    return self.add_log(&e);
+                }
+            },
+            #[cfg(feature = "usb")]
+            AppMsg::UsbDevice(device) => {
+                let log = fl!("usb_device_selected", device = device.to_string());
+                self.config.update(|c| c.usb_device = Some(device));
+                return self.add_log(&log);
+            }
             AppMsg::Connect => {
                 return self.connect();
             }
+            AppMsg::CheckOutput => {
+                if self.output.device_lost.swap(false, Ordering::Relaxed) {
+                    warn!("output device lost");
+                    PIPELINE_STATS
+                        .output_devices_lost
+                        .fetch_add(1, Ordering::Relaxed);
+                    notify(
+                        config,
+                        NotificationKind::OutputDeviceLost,
+                        &fl!("notification_output_device_lost"),
+                    );
+                    let _ = self.disconnect();
+                    return self.add_log(&fl!("notification_output_device_lost"));
+                }
+
+                let underruns = self.output.underruns.swap(0, Ordering::Relaxed);
+                PIPELINE_STATS
+                    .output_underruns
+                    .fetch_add(underruns.into(), Ordering::Relaxed);
+                if self.underrun_monitor.record(underruns) {
+                    PIPELINE_STATS
+                        .underrun_storms
+                        .fetch_add(1, Ordering::Relaxed);
+                    warn!("buffer underrun storm: {underruns} underruns in the last second");
+                    notify(
+                        config,
+                        NotificationKind::BufferUnderruns,
+                        &fl!("notification_buffer_underruns"),
+                    );
+                }
+            }
+            AppMsg::Mute(muted) => {
+                self.output.muted.store(muted, Ordering::Relaxed);
+                self.update_tray();
+            }
             AppMsg::Stop => {
                 return self.disconnect();
             }
@@ -603,6 +886,14 @@ impl Application for AppState {
                         .chain(set_window_title);
                 }
             },
+            AppMsg::StartAtLogin(enabled, result) => {
+                if let Err(e) = result {
+                    error!("{e}");
+                    if enabled {
+                        self.config.update(|s| s.start_at_login = false);
+                    }
+                }
+            }
             AppMsg::Config(msg) => match msg {
                 ConfigMsg::PortTextInput(text) => {
                     self.port_input = text;
@@ -631,8 +922,57 @@ impl Application for AppState {
                     return self.add_log(format!("Changed port to {}", port).as_str());
                 }
 
+                ConfigMsg::SwitchProfile(name) => {
+                    return self.switch_profile(&name);
+                }
+                ConfigMsg::ProfileNameInput(name) => {
+                    self.profile_name_input = name;
+                }
+                ConfigMsg::ProfileIncludeConnection(include) => {
+                    self.profile_include_connection = include;
+                }
+                ConfigMsg::ProfileIncludeDsp(include) => {
+                    self.profile_include_dsp = include;
+                }
+                ConfigMsg::SaveProfile => {
+                    let name = self.profile_name_input.trim().to_string();
+                    let (connection, dsp) =
+                        (self.profile_include_connection, self.profile_include_dsp);
+                    if name.is_empty() || !(connection || dsp) {
+                        return Task::none();
+                    }
+
+                    self.config
+                        .update(|c| c.save_profile(&name, connection, dsp));
+                    self.profile_name_input.clear();
+                    self.update_tray();
+                    return self.add_log(&fl!("profile_saved", name = name));
+                }
+                ConfigMsg::DeleteProfile(name) => {
+                    self.config.update(|c| c.delete_profile(&name));
+                    self.update_tray();
+                    return self.add_log(&fl!("profile_deleted", name = name));
+                }
                 ConfigMsg::SampleRate(sample_rate) => {
-                    self.config.update(|s| s.sample_rate = sample_rate);
+                    return self.set_sample_rate(sample_rate);
+                }
+                ConfigMsg::SampleRateTextInput(text) => {
+                    self.sample_rate_input = text;
+                }
+                ConfigMsg::SampleRateSave => match self.sample_rate_input.parse() {
+                    Ok(sample_rate) => return self.set_sample_rate(sample_rate),
+                    Err(e) => {
+                        self.sample_rate_input = self.config.data().sample_rate.to_string();
+                        return self.add_log(&e);
+                    }
+                },
+                ConfigMsg::ResamplerQuality(resampler_quality) => {
+                    self.config
+                        .update(|s| s.resampler_quality = resampler_quality);
+                    return self.update_audio_stream();
+                }
+                ConfigMsg::Dither(dither) => {
+                    self.config.update(|s| s.dither = dither);
                     return self.update_audio_stream();
                 }
                 ConfigMsg::ChannelCount(channel_count) => {
@@ -644,11 +984,35 @@ impl Application for AppState {
                     return self.update_audio_stream();
                 }
                 ConfigMsg::StartAtLogin(start_at_login) => {
-                    crate::start_at_login::start_at_login(start_at_login, &mut self.config);
+                    // shown at once, rolled back if it fails
+                    self.config.update(|s| s.start_at_login = start_at_login);
+                    let method = self.config.data().autostart_method;
+                    return Task::perform(
+                        crate::start_at_login::set_start_at_login(method, start_at_login),
+                        move |result| {
+                            cosmic::Action::App(AppMsg::StartAtLogin(start_at_login, result))
+                        },
+                    );
+                }
+                ConfigMsg::AutostartMethod(method) => {
+                    #[cfg(target_os = "linux")]
+                    let previous = self.config.data().autostart_method;
+                    self.config.update(|s| s.autostart_method = method);
+
+                    #[cfg(target_os = "linux")]
+                    if self.config.data().start_at_login && previous != method {
+                        return Task::perform(
+                            crate::start_at_login::move_start_at_login(previous, method),
+                            |result| cosmic::Action::App(AppMsg::StartAtLogin(true, result)),
+                        );
+                    }
                 }
                 ConfigMsg::AutoConnect(auto_connect) => {
                     self.config.update(|s| s.auto_connect = auto_connect);
                 }
+                ConfigMsg::AdbLaunchApp(launch_app) => {
+                    self.config.update(|s| s.adb_launch_app = launch_app);
+                }
                 ConfigMsg::UseRecommendedFormat => {
                     if let Some(device) = &self.audio_device
                         && let Ok(format) = device.default_output_config()
@@ -662,11 +1026,13 @@ impl Application for AppState {
                         self.config.update(|s| {
                             s.sample_rate =
                                 SampleRate::from_number(format.sample_rate()).unwrap_or_default();
-                            s.channel_count =
-                                ChannelCount::from_number(format.channels()).unwrap_or_default();
+                            // surround devices get stereo up-mixed
+                            s.channel_count = ChannelCount::from_number(format.channels().min(2))
+                                .unwrap_or_default();
                             s.audio_format = AudioFormat::from_cpal_format(format.sample_format())
                                 .unwrap_or_default();
                         });
+                        self.sample_rate_input = self.config.data().sample_rate.to_string();
                         return self.update_audio_stream();
                     }
                 }
@@ -682,6 +1048,11 @@ impl Application for AppState {
                     self.config.update(|s| s.theme = app_theme);
                     return cmd;
                 }
+                ConfigMsg::LogLevel(log_level) => {
+                    self.config.update(|s| s.log_level = log_level);
+                    logging::set_level(log_level);
+                    info!("log level set to {log_level}");
+                }
                 ConfigMsg::ToggleAboutWindow => match &self.about_window {
                     Some(about_window) => {
                         let id = about_window.window_id;
@@ -753,13 +1124,93 @@ impl Application for AppState {
                         .update(|c| c.speex_dereverb_level = speex_dereverb_level);
                     return self.update_audio_stream();
                 }
-                ConfigMsg::PostAudioEffect(post_effect) => {
-                    self.config.update(|c| c.post_effect = post_effect);
+                ConfigMsg::AddPostEffect(preset) => {
+                    if config.post_effects.len() < MAX_POST_EFFECTS
+                        && let Some(entry) = EffectChainEntry::new(preset)
+                    {
+                        self.config.update(|c| c.post_effects.push(entry));
+                        return self.update_audio_stream();
+                    }
+                }
+                ConfigMsg::RemovePostEffect(index) => {
+                    if index < config.post_effects.len() {
+                        self.config.update(|c| {
+                            c.post_effects.remove(index);
+                        });
+                        return self.update_audio_stream();
+                    }
+                }
+                ConfigMsg::MovePostEffectUp(index) => {
+                    if index > 0 && index < config.post_effects.len() {
+                        self.config
+                            .update(|c| c.post_effects.swap(index - 1, index));
+                        return self.update_audio_stream();
+                    }
+                }
+                ConfigMsg::MovePostEffectDown(index) => {
+                    if index + 1 < config.post_effects.len() {
+                        self.config
+                            .update(|c| c.post_effects.swap(index, index + 1));
+                        return self.update_audio_stream();
+                    }
+                }
+                ConfigMsg::PostEffectBypass(index, bypass) => {
+                    if index < config.post_effects.len() {
+                        self.config
+                            .update(|c| c.post_effects[index].bypass = bypass);
+                        return self.update_audio_stream();
+                    }
+                }
+                ConfigMsg::PostEffectParams(index, params) => {
+                    if index < config.post_effects.len() {
+                        self.config
+                            .update(|c| c.post_effects[index].params = params);
+                        return self.update_audio_stream();
+                    }
+                }
+                ConfigMsg::ResetPostEffectParams(index) => {
+                    if let Some(params) = config
+                        .post_effects
+                        .get(index)
+                        .and_then(|entry| entry.preset.preset())
+                    {
+                        self.config
+                            .update(|c| c.post_effects[index].params = params);
+                        return self.update_audio_stream();
+                    }
+                }
+                ConfigMsg::Equalizer(equalizer) => {
+                    self.config.update(|c| c.equalizer = equalizer);
                     return self.update_audio_stream();
                 }
+                ConfigMsg::EqualizerAddBand => {
+                    if config.equalizer_bands.len() < MAX_EQ_BANDS {
+                        self.config
+                            .update(|c| c.equalizer_bands.push(EqBand::default()));
+                        return self.update_audio_stream();
+                    }
+                }
+                ConfigMsg::EqualizerRemoveBand(index) => {
+                    if index < config.equalizer_bands.len() {
+                        self.config.update(|c| {
+                            c.equalizer_bands.remove(index);
+                        });
+                        return self.update_audio_stream();
+                    }
+                }
+                ConfigMsg::EqualizerBand(index, band) => {
+                    if index < config.equalizer_bands.len() {
+                        self.config.update(|c| c.equalizer_bands[index] = band);
+                        return self.update_audio_stream();
+                    }
+                }
                 ConfigMsg::StartMinimized(start_minimized) => {
                     self.config.update(|s| s.start_minimized = start_minimized);
                 }
+                ConfigMsg::Notification(kind, enabled) => {
+                    self.config
+                        .update(|s| s.set_notification_enabled(kind, enabled));
+                }
             },
             AppMsg::HideWindow => {
                 let mut effects = Vec::new();
@@ -790,14 +1241,7 @@ impl Application for AppState {
                 }
 
                 if !self.launched_automatically && !self.has_shown_minimize_notification {
-                    let _ = Notification::new()
-                        .summary("AndroidMic")
-                        .body(&fl!("minimized_to_tray"))
-                        .auto_icon()
-                        .show()
-                        .map_err(|e| {
-                            error!("failed to show notification: {e}");
-                        });
+                    notifications::show(&fl!("minimized_to_tray"));
                     self.has_shown_minimize_notification = true;
                 }
 
@@ -807,6 +1251,16 @@ impl Application for AppState {
             }
             AppMsg::Menu(menu_msg) => match menu_msg {
                 super::message::MenuMsg::ClearLogs => self.logs.clear(),
+                super::message::MenuMsg::CreateDiagnosticsBundle => {
+                    return self.create_diagnostics_bundle();
+                }
+                super::message::MenuMsg::OpenLogFolder => {
+                    if let Some(log_dir) = Path::new(&self.log_path).parent()
+                        && let Err(e) = open::that(log_dir)
+                    {
+                        error!("can't open log folder: {e}");
+                    }
+                }
             },
             AppMsg::LinkClicked(mut url) => {
                 if url.starts_with(CONFIG_PATH_WORKAROUND) {
@@ -823,7 +1277,6 @@ impl Application for AppState {
                     error!("{e}");
                 }
             }
-            #[cfg(not(target_os = "linux"))]
             AppMsg::SystemTray(tray_msg) => match tray_msg {
                 SystemTrayMsg::Show => {
                     if let Some(main_window) = &self.main_window {
@@ -844,6 +1297,7 @@ impl Application for AppState {
                 }
                 SystemTrayMsg::Connect => return self.connect(),
                 SystemTrayMsg::Disconnect => return self.disconnect(),
+                SystemTrayMsg::SwitchProfile(name) => return self.switch_profile(&name),
             },
             AppMsg::ShowWindow => {
                 if let Some(main_window) = &self.main_window {
@@ -893,17 +1347,19 @@ impl Application for AppState {
     }
 
     fn subscription(&self) -> cosmic::iced::Subscription<Self::Message> {
-        #[allow(unused_mut)]
         let mut subscriptions = vec![Subscription::run(|| streamer::sub().map(AppMsg::Streamer))];
 
-        #[cfg(not(target_os = "linux"))]
         if let Some(system_tray_stream) = &self.system_tray_stream {
             subscriptions.push(Subscription::run_with_id(
                 "system-tray",
-                system_tray_stream.clone().sub().map(AppMsg::SystemTray),
+                system_tray_stream.clone().sub(),
             ));
         }
 
+        if self.connection_state == ConnectionState::Connected {
+            subscriptions.push(time::every(Duration::from_secs(1)).map(|_| AppMsg::CheckOutput));
+        }
+
         Subscription::batch(subscriptions)
     }
 
@@ -921,11 +1377,11 @@ impl Application for AppState {
         if let Some(window) = &self.main_window
             && window.window_id == id
         {
-            #[cfg(not(target_os = "linux"))]
-            return Some(AppMsg::HideWindow);
-
-            #[cfg(target_os = "linux")]
-            return Some(AppMsg::Exit);
+            return Some(if self.system_tray.is_some() {
+                AppMsg::HideWindow
+            } else {
+                AppMsg::Exit
+            });
         }
 
         None
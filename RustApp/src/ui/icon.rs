@@ -73,3 +73,37 @@ macro_rules! tray_icon {
     }};
     ($name:literal) => {{ tray_icon!($name, 32, 32) }};
 }
+
+#[cfg(target_os = "linux")]
+#[macro_export]
+macro_rules! tray_icon {
+    ($name:literal, $width:expr, $height:expr) => {{
+        let svg = include_bytes!(concat!("../../res/icons/", $name, ".svg"));
+        let opt = resvg::usvg::Options::default();
+        let tree = resvg::usvg::Tree::from_data(svg, &opt).unwrap();
+        let viewbox = tree.size();
+
+        let mut pixmap = resvg::tiny_skia::Pixmap::new($width, $height).unwrap();
+        resvg::render(
+            &tree,
+            resvg::tiny_skia::Transform::from_scale(
+                $width as f32 / viewbox.width(),
+                $height as f32 / viewbox.height(),
+            ),
+            &mut pixmap.as_mut(),
+        );
+
+        // StatusNotifierItem wants ARGB32, in network byte order
+        let mut argb = pixmap.data().to_vec();
+        for pixel in argb.chunks_exact_mut(4) {
+            pixel.rotate_right(1);
+        }
+
+        ksni::Icon {
+            width: $width as i32,
+            height: $height as i32,
+            data: argb,
+        }
+    }};
+    ($name:literal) => {{ tray_icon!($name, 32, 32) }};
+}
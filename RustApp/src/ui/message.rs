@@ -1,14 +1,19 @@
 use super::app::AudioDevice;
-#[cfg(not(target_os = "linux"))]
 use super::tray::SystemTrayMsg;
 use crate::{
     config::{
-        AppTheme, AudioEffect, AudioFormat, ChannelCount, ConnectionMode, DenoiseKind,
-        NetworkAdapter, SampleRate,
+        AppTheme, AudioEffect, AudioFormat, AutostartMethod, ChannelCount, ConnectionMode,
+        DenoiseKind, DitherMode, EffectParams, EqBand, LogLevel, NetworkAdapter, NotificationKind,
+        ResamplerQuality, SampleRate,
     },
     streamer::StreamerMsg,
 };
 
+#[cfg(feature = "usb")]
+use crate::config::UsbDeviceId;
+#[cfg(feature = "adb")]
+use crate::streamer::AdbDevice;
+
 #[derive(Debug, Clone)]
 pub enum AppMsg {
     ChangeConnectionMode(ConnectionMode),
@@ -17,31 +22,60 @@ pub enum AppMsg {
     #[cfg(target_os = "linux")]
     SelectedHost(HostId),
     Adapter(NetworkAdapter),
+    #[cfg(feature = "adb")]
+    RefreshAdbDevices,
+    #[cfg(feature = "adb")]
+    AdbDevices(Result<Vec<AdbDevice>, String>),
+    #[cfg(feature = "adb")]
+    AdbDevice(AdbDevice),
+    #[cfg(feature = "usb")]
+    RefreshUsbDevices,
+    #[cfg(feature = "usb")]
+    UsbDevices(Result<Vec<UsbDeviceId>, String>),
+    #[cfg(feature = "usb")]
+    UsbDevice(UsbDeviceId),
     Connect,
     Stop,
+    Mute(bool),
+    /// Periodic check of the output stream, while connected
+    CheckOutput,
     ToggleSettingsWindow,
     Config(ConfigMsg),
+    /// Whether start at login was enabled, and the result
+    StartAtLogin(bool, Result<(), String>),
     RefreshAudioDevices,
     RefreshNetworkAdapters,
     HideWindow,
     ShowWindow,
     Menu(MenuMsg),
     LinkClicked(String),
-    #[cfg(not(target_os = "linux"))]
     SystemTray(SystemTrayMsg),
     Exit,
 }
 
 #[derive(Debug, Clone)]
 pub enum ConfigMsg {
+    SwitchProfile(String),
+    ProfileNameInput(String),
+    ProfileIncludeConnection(bool),
+    ProfileIncludeDsp(bool),
+    SaveProfile,
+    DeleteProfile(String),
     SampleRate(SampleRate),
+    SampleRateTextInput(String),
+    SampleRateSave,
+    ResamplerQuality(ResamplerQuality),
+    Dither(DitherMode),
     ChannelCount(ChannelCount),
     AudioFormat(AudioFormat),
     UseRecommendedFormat,
     ResetDenoiseSettings,
     StartAtLogin(bool),
+    AutostartMethod(AutostartMethod),
     StartMinimized(bool),
     AutoConnect(bool),
+    AdbLaunchApp(bool),
+    Notification(NotificationKind, bool),
     DeNoise(bool),
     DeNoiseKind(DenoiseKind),
     SpeexNoiseSuppress(i32),
@@ -52,17 +86,30 @@ pub enum ConfigMsg {
     SpeexDereverbEnabled(bool),
     SpeexDereverbLevel(f32),
     Theme(AppTheme),
+    LogLevel(LogLevel),
     Amplify(bool),
     AmplifyValue(f32),
     ToggleAboutWindow,
     PortTextInput(String),
     PortSave,
-    PostAudioEffect(AudioEffect),
+    AddPostEffect(AudioEffect),
+    RemovePostEffect(usize),
+    MovePostEffectUp(usize),
+    MovePostEffectDown(usize),
+    PostEffectBypass(usize, bool),
+    PostEffectParams(usize, EffectParams),
+    ResetPostEffectParams(usize),
+    Equalizer(bool),
+    EqualizerAddBand,
+    EqualizerRemoveBand(usize),
+    EqualizerBand(usize, EqBand),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Copy)]
 pub enum MenuMsg {
     ClearLogs,
+    OpenLogFolder,
+    CreateDiagnosticsBundle,
 }
 
 use cosmic::widget::menu::action::MenuAction;
@@ -1,8 +1,7 @@
 pub mod app;
 mod icon;
 mod message;
-
-#[cfg(not(target_os = "linux"))]
+mod notifications;
 mod tray;
 mod view;
 mod wave;
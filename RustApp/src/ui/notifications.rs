@@ -0,0 +1,80 @@
+//! Desktop notifications, most of them can be turned off in the settings
+
+use notify_rust::Notification;
+
+use crate::config::{Config, NotificationKind};
+
+/// A second with at least this many underruns is a bad second
+const UNDERRUNS_PER_SECOND: u32 = 10;
+/// Consecutive bad seconds before the user is told
+const STORM_SECONDS: u32 = 3;
+
+pub fn show(body: &str) {
+    let _ = Notification::new()
+        .summary("AndroidMic")
+        .body(body)
+        .auto_icon()
+        .show()
+        .map_err(|e| {
+            error!("failed to show notification: {e}");
+        });
+}
+
+pub fn notify(config: &Config, kind: NotificationKind, body: &str) {
+    if config.notification_enabled(kind) {
+        show(body);
+    }
+}
+
+/// A few underruns are expected, when the phone is late by a packet.
+/// Many of them in a row mean the audio is choppy.
+#[derive(Debug, Default)]
+pub struct UnderrunMonitor {
+    bad_seconds: u32,
+}
+
+impl UnderrunMonitor {
+    /// Called every second with the underruns of that second.
+    /// Returns `true` once per storm, when it starts.
+    pub fn record(&mut self, underruns: u32) -> bool {
+        if underruns >= UNDERRUNS_PER_SECOND {
+            self.bad_seconds += 1;
+        } else {
+            self.bad_seconds = 0;
+        }
+        self.bad_seconds == STORM_SECONDS
+    }
+
+    pub fn reset(&mut self) {
+        self.bad_seconds = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn underrun_storm_is_reported_once() {
+        let mut monitor = UnderrunMonitor::default();
+
+        // isolated bad seconds
+        assert!(!monitor.record(50));
+        assert!(!monitor.record(0));
+        assert!(!monitor.record(50));
+        assert!(!monitor.record(50));
+        assert!(!monitor.record(UNDERRUNS_PER_SECOND - 1));
+
+        let storm: Vec<bool> = (0..10).map(|_| monitor.record(100)).collect();
+        assert_eq!(storm.iter().filter(|started| **started).count(), 1);
+        assert!(storm[STORM_SECONDS as usize - 1]);
+
+        // a new storm after recovery
+        assert!(!monitor.record(0));
+        assert!(
+            (0..STORM_SECONDS)
+                .map(|_| monitor.record(100))
+                .any(|started| started)
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use cosmic::iced::{futures::Stream, stream};
+use futures::SinkExt;
+use std::sync::Arc;
+use tokio::sync::{Mutex, mpsc};
+
+use super::{
+    app::AudioDevice,
+    message::{AppMsg, ConfigMsg},
+};
+use crate::{
+    config::{AudioEffect, ConnectionMode, MAX_POST_EFFECTS},
+    fl,
+};
+
+#[cfg(not(target_os = "linux"))]
+mod native;
+#[cfg(target_os = "linux")]
+mod sni;
+
+#[cfg(not(target_os = "linux"))]
+pub use native::SystemTray;
+#[cfg(target_os = "linux")]
+pub use sni::SystemTray;
+
+#[derive(Debug, Clone)]
+pub enum SystemTrayMsg {
+    Show,
+    Connect,
+    Disconnect,
+    SwitchProfile(String),
+    Exit,
+}
+
+/// What the tray shows, a snapshot of the app state
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrayState {
+    pub status: String,
+    pub disconnected: bool,
+    pub connection_mode: ConnectionMode,
+    pub audio_devices: Vec<AudioDevice>,
+    pub device_id: Option<String>,
+    pub denoise: bool,
+    /// Preset and enabled state of each effect of the chain
+    pub post_effects: Vec<(AudioEffect, bool)>,
+    pub muted: bool,
+    pub profiles: Vec<String>,
+    pub active_profile: Option<String>,
+}
+
+/// Menu model shared by the tray backends. Items send the same messages as the windows.
+#[derive(Debug, Clone)]
+enum TrayItem {
+    Action {
+        label: String,
+        enabled: bool,
+        msg: AppMsg,
+    },
+    Check {
+        label: String,
+        checked: bool,
+        msg: AppMsg,
+    },
+    /// Disabled when empty
+    Submenu {
+        label: String,
+        items: Vec<TrayItem>,
+    },
+    Separator,
+}
+
+impl TrayItem {
+    fn action(label: String, msg: AppMsg) -> Self {
+        TrayItem::Action {
+            label,
+            enabled: true,
+            msg,
+        }
+    }
+
+    fn check(label: String, checked: bool, msg: AppMsg) -> Self {
+        TrayItem::Check {
+            label,
+            checked,
+            msg,
+        }
+    }
+}
+
+impl TrayState {
+    fn menu(&self) -> Vec<TrayItem> {
+        let connection_modes = ConnectionMode::VALUES
+            .iter()
+            .map(|mode| {
+                TrayItem::check(
+                    mode.label(),
+                    *mode == self.connection_mode,
+                    AppMsg::ChangeConnectionMode(*mode),
+                )
+            })
+            .collect();
+
+        let audio_devices = self
+            .audio_devices
+            .iter()
+            .map(|device| {
+                TrayItem::check(
+                    device.name.clone(),
+                    self.device_id.as_ref() == Some(&device.id),
+                    AppMsg::Device(device.clone()),
+                )
+            })
+            .collect();
+
+        let mut effects: Vec<_> = self
+            .post_effects
+            .iter()
+            .enumerate()
+            .map(|(index, (preset, enabled))| {
+                TrayItem::check(
+                    preset.to_string(),
+                    *enabled,
+                    AppMsg::Config(ConfigMsg::PostEffectBypass(index, *enabled)),
+                )
+            })
+            .collect();
+        if self.post_effects.len() < MAX_POST_EFFECTS {
+            if !effects.is_empty() {
+                effects.push(TrayItem::Separator);
+            }
+            effects.push(TrayItem::Submenu {
+                label: fl!("effect_add"),
+                items: AudioEffect::VALUES
+                    .iter()
+                    .filter(|effect| **effect != AudioEffect::NoEffect)
+                    .map(|effect| {
+                        TrayItem::action(
+                            effect.to_string(),
+                            AppMsg::Config(ConfigMsg::AddPostEffect(*effect)),
+                        )
+                    })
+                    .collect(),
+            });
+        }
+
+        let profiles = self
+            .profiles
+            .iter()
+            .map(|name| {
+                TrayItem::check(
+                    name.clone(),
+                    self.active_profile.as_ref() == Some(name),
+                    AppMsg::SystemTray(SystemTrayMsg::SwitchProfile(name.clone())),
+                )
+            })
+            .collect();
+
+        vec![
+            TrayItem::action(
+                fl!("tray_show_window"),
+                AppMsg::SystemTray(SystemTrayMsg::Show),
+            ),
+            TrayItem::Action {
+                label: fl!("tray_connect"),
+                enabled: self.disconnected,
+                msg: AppMsg::SystemTray(SystemTrayMsg::Connect),
+            },
+            TrayItem::Action {
+                label: fl!("tray_disconnect"),
+                enabled: !self.disconnected,
+                msg: AppMsg::SystemTray(SystemTrayMsg::Disconnect),
+            },
+            TrayItem::Separator,
+            TrayItem::Submenu {
+                label: fl!("tray_connection_mode"),
+                items: connection_modes,
+            },
+            TrayItem::Submenu {
+                label: fl!("tray_output_device"),
+                items: audio_devices,
+            },
+            TrayItem::check(
+                fl!("denoise"),
+                self.denoise,
+                AppMsg::Config(ConfigMsg::DeNoise(!self.denoise)),
+            ),
+            TrayItem::Submenu {
+                label: fl!("tray_effects"),
+                items: effects,
+            },
+            TrayItem::check(fl!("tray_mute"), self.muted, AppMsg::Mute(!self.muted)),
+            TrayItem::Separator,
+            TrayItem::Submenu {
+                label: fl!("tray_profiles"),
+                items: profiles,
+            },
+            TrayItem::Separator,
+            TrayItem::action(fl!("tray_exit"), AppMsg::SystemTray(SystemTrayMsg::Exit)),
+        ]
+    }
+}
+
+#[derive(Clone)]
+pub struct SystemTrayStream {
+    receiver: Arc<Mutex<mpsc::UnboundedReceiver<AppMsg>>>,
+}
+
+impl SystemTrayStream {
+    fn new(receiver: mpsc::UnboundedReceiver<AppMsg>) -> Self {
+        Self {
+            receiver: Arc::new(Mutex::new(receiver)),
+        }
+    }
+
+    pub fn sub(self) -> impl Stream<Item = AppMsg> {
+        let receiver_arc = self.receiver.clone();
+
+        stream::channel(1, |mut sender| async move {
+            loop {
+                let mut receiver = receiver_arc.lock().await;
+                if let Some(msg) = receiver.recv().await {
+                    if sender.send(msg).await.is_err() {
+                        break;
+                    }
+                } else {
+                    break;
+                }
+            }
+        })
+    }
+}
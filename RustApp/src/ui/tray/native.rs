@@ -0,0 +1,139 @@
+//! Tray of Windows and macOS, with tray-icon
+
+use std::sync::{Arc, Mutex as StdMutex};
+use tokio::sync::mpsc;
+
+use tray_icon::{
+    TrayIcon, TrayIconBuilder, TrayIconEvent,
+    menu::{
+        CheckMenuItem, IsMenuItem, Menu, MenuEvent, MenuId, MenuItem, PredefinedMenuItem, Submenu,
+    },
+};
+
+use super::{SystemTrayMsg, SystemTrayStream, TrayItem, TrayState};
+use crate::{tray_icon, ui::message::AppMsg};
+
+type MenuMessages = Arc<StdMutex<Vec<(MenuId, AppMsg)>>>;
+
+pub struct SystemTray {
+    tray_icon: TrayIcon,
+    state: TrayState,
+    /// Read by the menu event handler, to know which item was clicked
+    menu_messages: MenuMessages,
+}
+
+fn build_items(
+    items: &[TrayItem],
+    messages: &mut Vec<(MenuId, AppMsg)>,
+) -> anyhow::Result<Vec<Box<dyn IsMenuItem>>> {
+    let mut menu_items: Vec<Box<dyn IsMenuItem>> = Vec::new();
+
+    for item in items {
+        match item {
+            TrayItem::Action {
+                label,
+                enabled,
+                msg,
+            } => {
+                let item = MenuItem::new(label, *enabled, None);
+                messages.push((item.id().clone(), msg.clone()));
+                menu_items.push(Box::new(item));
+            }
+            TrayItem::Check {
+                label,
+                checked,
+                msg,
+            } => {
+                let item = CheckMenuItem::new(label, true, *checked, None);
+                messages.push((item.id().clone(), msg.clone()));
+                menu_items.push(Box::new(item));
+            }
+            TrayItem::Submenu { label, items } => {
+                let children = build_items(items, messages)?;
+                let children: Vec<&dyn IsMenuItem> = children.iter().map(|c| c.as_ref()).collect();
+                menu_items.push(Box::new(Submenu::with_items(
+                    label,
+                    !items.is_empty(),
+                    &children,
+                )?));
+            }
+            TrayItem::Separator => menu_items.push(Box::new(PredefinedMenuItem::separator())),
+        }
+    }
+
+    Ok(menu_items)
+}
+
+fn build_menu(state: &TrayState) -> anyhow::Result<(Menu, Vec<(MenuId, AppMsg)>)> {
+    let mut messages = Vec::new();
+    let items = build_items(&state.menu(), &mut messages)?;
+    let items: Vec<&dyn IsMenuItem> = items.iter().map(|item| item.as_ref()).collect();
+    Ok((Menu::with_items(&items)?, messages))
+}
+
+impl SystemTray {
+    pub fn new(state: TrayState) -> anyhow::Result<(Self, SystemTrayStream)> {
+        let (menu, messages) = build_menu(&state)?;
+
+        let tray_icon = TrayIconBuilder::new()
+            .with_menu(Box::new(menu))
+            .with_tooltip(format!("AndroidMic - {}", state.status))
+            .with_icon(tray_icon!("icon")?)
+            .build()?;
+
+        // set up event channel
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        let menu_messages: MenuMessages = Arc::new(StdMutex::new(messages));
+
+        let menu_sender = sender.clone();
+        let handler_messages = menu_messages.clone();
+        MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+            let messages = handler_messages.lock().unwrap();
+            if let Some((_, msg)) = messages.iter().find(|(id, _)| *id == event.id) {
+                let _ = menu_sender.send(msg.clone());
+            }
+        }));
+
+        let tray_sender = sender.clone();
+        TrayIconEvent::set_event_handler(Some(move |event: TrayIconEvent| {
+            if let TrayIconEvent::DoubleClick { .. } = event {
+                let _ = tray_sender.send(AppMsg::SystemTray(SystemTrayMsg::Show));
+            }
+        }));
+
+        Ok((
+            Self {
+                tray_icon,
+                state,
+                menu_messages,
+            },
+            SystemTrayStream::new(receiver),
+        ))
+    }
+
+    /// Rebuild the menu, the items can't all be updated in place
+    pub fn update(&mut self, state: TrayState) {
+        if state == self.state {
+            return;
+        }
+
+        if state.status != self.state.status {
+            let _ = self
+                .tray_icon
+                .set_tooltip(Some(format!("AndroidMic - {}", state.status)))
+                .map_err(|e| {
+                    error!("failed to set tray icon tooltip: {e}");
+                });
+        }
+
+        match build_menu(&state) {
+            Ok((menu, messages)) => {
+                *self.menu_messages.lock().unwrap() = messages;
+                self.tray_icon.set_menu(Some(Box::new(menu)));
+            }
+            Err(e) => error!("failed to build tray menu: {e}"),
+        }
+        self.state = state;
+    }
+}
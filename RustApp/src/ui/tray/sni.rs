@@ -0,0 +1,274 @@
+//! Tray of Linux, with the StatusNotifierItem DBus protocol
+//! https://www.freedesktop.org/wiki/Specifications/StatusNotifierItem/
+
+use ksni::{
+    ToolTip,
+    blocking::{Handle, TrayMethods},
+    menu::{CheckmarkItem, MenuItem, StandardItem, SubMenu},
+};
+use tokio::sync::mpsc;
+
+use super::{SystemTrayMsg, SystemTrayStream, TrayItem, TrayState};
+use crate::{tray_icon, ui::message::AppMsg, utils::APP_ID};
+
+/// State read by the tray host, through DBus
+struct Tray {
+    sender: mpsc::UnboundedSender<AppMsg>,
+    icon: ksni::Icon,
+    state: TrayState,
+    /// Kept to find the message of a clicked item
+    items: Vec<TrayItem>,
+}
+
+impl Tray {
+    fn send(&self, msg: AppMsg) {
+        let _ = self.sender.send(msg);
+    }
+
+    /// Send the message of the item at `path`, the indexes in each submenu
+    fn activate_item(&self, path: &[usize]) {
+        let mut items = &self.items;
+        for (depth, index) in path.iter().enumerate() {
+            match items.get(*index) {
+                Some(TrayItem::Submenu { items: submenu, .. }) => items = submenu,
+                Some(TrayItem::Action { msg, .. } | TrayItem::Check { msg, .. })
+                    if depth + 1 == path.len() =>
+                {
+                    self.send(msg.clone());
+                }
+                _ => return,
+            }
+        }
+    }
+}
+
+/// The activate callbacks must be `Sync`, unlike `AppMsg`: they only capture the path
+fn to_ksni_items(items: &[TrayItem], parent: &[usize]) -> Vec<MenuItem<Tray>> {
+    items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let path = [parent, &[index]].concat();
+            match item {
+                TrayItem::Action { label, enabled, .. } => StandardItem {
+                    label: label.clone(),
+                    enabled: *enabled,
+                    activate: Box::new(move |this: &mut Tray| this.activate_item(&path)),
+                    ..Default::default()
+                }
+                .into(),
+                TrayItem::Check { label, checked, .. } => CheckmarkItem {
+                    label: label.clone(),
+                    checked: *checked,
+                    activate: Box::new(move |this: &mut Tray| this.activate_item(&path)),
+                    ..Default::default()
+                }
+                .into(),
+                TrayItem::Submenu { label, items } => SubMenu {
+                    label: label.clone(),
+                    enabled: !items.is_empty(),
+                    submenu: to_ksni_items(items, &path),
+                    ..Default::default()
+                }
+                .into(),
+                TrayItem::Separator => MenuItem::Separator,
+            }
+        })
+        .collect()
+}
+
+impl ksni::Tray for Tray {
+    fn id(&self) -> String {
+        APP_ID.into()
+    }
+
+    fn title(&self) -> String {
+        "AndroidMic".into()
+    }
+
+    fn icon_name(&self) -> String {
+        // installed with the desktop entry, the pixmap is used otherwise
+        APP_ID.into()
+    }
+
+    fn icon_pixmap(&self) -> Vec<ksni::Icon> {
+        vec![self.icon.clone()]
+    }
+
+    fn tool_tip(&self) -> ToolTip {
+        ToolTip {
+            title: "AndroidMic".into(),
+            description: self.state.status.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn activate(&mut self, _x: i32, _y: i32) {
+        self.send(AppMsg::SystemTray(SystemTrayMsg::Show));
+    }
+
+    fn menu(&self) -> Vec<MenuItem<Self>> {
+        to_ksni_items(&self.items, &[])
+    }
+}
+
+pub struct SystemTray {
+    handle: Handle<Tray>,
+    state: TrayState,
+}
+
+impl SystemTray {
+    /// Fails when no StatusNotifierWatcher is running, like on GNOME without extension
+    pub fn new(state: TrayState) -> anyhow::Result<(Self, SystemTrayStream)> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        let tray = Tray {
+            sender,
+            icon: tray_icon!("icon"),
+            items: state.menu(),
+            state: state.clone(),
+        };
+        let handle = tray.spawn()?;
+
+        Ok((Self { handle, state }, SystemTrayStream::new(receiver)))
+    }
+
+    pub fn update(&mut self, state: TrayState) {
+        if state == self.state {
+            return;
+        }
+        self.state = state.clone();
+        self.handle.update(move |tray| {
+            tray.items = state.menu();
+            tray.state = state;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use zbus::{
+        blocking::{Connection, Proxy, connection},
+        zvariant::{OwnedValue, Value},
+    };
+
+    use super::*;
+
+    const WATCHER_NAME: &str = "org.kde.StatusNotifierWatcher";
+    const WATCHER_PATH: &str = "/StatusNotifierWatcher";
+    const ITEM_INTERFACE: &str = "org.kde.StatusNotifierItem";
+
+    /// Stands in for the panel of a desktop environment
+    #[derive(Default)]
+    struct Watcher {
+        items: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[zbus::interface(name = "org.kde.StatusNotifierWatcher")]
+    impl Watcher {
+        fn register_status_notifier_item(&self, service: &str) {
+            self.items.lock().unwrap().push(service.to_string());
+        }
+
+        fn register_status_notifier_host(&self, _service: &str) {}
+
+        #[zbus(property)]
+        fn registered_status_notifier_items(&self) -> Vec<String> {
+            self.items.lock().unwrap().clone()
+        }
+
+        #[zbus(property)]
+        fn is_status_notifier_host_registered(&self) -> bool {
+            true
+        }
+
+        #[zbus(property)]
+        fn protocol_version(&self) -> i32 {
+            0
+        }
+    }
+
+    fn wait_for<T>(mut f: impl FnMut() -> Option<T>) -> T {
+        let start = Instant::now();
+        loop {
+            if let Some(value) = f() {
+                return value;
+            }
+            assert!(start.elapsed() < Duration::from_secs(5), "timeout");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn tool_tip_description(item: &Proxy) -> Option<String> {
+        let tool_tip: OwnedValue = item.get_property("ToolTip").ok()?;
+        // (icon name, icon pixmaps, title, description)
+        match &*tool_tip {
+            Value::Structure(tool_tip) => match tool_tip.fields().get(3)? {
+                Value::Str(description) => Some(description.to_string()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Run with `just test-tray`, in a session bus of its own
+    #[test]
+    #[ignore = "needs a session bus"]
+    fn tray_on_session_bus() {
+        let connection = Connection::session().expect("no session bus");
+
+        // a desktop session already has a watcher, use it
+        let _watcher = connection::Builder::session()
+            .and_then(|builder| builder.name(WATCHER_NAME))
+            .and_then(|builder| builder.serve_at(WATCHER_PATH, Watcher::default()))
+            .and_then(|builder| builder.build())
+            .ok();
+
+        let state = TrayState {
+            status: "disconnected".into(),
+            disconnected: true,
+            connection_mode: Default::default(),
+            audio_devices: Vec::new(),
+            device_id: None,
+            denoise: false,
+            post_effects: Vec::new(),
+            muted: false,
+            profiles: Vec::new(),
+            active_profile: None,
+        };
+        let (mut tray, stream) = SystemTray::new(state.clone()).unwrap();
+        tray.update(TrayState {
+            status: "connected".into(),
+            disconnected: false,
+            ..state
+        });
+
+        let watcher = Proxy::new(&connection, WATCHER_NAME, WATCHER_PATH, WATCHER_NAME).unwrap();
+        let pid = std::process::id().to_string();
+        let service = wait_for(|| {
+            let items: Vec<String> = watcher.get_property("RegisteredStatusNotifierItems").ok()?;
+            items.into_iter().find(|item| item.contains(&pid))
+        });
+        // some watchers append the object path
+        let service = service.split('/').next().unwrap().to_string();
+
+        let item = Proxy::new(
+            &connection,
+            service.as_str(),
+            "/StatusNotifierItem",
+            ITEM_INTERFACE,
+        )
+        .unwrap();
+        wait_for(|| (tool_tip_description(&item)? == "connected").then_some(()));
+
+        item.call_method("Activate", &(0, 0)).unwrap();
+        let msg = stream.receiver.try_lock().unwrap().blocking_recv();
+        assert!(matches!(msg, Some(AppMsg::SystemTray(SystemTrayMsg::Show))));
+    }
+}
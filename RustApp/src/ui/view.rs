@@ -1,4 +1,4 @@
-use std::{collections::HashMap, sync::LazyLock};
+use std::{collections::HashMap, ops::RangeInclusive, sync::LazyLock};
 
 use cosmic::{
     Element,
@@ -20,7 +20,11 @@ use super::{
 };
 use crate::{
     config::{
-        AppTheme, AudioEffect, AudioFormat, ChannelCount, ConnectionMode, DenoiseKind, SampleRate,
+        AppTheme, AudioEffect, AudioFormat, AutostartMethod, ChannelCount, ConnectionMode,
+        DenoiseKind, DitherMode, EchoParams, EffectChainEntry, EffectParams, EqBand, EqBandKind,
+        FlangerParams, LogLevel, MAX_EQ_BANDS, MAX_POST_EFFECTS, NotificationKind, PhaserParams,
+        PitchShiftParams, PopstarParams, ResamplerQuality, ReverbParams, SampleRate, VocoderParams,
+        WalkieTalkieParams,
     },
     fl,
     ui::message::MenuMsg,
@@ -55,6 +59,28 @@ pub fn main_window(app: &AppState) -> Element<'_, AppMsg> {
                         || connection_mode == ConnectionMode::Udp)
                         .then(|| network_adapter(app)),
                 )
+                .push_maybe({
+                    #[cfg(feature = "adb")]
+                    {
+                        (connection_mode == ConnectionMode::Adb).then(|| adb_device(app))
+                    }
+
+                    #[cfg(not(feature = "adb"))]
+                    {
+                        Option::<Element<AppMsg>>::None
+                    }
+                })
+                .push_maybe({
+                    #[cfg(feature = "usb")]
+                    {
+                        (connection_mode == ConnectionMode::Usb).then(|| usb_device(app))
+                    }
+
+                    #[cfg(not(feature = "usb"))]
+                    {
+                        Option::<Element<AppMsg>>::None
+                    }
+                })
                 .push(audio(app))
                 .push(vertical_space())
                 .push(connection_type(app)),
@@ -86,11 +112,15 @@ fn logs(app: &AppState) -> Element<'_, AppMsg> {
         .class(cosmic::theme::Container::Card),
         Some(menu::items(
             &HashMap::new(),
-            vec![menu::Item::Button(
-                fl!("clear_logs"),
-                None,
-                MenuMsg::ClearLogs,
-            )],
+            vec![
+                menu::Item::Button(fl!("clear_logs"), None, MenuMsg::ClearLogs),
+                menu::Item::Button(fl!("open_log_folder"), None, MenuMsg::OpenLogFolder),
+                menu::Item::Button(
+                    fl!("create_diagnostics_bundle"),
+                    None,
+                    MenuMsg::CreateDiagnosticsBundle,
+                ),
+            ],
         )),
     )
     .into()
@@ -187,6 +217,80 @@ fn network_adapter(app: &AppState) -> Element<'_, AppMsg> {
         .into()
 }
 
+#[cfg(feature = "adb")]
+fn adb_device(app: &AppState) -> Element<'_, AppMsg> {
+    let serial = app.config.data().adb_serial.clone();
+    let selected = app
+        .adb_devices
+        .iter()
+        .find(|device| Some(&device.serial) == serial.as_ref());
+
+    column()
+        .spacing(20)
+        .align_x(Horizontal::Center)
+        .push(text::title4(fl!("adb_device")))
+        .push(
+            row()
+                .width(Length::Fill)
+                .spacing(5)
+                .push(
+                    pick_list(app.adb_devices.clone(), selected, AppMsg::AdbDevice)
+                        .placeholder(serial.unwrap_or_else(|| fl!("adb_device_auto")))
+                        .width(Length::Fill),
+                )
+                .push(
+                    widget_icon_button!("refresh24")
+                        .on_press(AppMsg::RefreshAdbDevices)
+                        .class(cosmic::theme::Button::Text)
+                        .width(Length::Shrink),
+                ),
+        )
+        .push(
+            row()
+                .align_y(Vertical::Center)
+                .push(text(fl!("adb_launch_app")))
+                .push(horizontal_space())
+                .push(
+                    toggler(app.config.data().adb_launch_app).on_toggle(|launch_app| {
+                        AppMsg::Config(ConfigMsg::AdbLaunchApp(launch_app))
+                    }),
+                ),
+        )
+        .into()
+}
+
+#[cfg(feature = "usb")]
+fn usb_device(app: &AppState) -> Element<'_, AppMsg> {
+    let device = app.config.data().usb_device.clone();
+    let selected = app.usb_devices.iter().find(|d| Some(d) == device.as_ref());
+
+    column()
+        .spacing(20)
+        .align_x(Horizontal::Center)
+        .push(text::title4(fl!("usb_device")))
+        .push(
+            row()
+                .width(Length::Fill)
+                .spacing(5)
+                .push(
+                    pick_list(app.usb_devices.clone(), selected, AppMsg::UsbDevice)
+                        .placeholder(
+                            device
+                                .map(|device| device.to_string())
+                                .unwrap_or_else(|| fl!("usb_device_auto")),
+                        )
+                        .width(Length::Fill),
+                )
+                .push(
+                    widget_icon_button!("refresh24")
+                        .on_press(AppMsg::RefreshUsbDevices)
+                        .class(cosmic::theme::Button::Text)
+                        .width(Length::Shrink),
+                ),
+        )
+        .into()
+}
+
 fn connection_type(app: &AppState) -> Element<'_, AppMsg> {
     let connection_mode = &app.config.data().connection_mode;
 
@@ -258,24 +362,98 @@ fn connect_button(app: &AppState) -> Element<'_, AppMsg> {
 pub fn settings_window(app: &AppState) -> Element<'_, ConfigMsg> {
     let config = app.config.data();
 
+    let can_save_profile = !app.profile_name_input.trim().is_empty()
+        && (app.profile_include_connection || app.profile_include_dsp);
+
     scrollable(
         column()
             .padding(50)
             .spacing(20)
+            .push(
+                settings::section()
+                    .title(fl!("profiles"))
+                    .add(
+                        settings::item::builder(fl!("profile_active")).control(
+                            row()
+                                .spacing(10)
+                                .align_y(Vertical::Center)
+                                .push(
+                                    pick_list(
+                                        config.profile_names(),
+                                        config.active_profile.clone(),
+                                        ConfigMsg::SwitchProfile,
+                                    )
+                                    .placeholder(fl!("profile_none")),
+                                )
+                                .push(button::destructive(fl!("profile_delete")).on_press_maybe(
+                                    config.active_profile.clone().map(ConfigMsg::DeleteProfile),
+                                )),
+                        ),
+                    )
+                    .add(
+                        settings::item::builder(fl!("profile_include_connection")).control(
+                            toggler(app.profile_include_connection)
+                                .on_toggle(ConfigMsg::ProfileIncludeConnection),
+                        ),
+                    )
+                    .add(settings::item::builder(fl!("profile_include_dsp")).control(
+                        toggler(app.profile_include_dsp).on_toggle(ConfigMsg::ProfileIncludeDsp),
+                    ))
+                    .add(
+                        row()
+                            .spacing(10)
+                            .align_y(Vertical::Center)
+                            .push(
+                                text_input(fl!("profile_name"), &app.profile_name_input)
+                                    .on_input(ConfigMsg::ProfileNameInput),
+                            )
+                            .push(button::text(fl!("profile_save")).on_press_maybe(
+                                can_save_profile.then_some(ConfigMsg::SaveProfile),
+                            )),
+                    ),
+            )
             .push(
                 settings::section()
                     .title(fl!("title_audio_format"))
                     .add(
                         row()
                             .align_y(Vertical::Center)
+                            .spacing(10)
                             .push(text(fl!("sample_rate")))
                             .push(horizontal_space())
+                            .push(
+                                text_input("", &app.sample_rate_input)
+                                    .on_input(ConfigMsg::SampleRateTextInput)
+                                    .width(Length::Fixed(100.0)),
+                            )
+                            .push(button::text(fl!("save")).on_press(ConfigMsg::SampleRateSave))
                             .push(pick_list(
                                 SampleRate::VALUES,
                                 Some(&config.sample_rate),
                                 ConfigMsg::SampleRate,
                             )),
                     )
+                    .add(
+                        settings::item::builder(fl!("resampler_quality"))
+                            .description(fl!(
+                                "resampler_latency",
+                                frames = config.resampler_quality.chunk_size()
+                            ))
+                            .control(pick_list(
+                                ResamplerQuality::VALUES,
+                                Some(&config.resampler_quality),
+                                ConfigMsg::ResamplerQuality,
+                            )),
+                    )
+                    .add(
+                        settings::item::builder(fl!("dither"))
+                            .description(fl!("dither_description"))
+                            .control(pick_list(
+                                DitherMode::VALUES,
+                                Some(&config.dither),
+                                ConfigMsg::Dither,
+                            )),
+                    )
                     .add(
                         row()
                             .align_y(Vertical::Center)
@@ -474,22 +652,69 @@ pub fn settings_window(app: &AppState) -> Element<'_, ConfigMsg> {
                     .on_press(ConfigMsg::ResetDenoiseSettings),
             )
             .push(
-                settings::section().title("Audio Effect").add(
-                    row()
-                        .align_y(Vertical::Center)
-                        .push(text("Audio Effect"))
-                        .push(horizontal_space())
-                        .push(pick_list(
-                            AudioEffect::VALUES,
-                            Some(&config.post_effect),
-                            ConfigMsg::PostAudioEffect,
-                        )),
-                ),
+                config
+                    .equalizer_bands
+                    .iter()
+                    .enumerate()
+                    .fold(
+                        settings::section().title(fl!("equalizer")).add(
+                            row()
+                                .align_y(Vertical::Center)
+                                .push(text(fl!("equalizer_enabled")))
+                                .push(horizontal_space())
+                                .push(toggler(config.equalizer).on_toggle(ConfigMsg::Equalizer)),
+                        ),
+                        |section, (index, band)| {
+                            section
+                                .add_maybe(config.equalizer.then(|| equalizer_band(index, *band)))
+                        },
+                    )
+                    .add_maybe(config.equalizer.then(|| {
+                        row()
+                            .push(horizontal_space())
+                            .push(
+                                button::text(fl!("equalizer_add_band")).on_press_maybe(
+                                    (config.equalizer_bands.len() < MAX_EQ_BANDS)
+                                        .then_some(ConfigMsg::EqualizerAddBand),
+                                ),
+                            )
+                            .push(horizontal_space())
+                    })),
+            )
+            .push(
+                config
+                    .post_effects
+                    .iter()
+                    .enumerate()
+                    .fold(
+                        settings::section().title("Audio Effect"),
+                        |section, (index, entry)| {
+                            section.add(post_effect_entry(index, entry, config.post_effects.len()))
+                        },
+                    )
+                    .add_maybe((config.post_effects.len() < MAX_POST_EFFECTS).then(|| {
+                        row()
+                            .align_y(Vertical::Center)
+                            .push(text(fl!("effect_add")))
+                            .push(horizontal_space())
+                            .push(
+                                pick_list(
+                                    AudioEffect::VALUES
+                                        .iter()
+                                        .copied()
+                                        .filter(|effect| *effect != AudioEffect::NoEffect)
+                                        .collect::<Vec<_>>(),
+                                    None::<AudioEffect>,
+                                    ConfigMsg::AddPostEffect,
+                                )
+                                .placeholder(fl!("effect_add_placeholder")),
+                            )
+                    })),
             )
             .push(
                 settings::section()
                     .title(fl!("title_app"))
-                    .add_maybe(if cfg!(target_os = "windows") {
+                    .add_maybe(if cfg!(any(target_os = "windows", target_os = "linux")) {
                         Some(
                             row()
                                 .align_y(Vertical::Center)
@@ -503,6 +728,23 @@ pub fn settings_window(app: &AppState) -> Element<'_, ConfigMsg> {
                     } else {
                         None
                     })
+                    .add_maybe(
+                        if cfg!(target_os = "linux") && !cfg!(ANDROID_MIC_FORMAT = "flatpak") {
+                            Some(
+                                row()
+                                    .align_y(Vertical::Center)
+                                    .push(text(fl!("autostart_method")))
+                                    .push(horizontal_space())
+                                    .push(pick_list(
+                                        AutostartMethod::VALUES,
+                                        Some(config.autostart_method),
+                                        ConfigMsg::AutostartMethod,
+                                    )),
+                            )
+                        } else {
+                            None
+                        },
+                    )
                     .add(
                         row()
                             .align_y(Vertical::Center)
@@ -531,14 +773,467 @@ pub fn settings_window(app: &AppState) -> Element<'_, ConfigMsg> {
                                 ConfigMsg::Theme,
                             )),
                     )
+                    .add(
+                        row()
+                            .align_y(Vertical::Center)
+                            .push(text(fl!("log_level")))
+                            .push(horizontal_space())
+                            .push(pick_list(
+                                LogLevel::VALUES,
+                                Some(config.log_level),
+                                ConfigMsg::LogLevel,
+                            )),
+                    )
                     .add(widget::settings::item::builder(fl!("about")).control(
                         button::text(fl!("about_open")).on_press(ConfigMsg::ToggleAboutWindow),
                     )),
-            ),
+            )
+            .push(NotificationKind::VALUES.iter().fold(
+                settings::section().title(fl!("title_notifications")),
+                |section, kind| {
+                    let kind = *kind;
+                    section.add(
+                        row()
+                            .align_y(Vertical::Center)
+                            .push(text(kind.to_string()))
+                            .push(horizontal_space())
+                            .push(
+                                toggler(config.notification_enabled(kind)).on_toggle(
+                                    move |enabled| ConfigMsg::Notification(kind, enabled),
+                                ),
+                            ),
+                    )
+                },
+            )),
     )
     .into()
 }
 
+fn post_effect_entry<'a>(
+    index: usize,
+    entry: &EffectChainEntry,
+    len: usize,
+) -> Element<'a, ConfigMsg> {
+    column()
+        .spacing(10)
+        .push(
+            row()
+                .align_y(Vertical::Center)
+                .spacing(10)
+                .push(text(format!("{}. {}", index + 1, entry.preset)))
+                .push(horizontal_space())
+                .push(text(fl!("effect_bypass")))
+                .push(
+                    toggler(entry.bypass)
+                        .on_toggle(move |bypass| ConfigMsg::PostEffectBypass(index, bypass)),
+                )
+                .push(
+                    button::text("↑")
+                        .on_press_maybe((index > 0).then_some(ConfigMsg::MovePostEffectUp(index))),
+                )
+                .push(button::text("↓").on_press_maybe(
+                    (index + 1 < len).then_some(ConfigMsg::MovePostEffectDown(index)),
+                ))
+                .push(
+                    button::text(fl!("effect_remove")).on_press(ConfigMsg::RemovePostEffect(index)),
+                ),
+        )
+        .push(effect_params(entry.params, move |params| {
+            ConfigMsg::PostEffectParams(index, params)
+        }))
+        .push_maybe((entry.preset.preset() != Some(entry.params)).then(|| {
+            row()
+                .push(horizontal_space())
+                .push(
+                    button::text(fl!("reset_effect_params"))
+                        .on_press(ConfigMsg::ResetPostEffectParams(index)),
+                )
+                .push(horizontal_space())
+        }))
+        .into()
+}
+
+fn equalizer_band<'a>(index: usize, band: EqBand) -> Element<'a, ConfigMsg> {
+    // the frequency slider works on a log scale, from 20 Hz to 20 kHz
+    const MIN_FREQUENCY_LOG: f32 = 1.30103;
+    const MAX_FREQUENCY_LOG: f32 = 4.30103;
+
+    column()
+        .spacing(10)
+        .push(
+            row()
+                .align_y(Vertical::Center)
+                .spacing(10)
+                .push(text(format!("{} {}", fl!("equalizer_band"), index + 1)))
+                .push(horizontal_space())
+                .push(pick_list(
+                    EqBandKind::VALUES,
+                    Some(band.kind),
+                    move |kind| ConfigMsg::EqualizerBand(index, EqBand { kind, ..band }),
+                ))
+                .push(
+                    button::text(fl!("equalizer_remove_band"))
+                        .on_press(ConfigMsg::EqualizerRemoveBand(index)),
+                ),
+        )
+        .push(
+            row()
+                .align_y(Vertical::Center)
+                .spacing(10)
+                .push(text(format!("{:.0} Hz", band.frequency)).width(Length::Fixed(80.0)))
+                .push(
+                    widget::slider(
+                        MIN_FREQUENCY_LOG..=MAX_FREQUENCY_LOG,
+                        band.frequency.log10(),
+                        move |value| {
+                            ConfigMsg::EqualizerBand(
+                                index,
+                                EqBand {
+                                    frequency: 10.0f32.powf(value),
+                                    ..band
+                                },
+                            )
+                        },
+                    )
+                    .step(0.01),
+                ),
+        )
+        .push_maybe(band.kind.has_gain().then(|| {
+            row()
+                .align_y(Vertical::Center)
+                .spacing(10)
+                .push(text(format!("{:+.1} dB", band.gain)).width(Length::Fixed(80.0)))
+                .push(
+                    widget::slider(-24.0..=24.0, band.gain, move |gain| {
+                        ConfigMsg::EqualizerBand(index, EqBand { gain, ..band })
+                    })
+                    .step(0.5),
+                )
+        }))
+        .push(
+            row()
+                .align_y(Vertical::Center)
+                .spacing(10)
+                .push(text(format!("Q {:.1}", band.q)).width(Length::Fixed(80.0)))
+                .push(
+                    widget::slider(0.1..=10.0, band.q, move |q| {
+                        ConfigMsg::EqualizerBand(index, EqBand { q, ..band })
+                    })
+                    .step(0.1),
+                ),
+        )
+        .into()
+}
+
+fn param_slider<'a, M: Clone + 'a>(
+    label: String,
+    value_text: String,
+    range: RangeInclusive<f32>,
+    value: f32,
+    step: f32,
+    on_change: impl Fn(f32) -> M + 'a,
+) -> Element<'a, M> {
+    row()
+        .align_y(Vertical::Center)
+        .spacing(10)
+        .push(text(label).width(Length::Fixed(130.0)))
+        .push(widget::slider(range, value, on_change).step(step))
+        .push(text(value_text).width(Length::Fixed(70.0)))
+        .into()
+}
+
+fn effect_params<'a, M: Clone + 'a>(
+    params: EffectParams,
+    on_change: impl Fn(EffectParams) -> M + Copy + 'a,
+) -> Element<'a, M> {
+    let sliders = match params {
+        EffectParams::Echo(p) => vec![
+            param_slider(
+                fl!("effect_delay"),
+                format!("{} ms", p.delay_ms),
+                20.0..=1000.0,
+                p.delay_ms as f32,
+                10.0,
+                move |v| {
+                    on_change(EffectParams::Echo(EchoParams {
+                        delay_ms: v as u32,
+                        ..p
+                    }))
+                },
+            ),
+            param_slider(
+                fl!("effect_feedback"),
+                format!("{:.2}", p.decay),
+                0.0..=0.95,
+                p.decay,
+                0.05,
+                move |decay| on_change(EffectParams::Echo(EchoParams { decay, ..p })),
+            ),
+            param_slider(
+                fl!("effect_cutoff"),
+                format!("{:.2}", p.cutoff),
+                0.0..=1.0,
+                p.cutoff,
+                0.05,
+                move |cutoff| on_change(EffectParams::Echo(EchoParams { cutoff, ..p })),
+            ),
+            param_slider(
+                fl!("effect_mix"),
+                format!("{:.0} %", p.mix * 100.0),
+                0.0..=1.0,
+                p.mix,
+                0.05,
+                move |mix| on_change(EffectParams::Echo(EchoParams { mix, ..p })),
+            ),
+        ],
+        EffectParams::Reverb(p) => vec![
+            param_slider(
+                fl!("effect_room_size"),
+                format!("{:.2}", p.room_size),
+                0.0..=1.0,
+                p.room_size,
+                0.05,
+                move |room_size| on_change(EffectParams::Reverb(ReverbParams { room_size, ..p })),
+            ),
+            param_slider(
+                fl!("effect_damping"),
+                format!("{:.2}", p.damping),
+                0.0..=1.0,
+                p.damping,
+                0.05,
+                move |damping| on_change(EffectParams::Reverb(ReverbParams { damping, ..p })),
+            ),
+            param_slider(
+                fl!("effect_mix"),
+                format!("{:.0} %", p.mix * 100.0),
+                0.0..=1.0,
+                p.mix,
+                0.05,
+                move |mix| on_change(EffectParams::Reverb(ReverbParams { mix, ..p })),
+            ),
+        ],
+        EffectParams::PitchShift(p) => vec![
+            param_slider(
+                fl!("effect_pitch"),
+                format!("x{:.2}", p.pitch_ratio),
+                0.5..=2.0,
+                p.pitch_ratio,
+                0.05,
+                move |pitch_ratio| {
+                    on_change(EffectParams::PitchShift(PitchShiftParams {
+                        pitch_ratio,
+                        ..p
+                    }))
+                },
+            ),
+            param_slider(
+                fl!("effect_mix"),
+                format!("{:.0} %", p.mix * 100.0),
+                0.0..=1.0,
+                p.mix,
+                0.05,
+                move |mix| on_change(EffectParams::PitchShift(PitchShiftParams { mix, ..p })),
+            ),
+        ],
+        EffectParams::Flanger(p) => vec![
+            param_slider(
+                fl!("effect_rate"),
+                format!("{:.1} Hz", p.rate_hz),
+                0.1..=10.0,
+                p.rate_hz,
+                0.05,
+                move |rate_hz| on_change(EffectParams::Flanger(FlangerParams { rate_hz, ..p })),
+            ),
+            param_slider(
+                fl!("effect_min_delay"),
+                format!("{:.1} ms", p.min_delay_ms),
+                0.1..=10.0,
+                p.min_delay_ms,
+                0.1,
+                move |min_delay_ms| {
+                    on_change(EffectParams::Flanger(FlangerParams { min_delay_ms, ..p }))
+                },
+            ),
+            param_slider(
+                fl!("effect_depth"),
+                format!("{:.1} ms", p.depth_ms),
+                0.0..=10.0,
+                p.depth_ms,
+                0.1,
+                move |depth_ms| on_change(EffectParams::Flanger(FlangerParams { depth_ms, ..p })),
+            ),
+            param_slider(
+                fl!("effect_feedback"),
+                format!("{:.2}", p.feedback),
+                0.0..=0.9,
+                p.feedback,
+                0.05,
+                move |feedback| on_change(EffectParams::Flanger(FlangerParams { feedback, ..p })),
+            ),
+            param_slider(
+                fl!("effect_mix"),
+                format!("{:.0} %", p.mix * 100.0),
+                0.0..=1.0,
+                p.mix,
+                0.05,
+                move |mix| on_change(EffectParams::Flanger(FlangerParams { mix, ..p })),
+            ),
+        ],
+        EffectParams::Phaser(p) => vec![
+            param_slider(
+                fl!("effect_rate"),
+                format!("{:.1} Hz", p.rate_hz),
+                0.1..=10.0,
+                p.rate_hz,
+                0.05,
+                move |rate_hz| on_change(EffectParams::Phaser(PhaserParams { rate_hz, ..p })),
+            ),
+            param_slider(
+                fl!("effect_min_frequency"),
+                format!("{:.0} Hz", p.f_min),
+                50.0..=1000.0,
+                p.f_min,
+                10.0,
+                move |f_min| on_change(EffectParams::Phaser(PhaserParams { f_min, ..p })),
+            ),
+            param_slider(
+                fl!("effect_max_frequency"),
+                format!("{:.0} Hz", p.f_max),
+                500.0..=5000.0,
+                p.f_max,
+                50.0,
+                move |f_max| on_change(EffectParams::Phaser(PhaserParams { f_max, ..p })),
+            ),
+            param_slider(
+                fl!("effect_feedback"),
+                format!("{:.2}", p.feedback),
+                0.0..=0.9,
+                p.feedback,
+                0.05,
+                move |feedback| on_change(EffectParams::Phaser(PhaserParams { feedback, ..p })),
+            ),
+            param_slider(
+                fl!("effect_mix"),
+                format!("{:.0} %", p.mix * 100.0),
+                0.0..=1.0,
+                p.mix,
+                0.05,
+                move |mix| on_change(EffectParams::Phaser(PhaserParams { mix, ..p })),
+            ),
+        ],
+        EffectParams::WalkieTalkie(p) => vec![
+            param_slider(
+                fl!("effect_center_frequency"),
+                format!("{:.0} Hz", p.center_freq),
+                300.0..=3000.0,
+                p.center_freq,
+                10.0,
+                move |center_freq| {
+                    on_change(EffectParams::WalkieTalkie(WalkieTalkieParams {
+                        center_freq,
+                        ..p
+                    }))
+                },
+            ),
+            param_slider(
+                fl!("effect_q"),
+                format!("{:.1}", p.q),
+                0.5..=5.0,
+                p.q,
+                0.1,
+                move |q| on_change(EffectParams::WalkieTalkie(WalkieTalkieParams { q, ..p })),
+            ),
+            param_slider(
+                fl!("effect_drive"),
+                format!("{:.1}", p.drive),
+                1.0..=10.0,
+                p.drive,
+                0.1,
+                move |drive| {
+                    on_change(EffectParams::WalkieTalkie(WalkieTalkieParams {
+                        drive,
+                        ..p
+                    }))
+                },
+            ),
+            param_slider(
+                fl!("effect_mix"),
+                format!("{:.0} %", p.mix * 100.0),
+                0.0..=1.0,
+                p.mix,
+                0.05,
+                move |mix| on_change(EffectParams::WalkieTalkie(WalkieTalkieParams { mix, ..p })),
+            ),
+        ],
+        EffectParams::Popstar(p) => vec![
+            param_slider(
+                fl!("effect_threshold"),
+                format!("{:.3}", p.rms_threshold),
+                0.0..=0.2,
+                p.rms_threshold,
+                0.005,
+                move |rms_threshold| {
+                    on_change(EffectParams::Popstar(PopstarParams { rms_threshold, ..p }))
+                },
+            ),
+            param_slider(
+                fl!("effect_mix"),
+                format!("{:.0} %", p.mix * 100.0),
+                0.0..=1.0,
+                p.mix,
+                0.05,
+                move |mix| on_change(EffectParams::Popstar(PopstarParams { mix, ..p })),
+            ),
+        ],
+        EffectParams::Vocoder(p) => vec![
+            param_slider(
+                fl!("effect_bands"),
+                format!("{}", p.num_bands),
+                1.0..=24.0,
+                p.num_bands as f32,
+                1.0,
+                move |v| {
+                    on_change(EffectParams::Vocoder(VocoderParams {
+                        num_bands: v as usize,
+                        ..p
+                    }))
+                },
+            ),
+            param_slider(
+                fl!("effect_carrier_frequency"),
+                format!("{:.0} Hz", p.carrier_freq),
+                50.0..=1000.0,
+                p.carrier_freq,
+                5.0,
+                move |carrier_freq| {
+                    on_change(EffectParams::Vocoder(VocoderParams { carrier_freq, ..p }))
+                },
+            ),
+            param_slider(
+                fl!("effect_q"),
+                format!("{:.1}", p.q_factor),
+                1.0..=10.0,
+                p.q_factor,
+                0.1,
+                move |q_factor| on_change(EffectParams::Vocoder(VocoderParams { q_factor, ..p })),
+            ),
+            param_slider(
+                fl!("effect_mix"),
+                format!("{:.0} %", p.mix * 100.0),
+                0.0..=1.0,
+                p.mix,
+                0.05,
+                move |mix| on_change(EffectParams::Vocoder(VocoderParams { mix, ..p })),
+            ),
+        ],
+    };
+
+    sliders
+        .into_iter()
+        .fold(column().spacing(10), |column, slider| column.push(slider))
+        .into()
+}
+
 static ABOUT: LazyLock<About> = LazyLock::new(|| {
     About::default()
         .name(APP)